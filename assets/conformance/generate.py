@@ -0,0 +1,133 @@
+"""Generates the synthetic conformance images of this directory.
+
+They cover every color type and bit depth, with and without Adam7
+interlacing (`-interlaced`), odd sizes (`-1x1` to `-35x35`) and image data
+split in many IDAT chunks (`-idat1`, `-idat64`), named after the color type
+and the bit depth (`gray1`, `rgba16`...). Every scanline uses the next of the
+five filters. The real PngSuite images are in assets/pngsuite.
+
+Sample values come from `sample` below, which the tests of src/image.rs
+compute again to check the decoded pixels. The tests also compare them with
+the pixels decoded by the png crate, which was not written from this
+generator.
+
+    python3 assets/conformance/generate.py
+"""
+
+import os
+import struct
+import zlib
+
+ADAM7 = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4),
+         (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+CHANNELS = {0: 1, 2: 3, 3: 1, 4: 2, 6: 4}
+COLORS = {0: "gray", 2: "rgb", 3: "palette", 4: "graya", 6: "rgba"}
+
+
+def sample(x, y, channel, depth, colors):
+    value = x * 7 + y * 13 + channel * 29 + x * y
+    return value % (colors if colors else 1 << depth)
+
+
+def palette_size(depth):
+    return min(1 << depth, 200)
+
+
+def chunk(chunk_type, data):
+    body = chunk_type + data
+    return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+
+def pack_row(xs, y, color_type, depth):
+    colors = palette_size(depth) if color_type == 3 else 0
+    samples = [sample(x, y, c, depth, colors)
+               for x in xs for c in range(CHANNELS[color_type])]
+    if depth == 16:
+        return b"".join(struct.pack(">H", s) for s in samples)
+    if depth == 8:
+        return bytes(samples)
+    row, bits, current = bytearray(), 0, 0
+    for s in samples:
+        current = (current << depth) | s
+        bits += depth
+        if bits == 8:
+            row.append(current)
+            bits, current = 0, 0
+    if bits:
+        row.append(current << (8 - bits))
+    return bytes(row)
+
+
+def paeth(a, b, c):
+    p = a + b - c
+    pa, pb, pc = abs(p - a), abs(p - b), abs(p - c)
+    if pa <= pb and pa <= pc:
+        return a
+    return b if pb <= pc else c
+
+
+def filter_row(kind, row, previous, bpp):
+    out = bytearray([kind])
+    for i, byte in enumerate(row):
+        a = row[i - bpp] if i >= bpp else 0
+        b = previous[i]
+        c = previous[i - bpp] if i >= bpp else 0
+        predictor = [0, a, b, (a + b) // 2, paeth(a, b, c)][kind]
+        out.append((byte - predictor) % 256)
+    return bytes(out)
+
+
+def image_data(width, height, color_type, depth, interlace):
+    bpp = max(1, CHANNELS[color_type] * depth // 8)
+    passes = ADAM7 if interlace else [(0, 0, 1, 1)]
+    data = bytearray()
+    for x0, y0, dx, dy in passes:
+        xs = range(x0, width, dx)
+        ys = range(y0, height, dy)
+        if not xs or not ys:
+            continue
+        previous = None
+        for line, y in enumerate(ys):
+            row = pack_row(xs, y, color_type, depth)
+            previous = previous or bytes(len(row))
+            data += filter_row(line % 5, row, previous, bpp)
+            previous = row
+    return zlib.compress(bytes(data), 9)
+
+
+def write(name, width, height, color_type, depth, interlace, idat_size=None):
+    ihdr = struct.pack(">IIBBBBB", width, height, depth, color_type, 0, 0, interlace)
+    chunks = [chunk(b"IHDR", ihdr)]
+    if color_type == 3:
+        palette = bytearray()
+        for i in range(palette_size(depth)):
+            palette += bytes([i * 37 % 256, i * 59 % 256, i * 91 % 256])
+        chunks.append(chunk(b"PLTE", bytes(palette)))
+    data = image_data(width, height, color_type, depth, interlace)
+    size = idat_size or len(data)
+    for start in range(0, len(data), size):
+        chunks.append(chunk(b"IDAT", data[start:start + size]))
+    chunks.append(chunk(b"IEND", b""))
+
+    directory = os.path.dirname(os.path.abspath(__file__))
+    with open(os.path.join(directory, name + ".png"), "wb") as file:
+        file.write(b"\x89PNG\r\n\x1a\n" + b"".join(chunks))
+
+
+def main():
+    for color_type, depths in [(0, [1, 2, 4, 8, 16]), (2, [8, 16]), (3, [1, 2, 4, 8]),
+                               (4, [8, 16]), (6, [8, 16])]:
+        for depth in depths:
+            name = "%s%d" % (COLORS[color_type], depth)
+            write(name, 32, 32, color_type, depth, 0)
+            write(name + "-interlaced", 32, 32, color_type, depth, 1)
+    for size in [1, 2, 3, 4, 5, 6, 7, 8, 9, 33, 35]:
+        name = "palette4-%dx%d" % (size, size)
+        write(name, size, size, 3, 4, 0)
+        write(name + "-interlaced", size, size, 3, 4, 1)
+    write("rgb16-idat1", 32, 32, 2, 16, 0, idat_size=1)
+    write("gray16-interlaced-idat64", 32, 32, 0, 16, 1, idat_size=64)
+
+
+if __name__ == "__main__":
+    main()
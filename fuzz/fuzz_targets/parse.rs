@@ -0,0 +1,29 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::{Chunk, ChunkReader, ChunkType, Png};
+use std::str::FromStr;
+
+// every parsing entry point must return an error on malformed input, never panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(png) = Png::try_from(data) {
+        let _ = png.to_string();
+        let _ = png.as_bytes();
+    }
+
+    if let Ok(reader) = ChunkReader::new(data) {
+        for chunk in reader.flatten() {
+            let _ = chunk.to_string();
+        }
+    }
+
+    if let Ok(chunk) = Chunk::try_from(data) {
+        let _ = chunk.to_string();
+    }
+
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(chunk_type) = ChunkType::from_str(s) {
+            let _ = chunk_type.to_string();
+        }
+    }
+});
@@ -0,0 +1,764 @@
+//! Looks for data hidden in a png file: in chunks of its own, after `IEND`,
+//! after the zlib stream of the image data or in the low bits of the pixels.
+//!
+//! Every sign found is an [`Indicator`] adding its weight to a suspicion
+//! score. The score sums up evidence, it is not a probability: a private
+//! chunk written by an editor scores like a hidden message.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+
+use crate::chunk::{Chunk, ParseMode};
+use crate::critical::ColorType;
+use crate::image::Image;
+use crate::inspect::{inspect, ChunkRecord};
+use crate::png::Png;
+use crate::validate::{Severity, Validator};
+use crate::{PngError, Result};
+
+/// Where an [`Indicator`] suggests data is hidden.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IndicatorKind {
+    PrivateChunk,
+    /// A public chunk type the specification does not define.
+    UnknownChunk,
+    AfterIend,
+    /// Chunks out of the order the specification requires, or bytes holding
+    /// no chunk.
+    Ordering,
+    /// An ancillary chunk whose data looks compressed or encrypted.
+    HighEntropy,
+    /// Bytes after the end of the zlib stream of the `IDAT` chunks.
+    IdatTrailingData,
+    /// The chi-square attack found pairs of values with equal counts.
+    ChiSquare,
+    /// RS analysis estimates that low bits of the pixels were changed.
+    RsAnalysis,
+    /// The decoded pixels exceed the size limit, their low bits were not
+    /// analyzed.
+    ImageTooLarge,
+}
+
+impl IndicatorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndicatorKind::PrivateChunk => "private-chunk",
+            IndicatorKind::UnknownChunk => "unknown-chunk",
+            IndicatorKind::AfterIend => "after-iend",
+            IndicatorKind::Ordering => "ordering",
+            IndicatorKind::HighEntropy => "high-entropy",
+            IndicatorKind::IdatTrailingData => "idat-trailing-data",
+            IndicatorKind::ChiSquare => "chi-square",
+            IndicatorKind::RsAnalysis => "rs-analysis",
+            IndicatorKind::ImageTooLarge => "image-too-large",
+        }
+    }
+}
+
+/// A sign of hidden data, with what was found.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Indicator {
+    pub kind: IndicatorKind,
+    /// Points added to the score.
+    pub weight: u32,
+    /// Offset in the file of the chunk or data concerned.
+    pub offset: Option<u64>,
+    pub evidence: String,
+}
+
+impl Display for Indicator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "[+{}] {}", self.weight, self.kind.name())?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        write!(f, ": {}", self.evidence)
+    }
+}
+
+/// Statistics of the low bits of the color samples.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LsbStatistics {
+    /// p-value of the chi-square attack over every sample: close to 1 when
+    /// the low bits hold random data.
+    pub chi_square: Option<f64>,
+    /// Longest share of the samples, from the first one and by steps of 1%,
+    /// whose p-value is high: a payload written row by row fills them.
+    pub chi_square_prefix: f64,
+    /// Share of samples carrying a payload bit estimated by RS analysis,
+    /// from 0 to 1.
+    pub rs_estimate: Option<f64>,
+}
+
+/// What [`analyze`] found.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Analysis {
+    /// The sum of the weights of the indicators, at most 100.
+    pub score: u32,
+    pub indicators: Vec<Indicator>,
+    /// `None` when the pixels can't be decoded, exceed the size limit, are
+    /// palette indexes or have less than 8 bits.
+    pub lsb: Option<LsbStatistics>,
+}
+
+impl Analysis {
+    pub const MAX_SCORE: u32 = 100;
+}
+
+const PRIVATE_CHUNK_WEIGHT: u32 = 20;
+const UNKNOWN_CHUNK_WEIGHT: u32 = 10;
+const AFTER_IEND_WEIGHT: u32 = 40;
+const ORDERING_WEIGHT: u32 = 10;
+const HIGH_ENTROPY_WEIGHT: u32 = 20;
+const IDAT_TRAILING_DATA_WEIGHT: u32 = 40;
+const CHI_SQUARE_WEIGHT: u32 = 30;
+/// Pixels too large to analyze may hide anything in their low bits.
+const IMAGE_TOO_LARGE_WEIGHT: u32 = 10;
+/// Weight of RS analysis for a payload in every sample, less for smaller
+/// ones.
+const RS_WEIGHT: f64 = 40.0;
+
+/// Chunks shorter than this are not checked for entropy, too few bytes to
+/// tell.
+const MIN_ENTROPY_LENGTH: usize = 128;
+/// Ancillary chunks compressed by the specification.
+const COMPRESSED_CHUNKS: [&str; 4] = ["zTXt", "iTXt", "iCCP", "fdAT"];
+/// p-value of the chi-square attack above which the low bits look random.
+const CHI_SQUARE_THRESHOLD: f64 = 0.9;
+/// RS estimates below this are common in untouched images.
+const RS_THRESHOLD: f64 = 0.05;
+
+/// Scans the bytes of a png file for hidden data. Fails only if they don't
+/// start with the png signature: damage is evidence too.
+///
+/// The pixels are decoded only up to `max_image_size` bytes, see
+/// [`Png::image_with_limit`]. Larger ones are reported by an
+/// [`IndicatorKind::ImageTooLarge`] indicator.
+pub fn analyze(bytes: &[u8], max_image_size: u64) -> Result<Analysis> {
+    let inspection = inspect(bytes, ParseMode::Resync)?;
+    let records = &inspection.chunks;
+    let iend = records
+        .iter()
+        .position(|record| record.chunk.chunk_type().to_string() == "IEND");
+    let image_records = &records[..iend.map_or(records.len(), |iend| iend + 1)];
+
+    let end = iend.map(|iend| {
+        records[iend].offset
+            + records[iend].chunk.length() as u64
+            + Chunk::CHUNK_METADATA_LENGTH as u64
+    });
+
+    let mut indicators = Vec::new();
+    for record in image_records {
+        chunk_indicators(record, &mut indicators);
+    }
+    if let (Some(iend), Some(end)) = (iend, end) {
+        if (end as usize) < bytes.len() {
+            indicators.push(Indicator {
+                kind: IndicatorKind::AfterIend,
+                weight: AFTER_IEND_WEIGHT,
+                offset: Some(end),
+                evidence: format!(
+                    "{} bytes after IEND, {} of them in chunks",
+                    bytes.len() - end as usize,
+                    records[iend + 1..]
+                        .iter()
+                        .map(|record| record.as_bytes().len())
+                        .sum::<usize>()
+                ),
+            });
+        }
+    }
+    ordering_indicators(image_records, &inspection.diagnostics, end, &mut indicators);
+
+    let png = Png::from_chunks(
+        image_records
+            .iter()
+            .map(|record| Chunk::new(*record.chunk.chunk_type(), record.chunk.data().to_vec()))
+            .collect(),
+    );
+    if let Some(indicator) = idat_trailing_data(&png, image_records) {
+        indicators.push(indicator);
+    }
+    let lsb = match png.image_with_limit(max_image_size) {
+        Ok(image) => lsb_statistics(&image),
+        Err(PngError::ImageTooLarge { limit }) => {
+            indicators.push(Indicator {
+                kind: IndicatorKind::ImageTooLarge,
+                weight: IMAGE_TOO_LARGE_WEIGHT,
+                offset: None,
+                evidence: format!(
+                    "pixels too large to analyze, over the limit of {} bytes",
+                    limit
+                ),
+            });
+            None
+        }
+        Err(_) => None,
+    };
+    if let Some(lsb) = lsb {
+        lsb_indicators(&lsb, &mut indicators);
+    }
+
+    let score = indicators
+        .iter()
+        .map(|indicator| indicator.weight)
+        .sum::<u32>()
+        .min(Analysis::MAX_SCORE);
+    Ok(Analysis {
+        score,
+        indicators,
+        lsb,
+    })
+}
+
+fn chunk_indicators(record: &ChunkRecord, indicators: &mut Vec<Indicator>) {
+    let chunk_type = record.chunk.chunk_type();
+    let name = chunk_type.to_string();
+    let length = record.chunk.length();
+    if !chunk_type.is_public() {
+        indicators.push(Indicator {
+            kind: IndicatorKind::PrivateChunk,
+            weight: PRIVATE_CHUNK_WEIGHT,
+            offset: Some(record.offset),
+            evidence: format!("private chunk {}, {} bytes", name, length),
+        });
+    } else if !chunk_type.is_known() {
+        indicators.push(Indicator {
+            kind: IndicatorKind::UnknownChunk,
+            weight: UNKNOWN_CHUNK_WEIGHT,
+            offset: Some(record.offset),
+            evidence: format!("unknown chunk {}, {} bytes", name, length),
+        });
+    }
+
+    let data = record.chunk.data();
+    if chunk_type.is_critical()
+        || COMPRESSED_CHUNKS.contains(&name.as_str())
+        || data.len() < MIN_ENTROPY_LENGTH
+    {
+        return;
+    }
+    // random bytes come close to the largest entropy the length allows,
+    // text and tables stay well below
+    let entropy = entropy(data);
+    let max_entropy = (data.len().min(256) as f64).log2();
+    if entropy >= 0.85 * max_entropy {
+        indicators.push(Indicator {
+            kind: IndicatorKind::HighEntropy,
+            weight: HIGH_ENTROPY_WEIGHT,
+            offset: Some(record.offset),
+            evidence: format!(
+                "{} holds {} bytes of entropy {:.2} bits per byte, like compressed or encrypted data",
+                name, length, entropy
+            ),
+        });
+    }
+}
+
+/// The ordering errors of the validator, and the bytes holding no chunk
+/// before `end`, where `IEND` ends.
+fn ordering_indicators(
+    records: &[ChunkRecord],
+    diagnostics: &[PngError],
+    end: Option<u64>,
+    indicators: &mut Vec<Indicator>,
+) {
+    let mut validator = Validator::new();
+    for record in records {
+        validator.push(&record.chunk);
+    }
+    let findings = validator.finish();
+    for finding in findings {
+        let (Some(index), Some(chunk_type)) = (finding.chunk_index, finding.chunk_type) else {
+            continue;
+        };
+        if finding.severity != Severity::Error {
+            continue;
+        }
+        indicators.push(Indicator {
+            kind: IndicatorKind::Ordering,
+            weight: ORDERING_WEIGHT,
+            offset: records.get(index).map(|record| record.offset),
+            evidence: format!("chunk #{} {}: {}", index, chunk_type, finding.message),
+        });
+    }
+    for diagnostic in diagnostics {
+        let offset = match diagnostic {
+            PngError::Chunk { offset, .. } => Some(*offset),
+            _ => None,
+        };
+        // bytes after IEND are counted once, as such
+        if offset.zip(end).is_some_and(|(offset, end)| offset >= end) {
+            continue;
+        }
+        indicators.push(Indicator {
+            kind: IndicatorKind::Ordering,
+            weight: ORDERING_WEIGHT,
+            offset,
+            evidence: diagnostic.to_string(),
+        });
+    }
+}
+
+/// Bytes left in the `IDAT` chunks once their zlib stream ended.
+fn idat_trailing_data(png: &Png, records: &[ChunkRecord]) -> Option<Indicator> {
+    let compressed: Vec<u8> = png
+        .chunks_by_type("IDAT")
+        .flat_map(|chunk| chunk.data())
+        .copied()
+        .collect();
+    if compressed.is_empty() {
+        return None;
+    }
+    let mut decoder = ZlibDecoder::new(&compressed[..]);
+    io::copy(&mut decoder.by_ref(), &mut io::sink()).ok()?;
+    let end = decoder.total_in() as usize;
+    if end >= compressed.len() {
+        return None;
+    }
+    let first = records
+        .iter()
+        .find(|record| record.chunk.chunk_type().to_string() == "IDAT")?;
+    Some(Indicator {
+        kind: IndicatorKind::IdatTrailingData,
+        weight: IDAT_TRAILING_DATA_WEIGHT,
+        offset: Some(first.offset),
+        evidence: format!(
+            "{} bytes after the end of the zlib stream of the image data",
+            compressed.len() - end
+        ),
+    })
+}
+
+fn lsb_indicators(lsb: &LsbStatistics, indicators: &mut Vec<Indicator>) {
+    if lsb.chi_square_prefix > 0.0 {
+        indicators.push(Indicator {
+            kind: IndicatorKind::ChiSquare,
+            weight: CHI_SQUARE_WEIGHT,
+            offset: None,
+            evidence: format!(
+                "pairs of sample values have equal counts in the first {:.0}% of the samples, as when low bits hold random data",
+                lsb.chi_square_prefix * 100.0
+            ),
+        });
+    }
+    if let Some(estimate) = lsb.rs_estimate.filter(|&estimate| estimate >= RS_THRESHOLD) {
+        indicators.push(Indicator {
+            kind: IndicatorKind::RsAnalysis,
+            weight: (estimate * RS_WEIGHT).round().clamp(1.0, RS_WEIGHT) as u32,
+            offset: None,
+            evidence: format!(
+                "about {:.0}% of the samples carry a changed low bit",
+                estimate * 100.0
+            ),
+        });
+    }
+}
+
+/// Shannon entropy of `data` in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0_usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / data.len() as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Runs the chi-square attack and RS analysis on the color samples, not on
+/// alpha which is often constant.
+fn lsb_statistics(image: &Image) -> Option<LsbStatistics> {
+    let colors = match image.color_type() {
+        ColorType::Indexed => return None,
+        ColorType::Grayscale | ColorType::GrayscaleAlpha => 1,
+        ColorType::Rgb | ColorType::Rgba => 3,
+    };
+    if image.bit_depth() < 8 {
+        return None;
+    }
+    let channels = image.channels();
+    let samples: Vec<u16> = image
+        .samples()
+        .chunks(channels)
+        .flat_map(|pixel| pixel[..colors].iter().copied())
+        .collect();
+
+    let values = 1 << image.bit_depth();
+    let planes: Vec<Vec<i32>> = (0..colors)
+        .map(|channel| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(colors)
+                .map(|&sample| sample as i32)
+                .collect()
+        })
+        .collect();
+    Some(LsbStatistics {
+        chi_square: chi_square(&histogram(&samples, values)),
+        chi_square_prefix: chi_square_prefix(&samples, values),
+        rs_estimate: rs_estimate(&planes),
+    })
+}
+
+fn histogram(samples: &[u16], values: usize) -> Vec<u64> {
+    let mut histogram = vec![0; values];
+    for &sample in samples {
+        histogram[sample as usize] += 1;
+    }
+    histogram
+}
+
+/// The longest share of the samples from the start whose chi-square
+/// p-value is above [`CHI_SQUARE_THRESHOLD`], 0 if none.
+fn chi_square_prefix(samples: &[u16], values: usize) -> f64 {
+    let mut histogram = vec![0; values];
+    let mut prefix = 0.0;
+    let mut counted = 0;
+    for percent in 1..=100 {
+        let end = samples.len() * percent / 100;
+        for &sample in &samples[counted..end] {
+            histogram[sample as usize] += 1;
+        }
+        counted = end;
+        if chi_square(&histogram).is_some_and(|p| p >= CHI_SQUARE_THRESHOLD) {
+            prefix = percent as f64 / 100.0;
+        }
+    }
+    prefix
+}
+
+/// p-value of the chi-square attack of Westfeld and Pfitzmann: embedding
+/// random bits in the low bits evens out the counts of `2k` and `2k + 1`.
+fn chi_square(histogram: &[u64]) -> Option<f64> {
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        // too few samples for the approximation to hold
+        if expected < 5.0 {
+            continue;
+        }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        pairs += 1;
+    }
+    if pairs < 2 {
+        return None;
+    }
+    let freedom = (pairs - 1) as f64;
+    Some(1.0 - regularized_gamma(freedom / 2.0, statistic / 2.0))
+}
+
+/// Share of changed low bits estimated by the RS analysis of Fridrich, Goljan
+/// and Du, over groups of 4 consecutive samples of each plane.
+fn rs_estimate(planes: &[Vec<i32>]) -> Option<f64> {
+    // regular and singular groups, with the mask and the negative mask, in
+    // the image and with every low bit flipped
+    let (d0, dn0) = rs_differences(planes, false)?;
+    let (d1, dn1) = rs_differences(planes, true)?;
+
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let x = if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let roots = [
+            (-b + discriminant.sqrt()) / (2.0 * a),
+            (-b - discriminant.sqrt()) / (2.0 * a),
+        ];
+        if roots[0].abs() < roots[1].abs() {
+            roots[0]
+        } else {
+            roots[1]
+        }
+    };
+    let estimate = x / (x - 0.5);
+    estimate.is_finite().then(|| estimate.clamp(0.0, 1.0))
+}
+
+/// `R - S` with the mask `[0, 1, 1, 0]` and with its negative.
+fn rs_differences(planes: &[Vec<i32>], flipped: bool) -> Option<(f64, f64)> {
+    const MASK: [bool; 4] = [false, true, true, false];
+    let flip = |value: i32| value ^ 1;
+    let flip_negative = |value: i32| ((value + 1) ^ 1) - 1;
+    let smoothness =
+        |group: &[i32; 4]| -> i32 { group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum() };
+
+    let (mut groups, mut difference, mut negative_difference) = (0, 0_i64, 0_i64);
+    for plane in planes {
+        for values in plane.chunks_exact(4) {
+            let mut group = [values[0], values[1], values[2], values[3]];
+            if flipped {
+                group = group.map(flip);
+            }
+            let before = smoothness(&group);
+            let mut masked = group;
+            let mut negative = group;
+            for i in 0..4 {
+                if MASK[i] {
+                    masked[i] = flip(masked[i]);
+                    negative[i] = flip_negative(negative[i]);
+                }
+            }
+            difference += (smoothness(&masked) - before).signum() as i64;
+            negative_difference += (smoothness(&negative) - before).signum() as i64;
+            groups += 1;
+        }
+    }
+    if groups == 0 {
+        return None;
+    }
+    Some((
+        difference as f64 / groups as f64,
+        negative_difference as f64 / groups as f64,
+    ))
+}
+
+/// The regularized lower incomplete gamma function `P(a, x)`, the
+/// cumulative distribution of chi-square with `2a` degrees of freedom at
+/// `2x`.
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // series
+        let (mut term, mut sum) = (1.0 / a, 1.0 / a);
+        for n in 1..1000 {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * 1e-14 {
+                break;
+            }
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        // continued fraction of Q(a, x), by the modified Lentz method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-14 {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+/// Logarithm of the gamma function, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // reflection
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, coefficient)| {
+            sum + coefficient / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::lsb::{self, LsbOptions};
+    use crate::ChunkPosition;
+    use std::str::FromStr;
+    use zeroize::Zeroizing;
+
+    /// res.png without the chunk after IEND.
+    fn testing_png() -> Png {
+        let bytes = std::fs::read("assets/res.png").unwrap();
+        let mut png = Png::try_from(bytes.as_ref()).unwrap();
+        png.remove_chunk("test").unwrap();
+        png
+    }
+
+    /// Bytes that look random, from a xorshift generator.
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    fn kinds(analysis: &Analysis) -> Vec<IndicatorKind> {
+        analysis
+            .indicators
+            .iter()
+            .map(|indicator| indicator.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let analysis = analyze(&testing_png().as_bytes(), Image::DEFAULT_LIMIT).unwrap();
+        assert_eq!(analysis.score, 0);
+        assert!(analysis.indicators.is_empty());
+        let lsb = analysis.lsb.unwrap();
+        assert!(lsb.rs_estimate.unwrap() < RS_THRESHOLD);
+        assert_eq!(lsb.chi_square_prefix, 0.0);
+    }
+
+    #[test]
+    fn test_chunk_indicators() {
+        let mut png = testing_png();
+        let chunk = |chunk_type: &str, data: Vec<u8>| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+        };
+        png.insert_chunk(chunk("ruSt", noise(512)), ChunkPosition::BeforeIend)
+            .unwrap();
+        png.insert_chunk(
+            chunk(
+                "tEXt",
+                [&b"Comment\0"[..], &b"a plain comment ".repeat(16)].concat(),
+            ),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk("gAMA", vec![0, 0, 177, 143]),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"appended");
+
+        let analysis = analyze(&bytes, Image::DEFAULT_LIMIT).unwrap();
+        assert_eq!(
+            kinds(&analysis),
+            [
+                IndicatorKind::PrivateChunk,
+                IndicatorKind::HighEntropy,
+                IndicatorKind::AfterIend,
+                IndicatorKind::Ordering,
+            ]
+        );
+        assert_eq!(analysis.score, 90);
+        assert!(analysis.indicators[3].evidence.contains("gAMA"));
+    }
+
+    #[test]
+    fn test_idat_trailing_data() {
+        let mut png = testing_png();
+        let idat: Vec<Chunk> = png
+            .chunks_by_type("IDAT")
+            .map(|chunk| {
+                let mut data = chunk.data().to_vec();
+                data.extend_from_slice(b"hidden");
+                Chunk::new(*chunk.chunk_type(), data)
+            })
+            .collect();
+        png.remove_where(|_, chunk| chunk.chunk_type().to_string() == "IDAT");
+        png.insert_chunks(idat, ChunkPosition::BeforeIend).unwrap();
+
+        let analysis = analyze(&png.as_bytes(), Image::DEFAULT_LIMIT).unwrap();
+        assert_eq!(kinds(&analysis), [IndicatorKind::IdatTrailingData]);
+        assert!(analysis.indicators[0].evidence.starts_with("6 bytes"));
+        // the image still decodes
+        assert!(analysis.lsb.is_some());
+    }
+
+    #[test]
+    fn test_lsb_payloads() {
+        let mut png = testing_png();
+        let original = png.image().unwrap();
+        let capacity = lsb::capacity(&original, &LsbOptions::default()).unwrap();
+
+        // written row by row, the payload fills the first samples
+        let mut image = original.clone();
+        lsb::embed(&mut image, &noise(capacity / 20), &LsbOptions::default()).unwrap();
+        png.replace_image(&image, &Default::default()).unwrap();
+        let analysis = analyze(&png.as_bytes(), Image::DEFAULT_LIMIT).unwrap();
+        assert_eq!(kinds(&analysis), [IndicatorKind::ChiSquare]);
+        assert_eq!(analysis.lsb.unwrap().chi_square_prefix, 0.05);
+
+        // spread over the image by a key, half of the samples carry a bit
+        let mut image = original;
+        let options = LsbOptions {
+            key: Some(Zeroizing::new(b"secret".to_vec())),
+            ..Default::default()
+        };
+        lsb::embed(&mut image, &noise(capacity / 2), &options).unwrap();
+        png.replace_image(&image, &Default::default()).unwrap();
+        let analysis = analyze(&png.as_bytes(), Image::DEFAULT_LIMIT).unwrap();
+        assert_eq!(kinds(&analysis), [IndicatorKind::RsAnalysis]);
+        let estimate = analysis.lsb.unwrap().rs_estimate.unwrap();
+        assert!((0.4..0.6).contains(&estimate), "estimate {}", estimate);
+        assert_eq!(analysis.score, 20);
+    }
+
+    #[test]
+    fn test_image_too_large() {
+        let analysis = analyze(&testing_png().as_bytes(), 16).unwrap();
+        assert_eq!(kinds(&analysis), [IndicatorKind::ImageTooLarge]);
+        assert!(analysis.indicators[0]
+            .evidence
+            .starts_with("pixels too large to analyze"));
+        assert_eq!(analysis.score, IMAGE_TOO_LARGE_WEIGHT);
+        assert_eq!(analysis.lsb, None);
+    }
+
+    #[test]
+    fn test_chi_square_distribution() {
+        // P(chi-square with 10 degrees of freedom <= 10)
+        assert!((regularized_gamma(5.0, 5.0) - 0.559_506_714_9).abs() < 1e-8);
+        assert!((ln_gamma(10.0) - 362_880_f64.ln()).abs() < 1e-10);
+    }
+}
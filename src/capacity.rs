@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+
+use crate::compression::Compression;
+use crate::critical::ColorType;
+use crate::lsb::{self, Channels, LsbOptions};
+use crate::png::Png;
+use crate::validate::Validator;
+use crate::{PngError, Result};
+
+/// Passphrase encryption: magic, version, kdf params, salt and nonce, then
+/// the Poly1305 tag.
+const PASSPHRASE_OVERHEAD: usize = 4 + 1 + 12 + 16 + 24 + 16;
+/// age: version line, mac line and payload nonce.
+const AGE_HEADER: usize = 22 + 48 + 16;
+/// age: `-> X25519` line with the ephemeral share, then the wrapped key.
+const AGE_RECIPIENT: usize = 54 + 44;
+/// age: the random stanza added to every header, at its longest.
+const AGE_GREASE: usize = 54 + 135;
+/// age: plaintext bytes per tag.
+const AGE_CHUNK: usize = 64 * 1024;
+const AGE_TAG: usize = 16;
+
+/// How a payload is encrypted, for the bytes it adds.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EncryptionKind {
+    Passphrase,
+    /// To this many age recipients.
+    Recipients(usize),
+}
+
+/// What happens to a payload before it is embedded, each step adding a
+/// header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Processing {
+    /// The room left is for the compressed payload, whose size can't be
+    /// known beforehand.
+    pub compression: Option<Compression>,
+    pub encryption: Option<EncryptionKind>,
+}
+
+impl Processing {
+    /// Bytes added to a payload of `length` bytes, at most.
+    pub fn overhead(&self, length: usize) -> usize {
+        let compression = match self.compression {
+            Some(_) => Compression::HEADER_LENGTH,
+            None => 0,
+        };
+        let encryption = match self.encryption {
+            Some(EncryptionKind::Passphrase) => PASSPHRASE_OVERHEAD,
+            Some(EncryptionKind::Recipients(recipients)) => {
+                let chunks = (length + compression).div_ceil(AGE_CHUNK).max(1);
+                AGE_HEADER + recipients * AGE_RECIPIENT + AGE_GREASE + chunks * AGE_TAG
+            }
+            None => 0,
+        };
+        compression + encryption
+    }
+
+    /// The largest payload whose processed length fits in `room` bytes.
+    pub fn payload_capacity(&self, room: usize) -> usize {
+        // the overhead grows by a tag every 64 KiB, so the guess from the
+        // overhead of the whole room is at most one tag short
+        let guess = room.saturating_sub(self.overhead(room));
+        (guess..=guess.saturating_add(AGE_TAG).min(room))
+            .rev()
+            .find(|&length| length + self.overhead(length) <= room)
+            .unwrap_or(0)
+    }
+}
+
+/// How much an embedding method can hold.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Room {
+    Bytes(usize),
+    /// Only limited by the file system.
+    Unlimited,
+    /// The method does not apply to this image, and why.
+    Unavailable(&'static str),
+}
+
+impl Display for Room {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Room::Bytes(bytes) => write!(f, "{} bytes", bytes),
+            Room::Unlimited => write!(f, "unlimited"),
+            Room::Unavailable(reason) => write!(f, "unavailable ({})", reason),
+        }
+    }
+}
+
+/// Payload bytes a png file can hide with each embedding method, after the
+/// headers of the method and of [`Processing`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Capacity {
+    /// In a single chunk. Larger payloads are split over more chunks, so
+    /// there is no limit.
+    pub chunk: Room,
+    /// In the low bits of the pixels, for each of [`Capacity::LSB_BITS`]
+    /// bits per channel.
+    pub lsb: Vec<(u8, Room)>,
+    /// In the palette entries no pixel uses, up to the number of colors the
+    /// bit depth allows, 3 bytes each.
+    pub palette: Room,
+    /// Appended after `IEND`, where decoders stop reading.
+    pub after_iend: Room,
+}
+
+impl Capacity {
+    pub const LSB_BITS: [u8; 3] = [1, 2, 4];
+}
+
+/// Measures how much `png` can hide, using `channels` for the low bits of
+/// the pixels. Decodes the image data, up to `max_image_size` bytes of
+/// samples.
+pub fn capacity(
+    png: &Png,
+    channels: Channels,
+    processing: &Processing,
+    max_image_size: u64,
+) -> Result<Capacity> {
+    let image = png.image_with_limit(max_image_size)?;
+    let room = |bytes: usize| Room::Bytes(processing.payload_capacity(bytes));
+
+    let mut lsb = Vec::new();
+    for bits in Capacity::LSB_BITS {
+        let options = LsbOptions {
+            bits,
+            channels,
+            key: None,
+        };
+        let capacity = match lsb::capacity(&image, &options) {
+            Ok(bytes) => room(bytes),
+            Err(PngError::LsbUnsupported(reason)) => Room::Unavailable(reason),
+            Err(error) => return Err(error),
+        };
+        lsb.push((bits, capacity));
+    }
+
+    let palette = if image.color_type() == ColorType::Indexed {
+        let used: HashSet<u16> = image.samples().iter().copied().collect();
+        let colors = 1_usize << image.bit_depth();
+        room(colors.saturating_sub(used.len()) * 3)
+    } else {
+        Room::Unavailable("not an indexed image")
+    };
+
+    Ok(Capacity {
+        chunk: room(Validator::MAX_CHUNK_LENGTH as usize),
+        lsb,
+        palette,
+        after_iend: Room::Unlimited,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Image;
+
+    fn load(path: &str) -> Png {
+        Png::try_from(std::fs::read(path).unwrap().as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = load("assets/res.png");
+        let image = png.image().unwrap();
+        let pixels = (image.width() * image.height()) as usize;
+
+        let report = capacity(
+            &png,
+            Channels::COLOR,
+            &Processing::default(),
+            Image::DEFAULT_LIMIT,
+        )
+        .unwrap();
+        assert_eq!(report.chunk, Room::Bytes(i32::MAX as usize));
+        assert_eq!(
+            report.lsb[1],
+            (2, Room::Bytes(pixels * 3 * 2 / 8 - lsb::HEADER_LENGTH))
+        );
+        assert!(matches!(report.palette, Room::Unavailable(_)));
+        assert_eq!(report.after_iend, Room::Unlimited);
+
+        let processing = Processing {
+            compression: Some(Compression::Deflate),
+            encryption: Some(EncryptionKind::Passphrase),
+        };
+        let encrypted = capacity(&png, Channels::COLOR, &processing, Image::DEFAULT_LIMIT).unwrap();
+        assert_eq!(
+            encrypted.chunk,
+            Room::Bytes(i32::MAX as usize - Compression::HEADER_LENGTH - PASSPHRASE_OVERHEAD)
+        );
+    }
+
+    #[test]
+    fn test_palette_capacity() {
+        // generate.py uses every color of the 4 bit palette
+        let png = load("assets/conformance/palette4.png");
+        let report = capacity(
+            &png,
+            Channels::COLOR,
+            &Processing::default(),
+            Image::DEFAULT_LIMIT,
+        )
+        .unwrap();
+        assert_eq!(report.palette, Room::Bytes(0));
+        assert!(matches!(report.lsb[0].1, Room::Unavailable(_)));
+
+        let png = load("assets/conformance/palette8.png");
+        let report = capacity(
+            &png,
+            Channels::COLOR,
+            &Processing::default(),
+            Image::DEFAULT_LIMIT,
+        )
+        .unwrap();
+        // 179 of the 256 colors are used
+        assert_eq!(report.palette, Room::Bytes((256 - 179) * 3));
+    }
+
+    #[test]
+    fn test_payload_capacity() {
+        let processing = Processing {
+            compression: None,
+            encryption: Some(EncryptionKind::Recipients(2)),
+        };
+        assert_eq!(processing.payload_capacity(100), 0);
+        for room in [1000, AGE_CHUNK, AGE_CHUNK * 3 + 500] {
+            let length = processing.payload_capacity(room);
+            assert!(length + processing.overhead(length) <= room);
+            assert!(
+                length + 1 + processing.overhead(length + 1) > room,
+                "{} bytes of room",
+                room
+            );
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_encryption_overhead() {
+        use crate::chunk_type::ChunkType;
+        use crate::crypto;
+        use std::str::FromStr;
+
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let params = crypto::KdfParams {
+            memory_cost: 8,
+            time_cost: 1,
+            parallelism: 1,
+        };
+        let encrypted = crypto::encrypt_with(b"hunter2", &chunk_type, &[0; 100], params).unwrap();
+        assert_eq!(encrypted.len(), 100 + PASSPHRASE_OVERHEAD);
+
+        let identities: Vec<_> = (0..2).map(|_| age::x25519::Identity::generate()).collect();
+        let recipients: Vec<_> = identities.iter().map(|id| id.to_public()).collect();
+        let processing = Processing {
+            compression: None,
+            encryption: Some(EncryptionKind::Recipients(2)),
+        };
+        for length in [0, 100, AGE_CHUNK + 1] {
+            // the grease stanza is random, try a few
+            for _ in 0..8 {
+                let encrypted =
+                    crypto::encrypt_to_recipients(&recipients, &vec![0; length]).unwrap();
+                assert!(encrypted.len() <= length + processing.overhead(length));
+            }
+        }
+    }
+}
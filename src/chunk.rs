@@ -1,7 +1,11 @@
 use crate::chunk_type::ChunkType;
+use crate::validate::Validator;
 use crate::{PngError, Result};
 use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
 
+/// A single PNG chunk: its type and data. The length and CRC are derived
+/// from them when serializing.
 #[derive(Debug)]
 pub struct Chunk {
     chunk_type: ChunkType,
@@ -12,22 +16,28 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = PngError;
 
     fn try_from(value: &[u8]) -> Result<Self> {
-        let chunk_type_bytes: [u8; 4] = value[4..8].try_into().unwrap();
+        if value.len() < Chunk::CHUNK_METADATA_LENGTH {
+            return Err(PngError::ShortChunk);
+        }
+
+        let length = crate::utils::ref_buffer_to_u32(&value[0..4])? as usize;
+        let chunk_type_bytes = crate::utils::segment4(&value[4..8])?;
         let main_data_bytes: Vec<u8> = value[8..value.len() - 4].to_vec();
         let crc_bytes: &[u8] = &value[value.len() - 4..value.len()];
 
-        let chunk_type = ChunkType::try_from(chunk_type_bytes).unwrap();
-
-        if crc_bytes.is_empty() {
-            return Err(PngError::EmptyCrc);
+        if length != main_data_bytes.len() {
+            return Err(PngError::InvalidChunkLength);
         }
 
+        let chunk_type = ChunkType::try_from(chunk_type_bytes)?;
         let chunk = Chunk::new(chunk_type, main_data_bytes);
 
-        let data_lenght = crate::utils::ref_buffer_to_u32(crc_bytes);
-
-        if chunk.crc() != data_lenght {
-            return Err(PngError::InvalidCrc);
+        let crc = crate::utils::ref_buffer_to_u32(crc_bytes)?;
+        if chunk.crc() != crc {
+            return Err(PngError::InvalidCrc {
+                expected: chunk.crc(),
+                found: crc,
+            });
         }
 
         Ok(chunk)
@@ -40,7 +50,7 @@ impl Display for Chunk {
         write!(f, "length: {}\n\r", self.length())?;
         match self.data_as_string() {
             Ok(data) => write!(f, "data: {}\n\r", data)?,
-            Err(_) => write!(f, "data: {}\n\r", "None")?,
+            Err(_) => write!(f, "data: None\n\r")?,
         };
         write!(f, "crc: {}\n\r", self.crc())?;
         write!(f, "bytes: {:?}\n\r", self.as_bytes())
@@ -48,12 +58,14 @@ impl Display for Chunk {
 }
 
 impl Chunk {
+    /// Length in bytes of the length, chunk type and crc fields of a chunk.
     pub const CHUNK_METADATA_LENGTH: usize = 12;
 
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         Chunk { chunk_type, data }
     }
 
+    /// Length of the chunk data, without the metadata.
     pub fn length(&self) -> u32 {
         self.data.len() as u32
     }
@@ -66,6 +78,12 @@ impl Chunk {
         &self.data
     }
 
+    /// Takes the chunk data, without copying it.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// CRC-32 of the chunk type and data, as stored after the data.
     pub fn crc(&self) -> u32 {
         let data = self.data();
         let type_as_bytes = self.chunk_type.bytes();
@@ -81,6 +99,7 @@ impl Chunk {
         }
     }
 
+    /// Serializes the chunk as length, chunk type, data and crc.
     pub fn as_bytes(&self) -> Vec<u8> {
         let chunk_as_bytes: Vec<u8> = self
             .length()
@@ -95,55 +114,180 @@ impl Chunk {
     }
 }
 
+/// What [`ChunkIterator`] does after an invalid chunk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ParseMode {
+    /// Stops after the first invalid chunk.
+    #[default]
+    Strict,
+    /// Skips the chunks with a wrong crc, trusting their length, and stops
+    /// after any other error.
+    SkipBadCrc,
+    /// Skips anything invalid up to the next chunk with a valid crc, within
+    /// [`MAX_RESYNC_BYTES`] of candidates with a wrong one over the whole
+    /// file.
+    Resync,
+}
+
+impl FromStr for ParseMode {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strict" => Ok(ParseMode::Strict),
+            "skip-bad-crc" => Ok(ParseMode::SkipBadCrc),
+            "resync" => Ok(ParseMode::Resync),
+            _ => Err(PngError::InvalidParseMode),
+        }
+    }
+}
+
+/// Iterates over the chunks of a byte stream (without the png signature).
+/// Stops after the first invalid chunk, unless another [`ParseMode`] is set.
 pub struct ChunkIterator<'a> {
     cur: &'a [u8],
+    offset: usize,
+    index: usize,
+    mode: ParseMode,
+    /// Bytes of candidates the resyncs may still check.
+    resync_budget: usize,
     tainted: bool,
 }
 
 impl<'a> ChunkIterator<'a> {
-    pub fn new(chunks: &'a [u8]) -> ChunkIterator {
+    pub fn new(chunks: &'a [u8]) -> ChunkIterator<'a> {
+        ChunkIterator::with_offset(chunks, 0)
+    }
+
+    /// Same as [`ChunkIterator::new`], but errors report offsets as if
+    /// `chunks` started at `offset` in the file.
+    pub fn with_offset(chunks: &'a [u8], offset: usize) -> ChunkIterator<'a> {
         ChunkIterator {
             cur: chunks,
+            offset,
+            index: 0,
+            mode: ParseMode::Strict,
+            resync_budget: MAX_RESYNC_BYTES,
             tainted: false,
         }
     }
+
+    /// Keeps going after invalid chunks as `mode` says. Every invalid chunk is
+    /// still yielded as an error.
+    pub fn parse_mode(mut self, mode: ParseMode) -> ChunkIterator<'a> {
+        self.mode = mode;
+        self
+    }
+
+    fn next_chunk(&mut self) -> Result<Chunk> {
+        if self.cur.len() < Chunk::CHUNK_METADATA_LENGTH {
+            // short chunk
+            return Err(PngError::ShortChunk);
+        }
+
+        let len = super::utils::ref_buffer_to_u32(&self.cur[0..4])? as usize; // recuperer la longueur du chunk
+        let len = len
+            .checked_add(Chunk::CHUNK_METADATA_LENGTH) // ajouter la longueur des metadatas
+            .filter(|len| *len <= self.cur.len()) // verifier si y a encore assez de donnees pour un chunk
+            .ok_or(PngError::ShortChunk)?;
+
+        let chunk = Chunk::try_from(&self.cur[0..len])?; // convertir le chunk en objet Chunk
+        self.skip(len);
+        self.index += 1;
+        Ok(chunk)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.cur = &self.cur[len..]; // supprimer les octets lus
+        self.offset += len;
+    }
+
+    /// Moves past the invalid chunk at the start of `cur`, or gives up.
+    fn recover(&mut self, error: &PngError) {
+        match self.mode {
+            ParseMode::SkipBadCrc if matches!(error, PngError::InvalidCrc { .. }) => {
+                // the length was checked before the crc
+                let len = super::utils::ref_buffer_to_u32(&self.cur[0..4]).expect("4 bytes");
+                self.skip(len as usize + Chunk::CHUNK_METADATA_LENGTH);
+                self.index += 1;
+            }
+            ParseMode::Resync => {
+                if matches!(error, PngError::InvalidCrc { .. }) {
+                    self.index += 1;
+                }
+                // the length may be damaged too, look for the next chunk from
+                // the next byte
+                match find_chunk(self.cur, 1, &mut self.resync_budget) {
+                    Some(next) => self.skip(next),
+                    None => self.tainted = true,
+                }
+            }
+            _ => self.tainted = true,
+        }
+    }
 }
 
 impl<'a> Iterator for ChunkIterator<'a> {
     type Item = Result<Chunk>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.tainted || self.cur.len() == 0 {
+        if self.tainted || self.cur.is_empty() {
             // tainted or empty
             return None; // no more chunks
-        } else if self.cur.len() < Chunk::CHUNK_METADATA_LENGTH {
-            // short chunk
-            self.tainted = true;
-            return Some(Err(PngError::ShortChunk));
         }
 
-        let len = super::utils::segment4(&self.cur[0..4]).unwrap(); // recuperer l'octet de la longueur du chunk
-        let len = u32::from_be_bytes(len) as usize + Chunk::CHUNK_METADATA_LENGTH; // transformer la longueur en entier en ajoutant la longueur des metadatas
-
-        if self.cur.len() < len {
-            // verifier si y a encore assez de donnees pour un chunk
-            self.tainted = true;
-            return Some(Err(PngError::ShortChunk));
-        }
+        Some(self.next_chunk().map_err(|e| {
+            // indiquer ou le chunk invalide commence, puis continuer ou marquer l'iterateur comme tainted
+            let chunk_type = self
+                .cur
+                .get(4..8)
+                .and_then(|bytes| super::utils::segment4(bytes).ok())
+                .and_then(|bytes| ChunkType::try_from(bytes).ok());
+            let (index, offset) = (self.index, self.offset as u64);
+            self.recover(&e);
+            e.in_chunk(index, offset, chunk_type)
+        }))
+    }
+}
 
-        let chunk = &self.cur[0..len]; // recuperer le chunk
-        self.cur = &self.cur[len..]; // supprimer le chunk de la liste des chunks
+/// Chunk type and data length of a plausible chunk header at `pos`.
+pub(crate) fn chunk_header(bytes: &[u8], pos: usize) -> Option<(ChunkType, usize)> {
+    let header = bytes.get(pos..pos.checked_add(8)?)?;
+    let length = super::utils::ref_buffer_to_u32(&header[0..4]).ok()?;
+    if length > Validator::MAX_CHUNK_LENGTH {
+        return None;
+    }
+    let chunk_type = ChunkType::try_from(super::utils::segment4(&header[4..8]).ok()?).ok()?;
+    Some((chunk_type, length as usize))
+}
 
-        Chunk::try_from(chunk).map_or_else(
-            // convertir le chunk en objet Chunk
-            |e| {
-                // si erreur
-                self.tainted = true; // marquer le chunk comme tainted
-                Some(Err(e)) // retourner l'erreur
-            },
-            |chunk| Some(Ok(chunk)), // sinon retourner le chunk
-        )
+/// Bytes of candidate chunks whose crc the resyncs of a file check before
+/// giving up. Every byte may start a plausible header, so without a bound
+/// crafted data would make resyncing quadratic.
+pub const MAX_RESYNC_BYTES: usize = 16 * 1024 * 1024;
+
+/// Position of the first whole chunk with a valid crc, from `pos`. Candidates
+/// running past the end are skipped without computing their crc, the others
+/// are taken from `budget`, shared by the searches of a file, and the search
+/// fails once it is spent.
+pub(crate) fn find_chunk(bytes: &[u8], pos: usize, budget: &mut usize) -> Option<usize> {
+    for start in pos..bytes.len() {
+        let Some((_, length)) = chunk_header(bytes, start) else {
+            continue;
+        };
+        let end = start + length + Chunk::CHUNK_METADATA_LENGTH;
+        if end > bytes.len() {
+            continue;
+        }
+        // chunk type and data
+        let checked = &bytes[start + 4..end - 4];
+        let crc = u32::from_be_bytes(bytes[end - 4..end].try_into().expect("4 bytes"));
+        if crc::crc32::checksum_ieee(checked) == crc {
+            return Some(start);
+        }
+        *budget = budget.checked_sub(checked.len())?;
     }
+    None
 }
 
 #[cfg(test)]
@@ -272,6 +416,102 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_short_chunk_from_bytes() {
+        assert!(Chunk::try_from([].as_ref()).is_err());
+        assert!(Chunk::try_from([0, 0, 0, 0, 82, 117].as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_from_bytes_with_wrong_length() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[3] = 41;
+
+        assert!(Chunk::try_from(chunk_data.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_iterator_huge_length() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[0..4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let chunks: Vec<Result<Chunk>> = ChunkIterator::new(&chunk_data).collect();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_err());
+    }
+
+    #[test]
+    fn test_resync_bound() {
+        // a plausible 16 KiB chunk header every 8 bytes, none with a valid crc
+        let mut bytes = b"\0\0\x40\0RuSt".repeat(1024 * 1024 / 8);
+        assert_eq!(find_chunk(&bytes, 0, &mut MAX_RESYNC_BYTES.clone()), None);
+
+        // a valid chunk right after is found, past the bound it is not
+        let valid = testing_chunk().as_bytes();
+        let mut near = b"\0\0\x40\0RuSt".repeat(16);
+        near.extend(&valid);
+        let mut budget = MAX_RESYNC_BYTES;
+        assert_eq!(find_chunk(&near, 0, &mut budget), Some(128));
+        // the candidates run past the end, their crc is not checked
+        assert_eq!(budget, MAX_RESYNC_BYTES);
+        bytes.extend(&valid);
+        assert_eq!(find_chunk(&bytes, 0, &mut MAX_RESYNC_BYTES.clone()), None);
+    }
+
+    #[test]
+    fn test_resync_bound_per_file() {
+        // many damaged regions, each costing about 28 KiB of crc checks before
+        // the valid chunk following it
+        let region = [
+            b"\0\0\x10\0RuSt".repeat(8),
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0; 4096]).as_bytes(),
+        ]
+        .concat();
+        let bytes = region.repeat(1024);
+
+        let chunks: Vec<Result<Chunk>> = ChunkIterator::new(&bytes)
+            .parse_mode(ParseMode::Resync)
+            .collect();
+        // the budget of the whole file runs out half way
+        let valid = chunks.iter().filter(|chunk| chunk.is_ok()).count();
+        // the resync checks the 7 candidates after the first one
+        assert_eq!(valid, MAX_RESYNC_BYTES / (7 * (4 + 4096)));
+    }
+
+    #[test]
+    fn test_chunk_iterator_parse_modes() {
+        let mut bytes = testing_chunk().as_bytes();
+        // a chunk with a wrong crc
+        bytes[50] ^= 1;
+        bytes.extend(testing_chunk().as_bytes());
+        // garbage, then a valid chunk
+        bytes.extend_from_slice(b"garbage");
+        bytes.extend(testing_chunk().as_bytes());
+
+        let results = |mode| -> Vec<bool> {
+            ChunkIterator::new(&bytes)
+                .parse_mode(mode)
+                .map(|chunk| chunk.is_ok())
+                .collect()
+        };
+        assert_eq!(results(ParseMode::Strict), [false]);
+        assert_eq!(results(ParseMode::SkipBadCrc), [false, true, false]);
+        assert_eq!(results(ParseMode::Resync), [false, true, false, true]);
+
+        let errors: Vec<PngError> = ChunkIterator::new(&bytes)
+            .parse_mode(ParseMode::Resync)
+            .filter_map(|chunk| chunk.err())
+            .collect();
+        assert!(matches!(
+            errors[1],
+            PngError::Chunk {
+                index: 2,
+                offset: 108,
+                ..
+            }
+        ));
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
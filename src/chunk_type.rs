@@ -3,7 +3,8 @@ use core::str::FromStr;
 
 use crate::{PngError, Result};
 
-#[derive(PartialEq, Eq, Debug)]
+/// The 4 bytes type code of a chunk, e.g. `IHDR` or `RuSt`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
@@ -11,6 +12,10 @@ pub struct ChunkType {
 impl TryFrom<[u8; 4]> for ChunkType {
     type Error = PngError;
     fn try_from(value: [u8; 4]) -> Result<Self> {
+        // chunk types are restricted to ASCII letters
+        if !value.iter().all(u8::is_ascii_alphabetic) {
+            return Err(PngError::InvalidChunkType);
+        }
         Ok(ChunkType { bytes: value })
     }
 }
@@ -19,25 +24,33 @@ impl FromStr for ChunkType {
     type Err = PngError;
 
     fn from_str(s: &str) -> Result<Self> {
-        let bytes = s.as_bytes();
-        let mut result = [0; 4];
-        result.copy_from_slice(bytes);
-        Ok(ChunkType { bytes: result }) // TODO: gerer l'erreur d'un chunk_type trop grand ou trop petit en renvoyant une erreur custom
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| PngError::InvalidChunkType)?;
+        ChunkType::try_from(bytes)
     }
 }
 
 impl Display for ChunkType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", String::from_utf8(self.bytes.to_vec()).unwrap())
+        write!(f, "{}", String::from_utf8_lossy(&self.bytes))
     }
 }
 
 impl ChunkType {
+    /// Chunk types defined by the PNG specification and its APNG extension.
+    pub const KNOWN: [&'static str; 25] = [
+        "IHDR", "PLTE", "IDAT", "IEND", "acTL", "bKGD", "cHRM", "cICP", "cLLI", "eXIf", "fcTL",
+        "fdAT", "gAMA", "hIST", "iCCP", "iTXt", "mDCV", "pHYs", "sBIT", "sPLT", "sRGB", "tEXt",
+        "tIME", "tRNS", "zTXt",
+    ];
+
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
 
-    #[allow(dead_code)]
+    /// Checks that the reserved bit is valid and that every byte is an ASCII letter.
     pub fn is_valid(&self) -> bool {
         if !self.is_reserved_bit_valid() {
             return false;
@@ -50,24 +63,29 @@ impl ChunkType {
         true
     }
 
-    #[allow(dead_code)]
+    /// Critical chunks are needed to display the image (bit 5 of the first byte is 0).
     pub fn is_critical(&self) -> bool {
-        return self.bytes[0].is_ascii_uppercase();
+        self.bytes[0].is_ascii_uppercase()
     }
 
-    #[allow(dead_code)]
+    /// Public chunks are defined by the PNG specification (bit 5 of the second byte is 0).
     pub fn is_public(&self) -> bool {
-        return self.bytes[1].is_ascii_uppercase();
+        self.bytes[1].is_ascii_uppercase()
     }
 
-    #[allow(dead_code)]
+    /// The reserved bit (bit 5 of the third byte) must be 0.
     pub fn is_reserved_bit_valid(&self) -> bool {
-        return self.bytes[2].is_ascii_uppercase();
+        self.bytes[2].is_ascii_uppercase()
     }
 
-    #[allow(dead_code)]
+    /// Safe to copy chunks may be kept by editors that modified critical chunks (bit 5 of the fourth byte is 1).
     pub fn is_safe_to_copy(&self) -> bool {
-        return self.bytes[3].is_ascii_lowercase();
+        self.bytes[3].is_ascii_lowercase()
+    }
+
+    /// Whether the chunk type is one of [`ChunkType::KNOWN`].
+    pub fn is_known(&self) -> bool {
+        Self::KNOWN.contains(&self.to_string().as_str())
     }
 }
 
@@ -151,9 +169,22 @@ mod tests {
         let chunk = ChunkType::from_str("Rust").unwrap();
         assert!(!chunk.is_valid());
 
-        // change this line because the version is outdated
-        // let chunk = ChunkType::from_str("Ru1t");
-        // assert!(chunk.is_err());
+        let chunk = ChunkType::from_str("Ru1t");
+        assert!(chunk.is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_invalid_length() {
+        assert!(ChunkType::from_str("RuS").is_err());
+        assert!(ChunkType::from_str("RuStt").is_err());
+        assert!(ChunkType::from_str("").is_err());
+        assert!(ChunkType::from_str("Rué").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_invalid_bytes() {
+        assert!(ChunkType::try_from([82, 117, 0, 116]).is_err());
+        assert!(ChunkType::try_from([0xff, 0xfe, 83, 116]).is_err());
     }
 
     #[test]
@@ -162,6 +193,13 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_is_known() {
+        assert!(ChunkType::from_str("IDAT").unwrap().is_known());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_known());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_known());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
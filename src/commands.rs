@@ -1,123 +1,1917 @@
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
-use crate::download::download_png_from_url;
-use crate::fs;
-use crate::{PngError, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use pngme::analyze::{self, Analysis};
+use pngme::capacity::{self, Capacity, Processing};
+use pngme::compression::{self, Compression};
+#[cfg(feature = "crypto")]
+use pngme::crypto;
+use pngme::fs::WriteOptions;
+use pngme::inspect::{self, ChunkRecord};
+use pngme::repair::{self, Fix};
+#[cfg(feature = "crypto")]
+use pngme::signature::{self, Integrity, Signature, Verification};
+use pngme::split::{self, Piece};
+use pngme::validate::Validator;
+use pngme::{fs, lsb, stream, validate};
+use pngme::{
+    Channels, Chunk, ChunkPosition, ChunkType, Finding, IdatOptions, Iend, Ihdr, Image, LsbOptions,
+    ParseMode, ParseOptions, Plte, Png, PngError, Result, Severity, StripPolicy, TextEntry,
+};
 
-use std::path::PathBuf;
+use crate::format::{self, OutputFormat};
+use regex::Regex;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use zeroize::Zeroizing;
 
+#[cfg(feature = "download")]
+fn download(url: &str) -> Result<Png> {
+    pngme::download::download_png_from_url(url)
+}
+
+#[cfg(not(feature = "download"))]
+fn download(_url: &str) -> Result<Png> {
+    Err(PngError::DownloadDisabled)
+}
+
+/// Environment variable read before prompting for a passphrase.
+#[cfg(feature = "crypto")]
+const PASSPHRASE_VAR: &str = "PNGME_PASSPHRASE";
+
+/// Reads the passphrase from `PNGME_PASSPHRASE`, or prompts for it on the
+/// terminal, twice when `confirm` is set.
+#[cfg(feature = "crypto")]
+pub fn read_passphrase(confirm: bool) -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    let passphrase = Zeroizing::new(rpassword::prompt_password("Passphrase: ")?);
+    if confirm {
+        let confirmation = Zeroizing::new(rpassword::prompt_password("Confirm passphrase: ")?);
+        if passphrase != confirmation {
+            return Err(PngError::PassphraseMismatch);
+        }
+    }
+    Ok(passphrase)
+}
+
+#[cfg(not(feature = "crypto"))]
+pub fn read_passphrase(_confirm: bool) -> Result<Passphrase> {
+    Err(PngError::EncryptionDisabled)
+}
+
+const LSB_KEY_VAR: &str = "PNGME_LSB_KEY";
+
+/// Reads the key shuffling the pixels of [`Method::Lsb`] from
+/// `PNGME_LSB_KEY`, or prompts for it on the terminal, twice when `confirm`
+/// is set.
+pub fn read_lsb_key(confirm: bool) -> Result<Zeroizing<Vec<u8>>> {
+    if let Ok(key) = std::env::var(LSB_KEY_VAR) {
+        return Ok(Zeroizing::new(key.into_bytes()));
+    }
+
+    let key = Zeroizing::new(rpassword::prompt_password("Key: ")?);
+    if confirm {
+        let confirmation = Zeroizing::new(rpassword::prompt_password("Confirm key: ")?);
+        if key != confirmation {
+            return Err(PngError::KeyMismatch);
+        }
+    }
+    Ok(Zeroizing::new(key.as_bytes().to_vec()))
+}
+
+#[cfg(feature = "crypto")]
+pub type Passphrase = Zeroizing<String>;
+#[cfg(not(feature = "crypto"))]
+pub type Passphrase = String;
+
+/// How `encode` encrypts the message.
+#[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+pub enum Encryption {
+    Passphrase(Passphrase),
+    /// age public keys (`age1...`), any of them can decrypt the message.
+    Recipients(Vec<String>),
+}
+
+/// How `decode` decrypts the message.
+#[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+pub enum Decryption {
+    Passphrase(Passphrase),
+    /// age identity files, as written by `keygen` or `age-keygen`.
+    IdentityFiles(Vec<PathBuf>),
+}
+
+#[cfg(feature = "crypto")]
+fn encrypt(encryption: &Encryption, chunk_type: &ChunkType, message: &[u8]) -> Result<Vec<u8>> {
+    match encryption {
+        Encryption::Passphrase(passphrase) => {
+            crypto::encrypt(passphrase.as_bytes(), chunk_type, message)
+        }
+        Encryption::Recipients(recipients) => {
+            let recipients = recipients
+                .iter()
+                .map(|recipient| crypto::parse_recipient(recipient))
+                .collect::<Result<Vec<_>>>()?;
+            crypto::encrypt_to_recipients(&recipients, message)
+        }
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+fn encrypt(_encryption: &Encryption, _chunk_type: &ChunkType, _message: &[u8]) -> Result<Vec<u8>> {
+    Err(PngError::EncryptionDisabled)
+}
+
+#[cfg(feature = "crypto")]
+fn decrypt(
+    decryption: &Decryption,
+    chunk_type: &ChunkType,
+    data: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    match decryption {
+        Decryption::Passphrase(passphrase) => {
+            crypto::decrypt(passphrase.as_bytes(), chunk_type, data)
+        }
+        Decryption::IdentityFiles(paths) => {
+            let mut identities = Vec::new();
+            for path in paths {
+                let contents = Zeroizing::new(std::fs::read_to_string(path)?);
+                identities.extend(crypto::parse_identities(&contents)?);
+            }
+            crypto::decrypt_with_identities(&identities, data)
+        }
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+fn decrypt(
+    _decryption: &Decryption,
+    _chunk_type: &ChunkType,
+    _data: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    Err(PngError::EncryptionDisabled)
+}
+
+/// Signs the payload chunk at `index` and the image data of `png`, adding the
+/// signature before `IEND`.
+#[cfg(feature = "crypto")]
+fn sign(png: &mut Png, index: usize, signing_key: &Path) -> Result<()> {
+    let contents = Zeroizing::new(std::fs::read_to_string(signing_key)?);
+    let key = signature::parse_signing_key_file(&contents)?;
+
+    let signature = Signature::new(png, &png.chunks()[index], &key);
+    png.insert_chunk(signature.to_chunk(), ChunkPosition::BeforeIend)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "crypto"))]
+fn sign(_png: &mut Png, _index: usize, _signing_key: &Path) -> Result<()> {
+    Err(PngError::EncryptionDisabled)
+}
+
+/// Creates an age identity file at `output_file` and returns its public key.
+#[cfg(feature = "crypto")]
+pub fn keygen(output_file: &Path) -> Result<String> {
+    let identity = crypto::Identity::generate();
+    fs::write_private_file(output_file, crypto::identity_file(&identity).as_bytes())?;
+
+    let public_key = identity.to_public().to_string();
+    println!("Public key: {}", public_key);
+    Ok(public_key)
+}
+
+#[cfg(not(feature = "crypto"))]
+pub fn keygen(_output_file: &Path) -> Result<String> {
+    Err(PngError::EncryptionDisabled)
+}
+
+/// Creates an Ed25519 signing key file at `output_file` and returns its
+/// public key.
+#[cfg(feature = "crypto")]
+pub fn keygen_signing(output_file: &Path) -> Result<String> {
+    let key = signature::generate_signing_key();
+    fs::write_private_file(output_file, signature::signing_key_file(&key).as_bytes())?;
+
+    let public_key = signature::verifying_key_to_string(&key.verifying_key());
+    println!("Public key: {}", public_key);
+    Ok(public_key)
+}
+
+#[cfg(not(feature = "crypto"))]
+pub fn keygen_signing(_output_file: &Path) -> Result<String> {
+    Err(PngError::EncryptionDisabled)
+}
+
+/// Checks the signatures of a file against the public keys given directly
+/// and in `trusted_files`, printing what each one covers. Succeeds when one
+/// trusted signature matches the file; signature chunks that can't be
+/// decoded are reported and skipped.
+#[cfg(feature = "crypto")]
+pub fn verify(
+    file_path: &Path,
+    trusted_files: &[PathBuf],
+    trusted_keys: &[String],
+) -> Result<Vec<Verification>> {
+    let mut trusted = Vec::new();
+    for path in trusted_files {
+        trusted.extend(signature::parse_verifying_keys(&std::fs::read_to_string(
+            path,
+        )?)?);
+    }
+    for key in trusted_keys {
+        trusted.push(signature::parse_verifying_key(key)?);
+    }
+
+    let png = fs::file_path_to_png(file_path)?;
+    let mut verifications = Vec::new();
+    for verification in signature::verify(&png, &trusted)? {
+        match verification {
+            Ok(verification) => verifications.push(verification),
+            Err(error) => match std::error::Error::source(&error) {
+                Some(cause) => eprintln!("warning: {}: {}", error, cause),
+                None => eprintln!("warning: {}", error),
+            },
+        }
+    }
+    for verification in verifications.iter() {
+        let describe = |integrity| match integrity {
+            Integrity::Intact => "intact",
+            Integrity::Altered => "ALTERED",
+            Integrity::Missing => "MISSING",
+        };
+        println!(
+            "signature #{} by {} ({})",
+            verification.index,
+            signature::verifying_key_to_string(&verification.signature.signer),
+            if verification.trusted {
+                "trusted"
+            } else {
+                "UNTRUSTED"
+            }
+        );
+        println!(
+            "  signature: {}",
+            if verification.valid {
+                "valid"
+            } else {
+                "INVALID"
+            }
+        );
+        println!(
+            "  payload {}: {}",
+            verification.signature.payload_type,
+            describe(verification.payload)
+        );
+        println!("  image data: {}", describe(verification.image));
+        let covered: Vec<_> = verification
+            .covered
+            .iter()
+            .map(|(index, chunk_type)| format!("#{} {}", index, chunk_type))
+            .collect();
+        println!("  covers: {}", covered.join(", "));
+    }
+
+    if !verifications.iter().any(Verification::is_ok) {
+        return Err(PngError::SignatureVerificationFailed);
+    }
+    Ok(verifications)
+}
+
+#[cfg(not(feature = "crypto"))]
+pub fn verify(
+    _file_path: &Path,
+    _trusted_files: &[PathBuf],
+    _trusted_keys: &[String],
+) -> Result<()> {
+    Err(PngError::EncryptionDisabled)
+}
+
+/// How payloads are written on the command line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Encoding {
+    /// The bytes as they are.
+    #[default]
+    Raw,
+    /// UTF-8 text, checked when decoding.
+    Utf8,
+    Base64,
+    Hex,
+}
+
+impl FromStr for Encoding {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "raw" => Ok(Encoding::Raw),
+            "utf8" => Ok(Encoding::Utf8),
+            "base64" => Ok(Encoding::Base64),
+            "hex" => Ok(Encoding::Hex),
+            _ => Err(PngError::InvalidEncoding),
+        }
+    }
+}
+
+impl Encoding {
+    /// Turns text in this encoding back into the bytes it stands for.
+    pub fn decode(&self, text: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Raw => Ok(text.to_vec()),
+            Encoding::Utf8 => match std::str::from_utf8(text) {
+                Ok(text) => Ok(text.as_bytes().to_vec()),
+                Err(_) => Err(PngError::ChunkDataUTF8Error),
+            },
+            Encoding::Base64 => BASE64
+                .decode(text.trim_ascii())
+                .map_err(|_| PngError::InvalidEncoding),
+            Encoding::Hex => hex::decode(text.trim_ascii()).map_err(|_| PngError::InvalidEncoding),
+        }
+    }
+
+    /// Writes `bytes` in this encoding; base64 and hex end with a newline.
+    pub fn encode(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Raw => Ok(bytes.to_vec()),
+            Encoding::Utf8 => self.decode(bytes),
+            Encoding::Base64 => Ok(format!("{}\n", BASE64.encode(bytes)).into_bytes()),
+            Encoding::Hex => Ok(format!("{}\n", hex::encode(bytes)).into_bytes()),
+        }
+    }
+}
+
+/// Reads a payload given either on the command line or from `input` (stdin
+/// for `-`), in the given encoding.
+pub fn read_payload(
+    message: Option<&str>,
+    input: Option<&Path>,
+    encoding: Encoding,
+) -> Result<Vec<u8>> {
+    let text = match (message, input) {
+        (Some(message), _) => message.as_bytes().to_vec(),
+        (None, Some(input)) => fs::read_file(input)?,
+        (None, None) => return Err(PngError::MessageNotSpecified),
+    };
+    encoding.decode(&text)
+}
+
+/// Where `encode` hides the payload and `decode` looks for it.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum Method {
+    /// In chunks of its own.
+    #[default]
+    Chunk,
+    /// In the low bits of the pixels, see [`lsb`].
+    Lsb(LsbOptions),
+}
+
+/// Optional behaviour of `encode`.
+#[derive(Default)]
+pub struct EncodeOptions {
+    pub method: Method,
+    /// Where the chunk goes, with [`Method::Chunk`].
+    pub position: ChunkPosition,
+    /// Compresses the payload, before encrypting it.
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+    /// Signing key file used to add a signature chunk.
+    pub signing_key: Option<PathBuf>,
+    /// Splits the payload in chunks of at most this size. Payloads too large
+    /// for one chunk are always split.
+    pub max_chunk_size: Option<usize>,
+    /// Size limit of the decoded pixels with [`Method::Lsb`],
+    /// [`Image::DEFAULT_LIMIT`] if not given.
+    pub max_image_size: Option<u64>,
+    /// How the output file is replaced.
+    pub write: WriteOptions,
+}
+
+/// Reads the png to encode into with [`Method::Lsb`] and hides `data` in its
+/// pixels.
+fn embed_lsb(
+    file_path: &Option<&str>,
+    url: &Option<&str>,
+    data: &[u8],
+    lsb: &LsbOptions,
+    options: &EncodeOptions,
+) -> Result<Png> {
+    let mut png = match (file_path, url) {
+        (Some(file_path), _) => fs::file_path_to_png(Path::new(file_path))?,
+        (None, Some(url)) => download(url)?,
+        (None, None) => return Err(PngError::NoSource),
+    };
+    let mut image = png.image_with_limit(options.max_image_size.unwrap_or(Image::DEFAULT_LIMIT))?;
+    lsb::embed(&mut image, data, lsb)?;
+    png.replace_image(&image, &IdatOptions::default())?;
+    Ok(png)
+}
+
+/// Hides `message` in a new chunk of `chunk_type`, or in the pixels with
+/// [`Method::Lsb`], which takes no chunk type.
 pub fn encode(
     file_path: &Option<&str>,
     url: &Option<&str>,
-    chunk_type: &str,
-    message: &str,
+    chunk_type: Option<&str>,
+    message: &[u8],
     output_file: &Option<PathBuf>,
+    options: &EncodeOptions,
 ) -> Result<()> {
-    let mut png;
-    let mut path = &PathBuf::from_str("dist/output.png").unwrap();
-    let file_path_buf: PathBuf;
+    let chunk_type = match (&options.method, chunk_type) {
+        // the encryption of a payload in the pixels is bound to a fixed type
+        (Method::Lsb(_), _) => ChunkType::from_str(lsb::CHUNK_TYPE)?,
+        (Method::Chunk, Some(chunk_type)) => ChunkType::from_str(chunk_type)?,
+        (Method::Chunk, None) => return Err(PngError::ChunkTypeNotSpecified),
+    };
+    let compressed;
+    let message = match options.compression {
+        Some(compression) => {
+            compressed = compression::compress(message, compression)?;
+            &compressed[..]
+        }
+        None => message,
+    };
+    let data = match &options.encryption {
+        Some(encryption) => encrypt(encryption, &chunk_type, message)?,
+        None => message.to_vec(),
+    };
+    if let Method::Lsb(lsb) = &options.method {
+        if options.signing_key.is_some() {
+            return Err(PngError::LsbUnsupported("signatures cover a payload chunk"));
+        }
+        let output_file = match (file_path, url) {
+            (Some(file_path), _) => output_file.as_deref().unwrap_or(Path::new(file_path)),
+            (None, Some(_)) => output_file
+                .as_deref()
+                .ok_or(PngError::OutputFileNotSpecified)?,
+            (None, None) => return Err(PngError::NoSource),
+        };
+        if output_file == Path::new("-") {
+            let png = embed_lsb(file_path, url, &data, lsb, options)?;
+            return fs::write_file(output_file, &png.as_bytes());
+        }
+        // the output may be the input file, nothing may change it between
+        // reading and rewriting it
+        let _lock = fs::lock_file(output_file)?;
+        let png = embed_lsb(file_path, url, &data, lsb, options)?;
+        return fs::replace_file(output_file, &png.as_bytes(), &options.write);
+    }
+    let max_chunk_size = match options.max_chunk_size {
+        None if data.len() > Validator::MAX_CHUNK_LENGTH as usize => {
+            Some(Validator::MAX_CHUNK_LENGTH as usize)
+        }
+        max_chunk_size => max_chunk_size,
+    };
+    let chunks = match max_chunk_size {
+        Some(max_chunk_size) => split::split(chunk_type, &data, max_chunk_size)?,
+        None => vec![Chunk::new(chunk_type, data)],
+    };
+    let position = options.position;
 
     if let Some(file_path) = file_path {
-        file_path_buf = PathBuf::from_str(file_path).unwrap();
-        png = fs::file_path_to_png(&file_path_buf)?;
-        path = &file_path_buf;
+        let file_path = Path::new(file_path);
+        // rewrite the previous file if no output file is given
+        let output_file = output_file.as_deref().unwrap_or(file_path);
+        fs::stream_png_file_with(file_path, output_file, &options.write, |reader, writer| {
+            let signing_key = match &options.signing_key {
+                Some(signing_key) => signing_key,
+                None => return stream::insert_chunks(reader, writer, chunks, position),
+            };
+            // the signature covers the image data, which needs the whole file
+            let mut png = Png::from_chunks(reader.collect::<Result<_>>()?);
+            let index = png.insert_chunks(chunks, position)?;
+            sign(&mut png, index, signing_key)?;
+            png.chunks()
+                .iter()
+                .try_for_each(|chunk| writer.write_chunk(chunk))
+        })
     } else if let Some(url) = url {
-        if let None = output_file {
-            return Err(PngError::OutputFileNotSpecified);
+        let output_file = output_file
+            .as_ref()
+            .ok_or(PngError::OutputFileNotSpecified)?;
+        let mut png = download(url)?;
+        let index = png.insert_chunks(chunks, position)?;
+        if let Some(signing_key) = &options.signing_key {
+            sign(&mut png, index, signing_key)?;
         }
-        png = download_png_from_url(url).unwrap();
+        fs::write_png_file(output_file, &png.as_bytes(), &options.write)
     } else {
-        return Err(PngError::NoSource);
+        Err(PngError::NoSource)
     }
+}
 
-    let chunk_type = ChunkType::from_str(chunk_type)?;
+/// Which chunks `decode`, `remove` and `print` act on. A chunk is selected
+/// when it passes every criterion that is set.
+#[derive(Default)]
+pub struct Selector {
+    pub chunk_type: Option<String>,
+    pub type_regex: Option<Regex>,
+    /// Index of the chunk in the file, from 0.
+    pub index: Option<usize>,
+    /// Any of these chunk types, or any type if empty.
+    pub chunk_types: Vec<String>,
+    pub private: bool,
+    pub ancillary: bool,
+    /// Chunk types the PNG specification does not define.
+    pub unknown: bool,
+    /// Acts on every selected chunk instead of the first one.
+    pub all: bool,
+}
 
-    png.append_chunk(Chunk::new(chunk_type, message.as_bytes().to_vec()));
+impl Selector {
+    pub fn matches(&self, index: usize, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        self.chunk_type
+            .as_ref()
+            .is_none_or(|expected| *expected == name)
+            && self
+                .type_regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&name))
+            && self.index.is_none_or(|expected| expected == index)
+            && (self.chunk_types.is_empty() || self.chunk_types.contains(&name))
+            && (!self.private || !chunk_type.is_public())
+            && (!self.ancillary || !chunk_type.is_critical())
+            && (!self.unknown || !chunk_type.is_known())
+    }
+}
 
-    match output_file {
-        Some(output_file) => {
-            // return the file to the output_file
-            fs::vec_to_new_png_file(output_file, &png.as_bytes())
+/// Optional behaviour of `decode`.
+#[derive(Default)]
+pub struct DecodeOptions {
+    pub method: Method,
+    pub decryption: Option<Decryption>,
+    /// Decompresses the payload, which must have been compressed by
+    /// `encode`. Nothing else tells apart an uncompressed payload starting
+    /// with the bytes of a compression header.
+    pub decompress: bool,
+    /// Size limit of a decompressed payload, [`Compression::DEFAULT_LIMIT`]
+    /// if not given.
+    pub max_decompressed_size: Option<u64>,
+    /// Size limit of the decoded pixels with [`Method::Lsb`],
+    /// [`Image::DEFAULT_LIMIT`] if not given.
+    pub max_image_size: Option<u64>,
+    /// Where to write the payload (stdout for `-`) instead of printing the
+    /// chunk.
+    pub output: Option<PathBuf>,
+    pub encoding: Encoding,
+    pub parse_mode: ParseMode,
+}
+
+/// A payload found by [`decode`], the plaintext once decrypted. Its data is
+/// wiped from memory when dropped.
+pub struct Payload {
+    pub chunk_type: ChunkType,
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl Payload {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Shown as the chunk holding the payload.
+impl Display for Payload {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let chunk = Chunk::new(self.chunk_type, self.data.to_vec());
+        let result = write!(f, "{}", chunk);
+        // the copy is wiped too
+        drop(Zeroizing::new(chunk.into_data()));
+        result
+    }
+}
+
+/// Streams the chunks of a file, or with a lenient parse mode, reads it whole
+/// and warns about the invalid chunks it went past.
+fn read_chunks(
+    file_path: &Path,
+    mode: ParseMode,
+) -> Result<Box<dyn Iterator<Item = Result<Chunk>>>> {
+    if mode == ParseMode::Strict {
+        return Ok(Box::new(fs::open_png_reader(file_path)?));
+    }
+
+    let parsed = Png::parse_with(&fs::read_file(file_path)?, &ParseOptions { mode })?;
+    for diagnostic in parsed.diagnostics.iter() {
+        match std::error::Error::source(diagnostic) {
+            Some(cause) => eprintln!("warning: {}: {}", diagnostic, cause),
+            None => eprintln!("warning: {}", diagnostic),
+        }
+    }
+    Ok(Box::new(parsed.png.into_chunks().into_iter().map(Ok)))
+}
+
+/// The payload of the first selected chunk, or of all of them, a payload
+/// split in several chunks put back together.
+fn find_payloads(file_path: &Path, selector: &Selector, mode: ParseMode) -> Result<Vec<Chunk>> {
+    // the pieces of a split payload are grouped by chunk type and id, in the
+    // place of the first one
+    type Payload = (Option<(ChunkType, u32)>, Vec<Chunk>);
+    let mut payloads: Vec<Payload> = Vec::new();
+    for (index, chunk) in read_chunks(file_path, mode)?.enumerate() {
+        let chunk = chunk?;
+        if !selector.matches(index, &chunk) {
+            continue;
         }
-        None => {
-            // return the file to the previous file
-            fs::rewrite_png_file(path, &png.as_bytes())
+        let key = Piece::try_from(&chunk)
+            .ok()
+            .map(|piece| (*chunk.chunk_type(), piece.id));
+        let group = payloads
+            .iter()
+            .position(|(other, _)| key.is_some() && *other == key);
+        match group {
+            Some(group) => payloads[group].1.push(chunk),
+            None if selector.all || payloads.is_empty() => payloads.push((key, vec![chunk])),
+            None => {}
         }
+        if !selector.all && payloads.first().is_some_and(|(key, _)| key.is_none()) {
+            // a plain payload is a single chunk, no need to read further
+            break;
+        }
+    }
+    if payloads.is_empty() {
+        return Err(PngError::ChunkNotFound);
     }
+
+    payloads
+        .into_iter()
+        .map(|(key, mut pieces)| match key {
+            Some((chunk_type, _)) => Ok(Chunk::new(chunk_type, split::reassemble(&pieces)?)),
+            None => Ok(pieces.remove(0)),
+        })
+        .collect()
 }
 
-pub fn decode(file_path: &PathBuf, chunk_type: &str) -> Result<Option<Chunk>> {
-    let png = fs::file_path_to_png(file_path)?;
-    match png.chunk_by_type(chunk_type) {
-        Some(chunk) => {
-            println!("{}", chunk);
-            Ok(Some(Chunk::new(
-                ChunkType::from_str(chunk_type)?,
-                chunk.data().to_vec(),
-            )))
+/// Finds the first selected chunk, or all of them, and prints it, or writes
+/// only the payloads when an output or an encoding is given. A payload split
+/// in several chunks is put back together. With a decryption key, the data is
+/// decrypted first and the returned payloads hold the plaintext, decompressed
+/// if asked.
+///
+/// With [`Method::Lsb`], the payload is read from the pixels instead, the
+/// selector is not used and the payload has the type [`lsb::CHUNK_TYPE`].
+pub fn decode(
+    file_path: &Path,
+    selector: &Selector,
+    options: &DecodeOptions,
+) -> Result<Vec<Payload>> {
+    let payloads = match &options.method {
+        Method::Chunk => find_payloads(file_path, selector, options.parse_mode)?,
+        Method::Lsb(lsb) => {
+            let bytes = fs::read_file(file_path)?;
+            let png = Png::parse_with(
+                &bytes,
+                &ParseOptions {
+                    mode: options.parse_mode,
+                },
+            )?
+            .png;
+            let limit = options.max_image_size.unwrap_or(Image::DEFAULT_LIMIT);
+            let data = lsb::extract(&png.image_with_limit(limit)?, lsb)?;
+            vec![Chunk::new(ChunkType::from_str(lsb::CHUNK_TYPE)?, data)]
+        }
+    };
+
+    let mut decoded = Vec::new();
+    let mut written = Zeroizing::new(Vec::new());
+    for chunk in payloads {
+        let chunk_type = *chunk.chunk_type();
+        let mut data = match &options.decryption {
+            Some(decryption) => decrypt(decryption, &chunk_type, chunk.data())?,
+            None => Zeroizing::new(chunk.into_data()),
+        };
+        if options.decompress {
+            let limit = options
+                .max_decompressed_size
+                .unwrap_or(Compression::DEFAULT_LIMIT);
+            data = Zeroizing::new(compression::decompress(&data, limit)?);
+        }
+        let payload = Payload { chunk_type, data };
+
+        if options.output.is_none() && options.encoding == Encoding::Raw {
+            println!("{}", payload);
+        } else {
+            let encoded = Zeroizing::new(options.encoding.encode(payload.data())?);
+            written.extend_from_slice(&encoded);
         }
-        None => Err(PngError::ChunkNotFound),
+        decoded.push(payload);
     }
+
+    if options.output.is_some() || options.encoding != Encoding::Raw {
+        let output = options.output.as_deref().unwrap_or(Path::new("-"));
+        fs::write_file(output, &written)?;
+    }
+    Ok(decoded)
 }
 
-pub fn remove(file_path: &PathBuf, chunk_type: &str) -> Result<Option<Chunk>> {
-    let mut png = fs::file_path_to_png(file_path)?;
-    match png.remove_chunk(chunk_type) {
-        Ok(chunk) => {
-            println!(
-                "chunk with chunk type {} has been deleted",
-                chunk.chunk_type()
-            );
-            fs::rewrite_png_file(file_path, &png.as_bytes())?;
-            Ok(Some(chunk))
+/// Removes the first selected chunk, or all of them, and returns them.
+pub fn remove(file_path: &Path, selector: &Selector, write: &WriteOptions) -> Result<Vec<Chunk>> {
+    let chunks = fs::stream_png_file_with(file_path, file_path, write, |reader, writer| {
+        let mut removed = Vec::new();
+        for (index, chunk) in reader.enumerate() {
+            let chunk = chunk?;
+            if (selector.all || removed.is_empty()) && selector.matches(index, &chunk) {
+                removed.push(chunk);
+            } else {
+                writer.write_chunk(&chunk)?;
+            }
+        }
+        if removed.is_empty() {
+            return Err(PngError::ChunkNotFound);
         }
-        Err(error) => Err(error),
+        Ok(removed)
+    })?;
+
+    for chunk in chunks.iter() {
+        println!(
+            "chunk with chunk type {} has been deleted",
+            chunk.chunk_type()
+        );
     }
+    Ok(chunks)
+}
+
+/// Optional behaviour of `print`.
+pub struct PrintOptions {
+    pub format: OutputFormat,
+    pub parse_mode: ParseMode,
+    /// Number of data bytes shown in previews.
+    pub preview_length: usize,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            format: OutputFormat::default(),
+            parse_mode: ParseMode::default(),
+            preview_length: 32,
+        }
+    }
+}
+
+/// Prints every selected chunk, whatever `selector.all` says.
+///
+/// Every format but text lists chunks with a wrong crc instead of failing on
+/// them, other damage is handled as `options.parse_mode` says.
+pub fn print(file_path: &Path, selector: &Selector, options: &PrintOptions) -> Result<()> {
+    if options.format != OutputFormat::Text {
+        let bytes = fs::read_file(file_path)?;
+        let inspection = inspect::inspect(&bytes, options.parse_mode)?;
+        for error in inspection.diagnostics.iter() {
+            eprintln!("warning: {}", error);
+        }
+        let records: Vec<ChunkRecord> = inspection
+            .chunks
+            .into_iter()
+            .filter(|record| selector.matches(record.index, &record.chunk))
+            .collect();
+        print!(
+            "{}",
+            format::render(
+                options.format,
+                file_path,
+                bytes.len() as u64,
+                &records,
+                options.preview_length
+            )
+        );
+        return Ok(());
+    }
+
+    for (index, chunk) in read_chunks(file_path, options.parse_mode)?.enumerate() {
+        let chunk = chunk?;
+        if selector.matches(index, &chunk) {
+            print!("{}", chunk);
+        }
+    }
+    println!();
+    Ok(())
 }
 
-pub fn print(file_path: &PathBuf) -> Result<()> {
+/// Removes the chunks `policy` does not keep, writing to `output_file` or
+/// rewriting the file, and prints the bytes saved.
+pub fn strip(
+    file_path: &Path,
+    output_file: Option<&Path>,
+    policy: &StripPolicy,
+    write: &WriteOptions,
+) -> Result<Vec<Chunk>> {
+    let output_file = output_file.unwrap_or(file_path);
+    let removed = fs::stream_png_file_with(file_path, output_file, write, |reader, writer| {
+        let mut removed = Vec::new();
+        for chunk in reader {
+            let chunk = chunk?;
+            if policy.removes(&chunk) {
+                removed.push(chunk);
+            } else {
+                writer.write_chunk(&chunk)?;
+            }
+        }
+        Ok(removed)
+    })?;
+
+    let mut saved = 0;
+    for chunk in removed.iter() {
+        // length, type and crc come with the data
+        saved += 12 + chunk.length() as u64;
+        println!("removed {} ({} bytes)", chunk.chunk_type(), chunk.length());
+    }
+    println!("{} chunks removed, {} bytes saved", removed.len(), saved);
+    Ok(removed)
+}
+
+/// Decodes the image data, up to `max_image_size` bytes of samples, and
+/// encodes it again as `options` says, writing to `output_file` or rewriting
+/// the file. Returns and prints the length of the image data before and
+/// after.
+pub fn reencode(
+    file_path: &Path,
+    output_file: Option<&Path>,
+    options: &IdatOptions,
+    max_image_size: u64,
+    write: &WriteOptions,
+) -> Result<(u64, u64)> {
+    let idat_length = |png: &Png| -> u64 {
+        png.chunks_by_type("IDAT")
+            .map(|chunk| chunk.length() as u64)
+            .sum()
+    };
+    let reencode = |png: &mut Png| {
+        let before = idat_length(png);
+        let image = png.image_with_limit(max_image_size)?;
+        png.replace_image(&image, options)?;
+        Ok((before, idat_length(png)))
+    };
+
+    let (before, after) = match output_file {
+        None => fs::modify_png_file(file_path, write, reencode)?,
+        Some(output_file) => {
+            let mut png = fs::file_path_to_png(file_path)?;
+            let lengths = reencode(&mut png)?;
+            fs::write_png_file(output_file, &png.as_bytes(), write)?;
+            lengths
+        }
+    };
+    println!("image data: {} bytes before, {} bytes after", before, after);
+    Ok((before, after))
+}
+
+/// Prints how many payload bytes each embedding method can hide in the file,
+/// once `processing` added its headers.
+pub fn capacity(
+    file_path: &Path,
+    channels: Channels,
+    processing: &Processing,
+    max_image_size: u64,
+) -> Result<Capacity> {
     let png = fs::file_path_to_png(file_path)?;
-    println!("{}", png);
+    let capacity = capacity::capacity(&png, channels, processing, max_image_size)?;
+
+    println!(
+        "chunk: {} in one chunk, unlimited when split",
+        capacity.chunk
+    );
+    for (bits, room) in capacity.lsb.iter() {
+        let plural = if *bits == 1 { "" } else { "s" };
+        println!("lsb, {} bit{} per channel: {}", bits, plural, room);
+    }
+    println!("palette: {}", capacity.palette);
+    println!("after IEND: {}", capacity.after_iend);
+    if *processing != Processing::default() {
+        println!(
+            "headers: {} bytes of compression and encryption counted",
+            processing.overhead(0)
+        );
+    }
+    Ok(capacity)
+}
+
+/// Prints the signs of hidden data found in a file and its suspicion score,
+/// failing if the score reaches `threshold`.
+pub fn analyze(file_path: &Path, threshold: Option<u32>, max_image_size: u64) -> Result<Analysis> {
+    let analysis = analyze::analyze(&fs::read_file(file_path)?, max_image_size)?;
+
+    for indicator in analysis.indicators.iter() {
+        println!("{}", indicator);
+    }
+    match analysis.lsb {
+        Some(lsb) => {
+            match lsb.chi_square {
+                Some(p) => println!(
+                    "chi-square: p = {:.4}, random low bits in the first {:.0}% of the samples",
+                    p,
+                    lsb.chi_square_prefix * 100.0
+                ),
+                None => println!("chi-square: too few samples"),
+            }
+            match lsb.rs_estimate {
+                Some(estimate) => println!(
+                    "rs analysis: {:.1}% of the samples changed",
+                    estimate * 100.0
+                ),
+                None => println!("rs analysis: no estimate"),
+            }
+        }
+        None => println!("pixels not analyzed"),
+    }
+    println!(
+        "suspicion score: {}/{}",
+        analysis.score,
+        Analysis::MAX_SCORE
+    );
+
+    if threshold.is_some_and(|threshold| analysis.score >= threshold) {
+        return Err(PngError::Suspicious {
+            score: analysis.score,
+        });
+    }
+    Ok(analysis)
+}
+
+/// Recovers the chunks of a damaged file, prints every fix and writes the
+/// result to `output_file` or back to the file, unless `dry_run` is set.
+pub fn repair(
+    file_path: &Path,
+    output_file: Option<&Path>,
+    dry_run: bool,
+    write: &WriteOptions,
+) -> Result<Vec<Fix>> {
+    // nothing may change the file between reading and rewriting it
+    let _lock = match output_file {
+        None if !dry_run => fs::lock_file(file_path)?,
+        _ => None,
+    };
+    let repair = repair::repair(&fs::png_file_to_vec(file_path)?)?;
+    for fix in repair.fixes.iter() {
+        println!("{}", fix);
+    }
+
+    if repair.fixes.is_empty() {
+        println!("no damage found");
+    } else if dry_run {
+        println!("{} fixes, nothing written (dry run)", repair.fixes.len());
+    } else {
+        match output_file {
+            Some(output_file) => fs::write_png_file(output_file, &repair.png.as_bytes(), write)?,
+            None => fs::replace_file(file_path, &repair.png.as_bytes(), write)?,
+        }
+        let output_file = output_file.unwrap_or(file_path);
+        println!(
+            "{} fixes, written to {}",
+            repair.fixes.len(),
+            output_file.display()
+        );
+    }
+    Ok(repair.fixes)
+}
+
+pub fn info(file_path: &Path) -> Result<()> {
+    let mut ihdr = None;
+    let mut palette = None;
+    // IHDR and PLTE must come before the image data, no need to read further
+    for chunk in fs::open_png_reader(file_path)? {
+        let chunk = chunk?;
+        match chunk.chunk_type().to_string().as_str() {
+            Ihdr::CHUNK_TYPE => ihdr = Some(Ihdr::try_from(&chunk)?),
+            Plte::CHUNK_TYPE => palette = Some(Plte::try_from(&chunk)?),
+            "IDAT" | Iend::CHUNK_TYPE => break,
+            _ => {}
+        }
+    }
+
+    let ihdr = ihdr.ok_or(PngError::ChunkNotFound)?;
+    println!("width: {}", ihdr.width);
+    println!("height: {}", ihdr.height);
+    println!("bit depth: {}", ihdr.bit_depth);
+    println!(
+        "color type: {} ({})",
+        ihdr.color_type,
+        ihdr.color_type.value()
+    );
+    println!("compression: {}", ihdr.compression);
+    println!("filter: {}", ihdr.filter);
+    println!(
+        "interlace: {}",
+        if ihdr.is_interlaced() {
+            "adam7"
+        } else {
+            "none"
+        }
+    );
+    if let Some(palette) = palette {
+        println!("palette: {} entries", palette.entries().len());
+        for (index, rgb) in palette.entries().iter().enumerate() {
+            println!("  {}: #{:02x}{:02x}{:02x}", index, rgb.r, rgb.g, rgb.b);
+        }
+    }
     Ok(())
 }
 
+/// Prints every text entry, and a warning for each text chunk that can't be
+/// decoded.
+pub fn text_list(file_path: &Path) -> Result<Vec<TextEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::file_path_to_png(file_path)?.text_entries() {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(error) => match std::error::Error::source(&error) {
+                Some(cause) => eprintln!("warning: {}: {}", error, cause),
+                None => eprintln!("warning: {}", error),
+            },
+        }
+    }
+    for entry in entries.iter() {
+        println!(
+            "{} [{}]: {}",
+            entry.keyword,
+            entry.kind.chunk_type(),
+            entry.text
+        );
+    }
+    Ok(entries)
+}
+
+pub fn text_get(file_path: &Path, keyword: &str) -> Result<TextEntry> {
+    let png = fs::file_path_to_png(file_path)?;
+    let entry = png.text(keyword).ok_or(PngError::ChunkNotFound)?;
+    println!("{}", entry.text);
+    Ok(entry)
+}
+
+pub fn text_add(file_path: &Path, entry: &TextEntry, write: &WriteOptions) -> Result<()> {
+    fs::modify_png_file(file_path, write, |png| png.add_text(entry))
+}
+
+pub fn text_set(file_path: &Path, entry: &TextEntry, write: &WriteOptions) -> Result<()> {
+    fs::modify_png_file(file_path, write, |png| png.set_text(entry))
+}
+
+pub fn text_remove(
+    file_path: &Path,
+    keyword: &str,
+    write: &WriteOptions,
+) -> Result<Vec<TextEntry>> {
+    let removed = fs::modify_png_file(file_path, write, |png| png.remove_text(keyword))?;
+    println!(
+        "{} text entries with keyword {} have been deleted",
+        removed.len(),
+        keyword
+    );
+    Ok(removed)
+}
+
+pub fn lint(file_path: &Path, strict: bool) -> Result<Vec<Finding>> {
+    let findings = validate::validate_reader(fs::open_file_reader(file_path)?)?;
+
+    for finding in findings.iter() {
+        println!("{}", finding);
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+
+    if errors > 0 || (strict && warnings > 0) {
+        return Err(PngError::ValidationFailed { errors, warnings });
+    }
+    println!("{} errors, {} warnings", errors, warnings);
+    Ok(findings)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::{Duration, SystemTime};
 
-    const TEST_FILE_PATH: &str = "E:/rust/pngme/assets/bg sa mere.png";
+    const TEST_FILE_PATH: &str = "assets/res.png";
     const TEST_DATA: &str = "This is where your secret message will be!";
 
-    // DON'T RUN ALL THESE TESTS AT THE SAME TIME !
-    // USE TEST ONE BY ONE !
-    // thanks <3
-
-    #[test]
-    // pub fn test_encode_command_with_local_file_for_data() {
-    //     encode(
-    //         &Some(PathBuf::from(TEST_FILE_PATH)),
-    //         &None,
-    //         &String::from("RuSt"),
-    //         &String::from(TEST_DATA),
-    //         &None,
-    //     )
-    //     .unwrap();
-    //     let png = fs::file_path_to_png(&PathBuf::from(TEST_FILE_PATH)).unwrap();
-    //     let chunks = png.chunks();
-    //     assert_eq!(
-    //         TEST_DATA,
-    //         &chunks[chunks.len() - 1].data_as_string().unwrap()
-    //     );
-    // }
+    fn of_type(chunk_type: &str) -> Selector {
+        Selector {
+            chunk_type: Some(chunk_type.to_string()),
+            ..Default::default()
+        }
+    }
+
+    // every test works on its own copy of the asset so they can run in parallel
+    fn testing_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pngme-commands-{}.png", name));
+        std::fs::copy(TEST_FILE_PATH, &path).unwrap();
+        path
+    }
+
+    #[cfg(feature = "crypto")]
+    fn passphrase(passphrase: &str) -> Passphrase {
+        Zeroizing::new(passphrase.to_string())
+    }
+
+    #[test]
+    pub fn test_encode_command_with_local_file_for_data() {
+        let path = testing_file("encode");
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+        let png = fs::file_path_to_png(&path).unwrap();
+        let chunks = png.chunks();
+        assert_eq!(&chunks[1].chunk_type().to_string(), "IDAT");
+        assert_eq!(TEST_DATA, &chunks[2].data_as_string().unwrap());
+        assert_eq!(&chunks[3].chunk_type().to_string(), "IEND");
+    }
 
     #[test]
     pub fn test_decode_command() {
-        assert!(decode(&PathBuf::from(TEST_FILE_PATH), &String::from("RuSt")).is_ok());
+        let path = testing_file("decode");
+        assert!(decode(&path, &of_type("test"), &DecodeOptions::default()).is_ok());
+    }
+
+    #[test]
+    pub fn test_binary_payload() {
+        let path = testing_file("binary");
+        let input = testing_file("binary-input");
+        let output = testing_file("binary-output");
+        let payload: Vec<u8> = (0..=255).collect();
+        std::fs::write(&input, &payload).unwrap();
+
+        let message = read_payload(None, Some(&input), Encoding::Raw).unwrap();
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            &message,
+            &None,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+
+        let options = DecodeOptions {
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        decode(&path, &of_type("RuSt"), &options).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), payload);
+
+        let options = DecodeOptions {
+            output: Some(output.clone()),
+            encoding: Encoding::Base64,
+            ..Default::default()
+        };
+        decode(&path, &of_type("RuSt"), &options).unwrap();
+        let text = std::fs::read(&output).unwrap();
+        assert_eq!(Encoding::Base64.decode(&text).unwrap(), payload);
+    }
+
+    #[test]
+    pub fn test_split_payload_twice() {
+        let path = testing_file("split-twice");
+        let options = EncodeOptions {
+            max_chunk_size: Some(100),
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            encode(
+                &path.to_str(),
+                &None,
+                Some("RuSt"),
+                &[7; 500],
+                &None,
+                &options,
+            )
+            .unwrap();
+        }
+
+        let selector = Selector {
+            all: true,
+            ..of_type("RuSt")
+        };
+        let chunks = decode(&path, &selector, &DecodeOptions::default()).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|chunk| chunk.data() == [7; 500]));
+    }
+
+    #[test]
+    pub fn test_split_payload() {
+        let path = testing_file("split");
+        let payload: Vec<u8> = (0..=255).cycle().take(2000).collect();
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            &payload,
+            &None,
+            &EncodeOptions {
+                max_chunk_size: Some(500),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let png = fs::file_path_to_png(&path).unwrap();
+        let pieces: Vec<_> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == "RuSt")
+            .collect();
+        assert_eq!(pieces.len(), 5);
+        assert!(pieces.iter().all(|chunk| chunk.length() <= 500));
+
+        let chunk = decode(&path, &of_type("RuSt"), &DecodeOptions::default())
+            .unwrap()
+            .remove(0);
+        assert_eq!(chunk.data(), payload);
+
+        // dropping a piece is detected
+        let mut png = png;
+        png.remove_chunk("RuSt").unwrap();
+        fs::rewrite_png_file(&path, &png.as_bytes()).unwrap();
+        assert!(matches!(
+            decode(&path, &of_type("RuSt"), &DecodeOptions::default()),
+            Err(PngError::MissingPiece { sequence: 0, .. })
+        ));
+    }
+
+    #[test]
+    pub fn test_compressed_payload() {
+        let path = testing_file("compressed");
+        let payload = b"compressible payload ".repeat(100);
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            &payload,
+            &None,
+            &EncodeOptions {
+                compression: Some(Compression::Deflate),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let png = fs::file_path_to_png(&path).unwrap();
+        let stored = png.chunk_by_type("RuSt").unwrap();
+        assert!(compression::is_compressed(stored.data()));
+        assert!(stored.data().len() < payload.len());
+
+        let options = DecodeOptions {
+            decompress: true,
+            ..Default::default()
+        };
+        let chunk = decode(&path, &of_type("RuSt"), &options).unwrap().remove(0);
+        assert_eq!(chunk.data(), payload);
+
+        // left as is unless asked
+        let chunk = decode(&path, &of_type("RuSt"), &DecodeOptions::default())
+            .unwrap()
+            .remove(0);
+        assert_eq!(chunk.data(), stored.data());
+
+        let options = DecodeOptions {
+            decompress: true,
+            max_decompressed_size: Some(100),
+            ..Default::default()
+        };
+        assert!(matches!(
+            decode(&path, &of_type("RuSt"), &options),
+            Err(PngError::DecompressionLimitExceeded { limit: 100 })
+        ));
+    }
+
+    #[test]
+    pub fn test_payload_like_compression_header() {
+        let path = testing_file("compression-header");
+        let mut payload = compression::compress(b"", Compression::Deflate).unwrap();
+        payload.truncate(Compression::HEADER_LENGTH);
+        payload.extend_from_slice(b"raw bytes");
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            &payload,
+            &None,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+
+        let chunk = decode(&path, &of_type("RuSt"), &DecodeOptions::default())
+            .unwrap()
+            .remove(0);
+        assert_eq!(chunk.data(), payload);
+    }
+
+    #[test]
+    pub fn test_encodings() {
+        assert_eq!(Encoding::Hex.encode(b"\x00\xff").unwrap(), b"00ff\n");
+        assert_eq!(Encoding::Hex.decode(b"00ff\n").unwrap(), b"\x00\xff");
+        assert_eq!(Encoding::Base64.decode(b"aGk=").unwrap(), b"hi");
+        assert!(Encoding::Hex.decode(b"0g").is_err());
+        assert!(Encoding::Base64.decode(b"not base64!").is_err());
+        assert!(Encoding::Utf8.encode(b"\xff").is_err());
+        assert_eq!(
+            read_payload(Some("aGk="), None, Encoding::Base64).unwrap(),
+            b"hi"
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    pub fn test_encrypted_encode_decode() {
+        let path = testing_file("encrypt");
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions {
+                encryption: Some(Encryption::Passphrase(passphrase("hunter2"))),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let png = fs::file_path_to_png(&path).unwrap();
+        assert_ne!(
+            png.chunk_by_type("RuSt").unwrap().data(),
+            TEST_DATA.as_bytes()
+        );
+
+        let options = DecodeOptions {
+            decryption: Some(Decryption::Passphrase(passphrase("hunter2"))),
+            ..Default::default()
+        };
+        let chunk = decode(&path, &of_type("RuSt"), &options).unwrap().remove(0);
+        assert_eq!(chunk.data(), TEST_DATA.as_bytes());
+
+        let options = DecodeOptions {
+            decryption: Some(Decryption::Passphrase(passphrase("hunter3"))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            decode(&path, &of_type("RuSt"), &options),
+            Err(PngError::DecryptionFailed)
+        ));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    pub fn test_recipients_encode_decode() {
+        let path = testing_file("recipients");
+        let identity_path = testing_file("identity");
+        std::fs::remove_file(&identity_path).unwrap();
+
+        let public_key = keygen(&identity_path).unwrap();
+        // never overwrite an existing key
+        assert!(keygen(&identity_path).is_err());
+
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions {
+                encryption: Some(Encryption::Recipients(vec![public_key])),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let options = DecodeOptions {
+            decryption: Some(Decryption::IdentityFiles(vec![identity_path])),
+            ..Default::default()
+        };
+        let chunk = decode(&path, &of_type("RuSt"), &options).unwrap().remove(0);
+        assert_eq!(chunk.data(), TEST_DATA.as_bytes());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    pub fn test_signed_encode_verify() {
+        let path = testing_file("signed");
+        let key_path = testing_file("signing-key");
+        std::fs::remove_file(&key_path).unwrap();
+        let public_key = keygen_signing(&key_path).unwrap();
+
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions {
+                position: ChunkPosition::AfterIhdr,
+                signing_key: Some(key_path),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let verifications = verify(&path, &[], std::slice::from_ref(&public_key)).unwrap();
+        assert_eq!(verifications[0].covered.len(), 3);
+        assert!(matches!(
+            verify(&path, &[], &[]),
+            Err(PngError::SignatureVerificationFailed)
+        ));
+
+        // a malformed signature chunk doesn't hide the good one
+        let mut png = fs::file_path_to_png(&path).unwrap();
+        png.insert_chunk(
+            Chunk::new(
+                ChunkType::from_str(Signature::CHUNK_TYPE).unwrap(),
+                b"garbage".to_vec(),
+            ),
+            ChunkPosition::AfterIhdr,
+        )
+        .unwrap();
+        fs::rewrite_png_file(&path, &png.as_bytes()).unwrap();
+        let verifications = verify(&path, &[], std::slice::from_ref(&public_key)).unwrap();
+        assert_eq!(verifications.len(), 1);
+
+        // swapping the hidden message breaks the signature
+        remove(&path, &of_type("RuSt"), &WriteOptions::default()).unwrap();
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            b"forged",
+            &None,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+        assert!(verify(&path, &[], &[public_key]).is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    pub fn test_signed_split_payload() {
+        let path = testing_file("signed-split");
+        let key_path = testing_file("signing-key-split");
+        std::fs::remove_file(&key_path).unwrap();
+        let public_key = keygen_signing(&key_path).unwrap();
+
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            &[7; 500],
+            &None,
+            &EncodeOptions {
+                signing_key: Some(key_path),
+                max_chunk_size: Some(100),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let verifications = verify(&path, &[], std::slice::from_ref(&public_key)).unwrap();
+        // IHDR, IDAT and the 10 pieces
+        assert_eq!(verifications[0].covered.len(), 12);
+
+        // swap the data of a later piece, keeping its header
+        let mut png = fs::file_path_to_png(&path).unwrap();
+        let mut data = png.chunks_by_type("RuSt").nth(3).unwrap().data().to_vec();
+        data[Piece::HEADER_LENGTH] ^= 1;
+        let index = png
+            .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().to_string() == "RuSt")
+            .nth(3)
+            .unwrap()
+            .0;
+        png.remove_where(|i, _| i == index);
+        png.insert_chunk(
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), data),
+            ChunkPosition::Index(index),
+        )
+        .unwrap();
+        fs::rewrite_png_file(&path, &png.as_bytes()).unwrap();
+        assert!(matches!(
+            verify(&path, &[], &[public_key]),
+            Err(PngError::SignatureVerificationFailed)
+        ));
+    }
+
+    #[test]
+    pub fn test_info_command() {
+        assert!(info(Path::new(TEST_FILE_PATH)).is_ok());
+    }
+
+    #[test]
+    pub fn test_text_commands() {
+        let path = testing_file("text");
+        let entry = TextEntry::new("Comment", "hello", pngme::TextKind::CompressedText).unwrap();
+        text_add(&path, &entry, &WriteOptions::default()).unwrap();
+        assert_eq!(text_get(&path, "Comment").unwrap(), entry);
+
+        let updated = TextEntry::new("Comment", "bonjour", pngme::TextKind::Text).unwrap();
+        text_set(&path, &updated, &WriteOptions::default()).unwrap();
+        assert_eq!(text_list(&path).unwrap(), [updated]);
+
+        text_remove(&path, "Comment", &WriteOptions::default()).unwrap();
+        assert!(text_get(&path, "Comment").is_err());
+    }
+
+    #[test]
+    pub fn test_lint_command() {
+        // the test asset has a chunk after IEND, with an invalid reserved bit
+        let findings = lint(Path::new(TEST_FILE_PATH), false);
+        assert!(matches!(
+            findings,
+            Err(PngError::ValidationFailed { errors: 2, .. })
+        ));
+
+        let path = testing_file("lint");
+        remove(&path, &of_type("test"), &WriteOptions::default()).unwrap();
+        assert!(lint(&path, true).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_analyze_command() {
+        let path = testing_file("analyze");
+        let analysis = analyze(&path, None, Image::DEFAULT_LIMIT).unwrap();
+        assert!(analysis
+            .indicators
+            .iter()
+            .any(|indicator| indicator.kind == analyze::IndicatorKind::AfterIend));
+        assert!(matches!(
+            analyze(&path, Some(analysis.score), Image::DEFAULT_LIMIT),
+            Err(PngError::Suspicious { .. })
+        ));
+
+        remove(&path, &of_type("test"), &WriteOptions::default()).unwrap();
+        assert_eq!(
+            analyze(&path, Some(1), Image::DEFAULT_LIMIT).unwrap().score,
+            0
+        );
     }
 
     #[test]
     pub fn test_remove_command() {
-        assert!(remove(&PathBuf::from(TEST_FILE_PATH), &String::from("RuSt")).is_ok());
+        let path = testing_file("remove");
+        assert!(remove(&path, &of_type("test"), &WriteOptions::default()).is_ok());
+        assert!(decode(&path, &of_type("test"), &DecodeOptions::default()).is_err());
+    }
+
+    #[test]
+    pub fn test_selectors() {
+        let path = testing_file("selectors");
+        for message in ["first", "second", "third"] {
+            encode(
+                &path.to_str(),
+                &None,
+                Some("RuSt"),
+                message.as_bytes(),
+                &None,
+                &EncodeOptions::default(),
+            )
+            .unwrap();
+        }
+        // IHDR, IDAT, RuSt x3, IEND, test
+        let all = Selector {
+            all: true,
+            ..of_type("RuSt")
+        };
+        let chunks = decode(&path, &all, &DecodeOptions::default()).unwrap();
+        let messages: Vec<_> = chunks.iter().map(|chunk| chunk.data()).collect();
+        assert_eq!(messages, [&b"first"[..], b"second", b"third"]);
+
+        let second = Selector {
+            index: Some(3),
+            ..Default::default()
+        };
+        let chunks = decode(&path, &second, &DecodeOptions::default()).unwrap();
+        assert_eq!(chunks[0].data(), b"second");
+
+        let unknown = Selector {
+            unknown: true,
+            private: true,
+            all: true,
+            ..Default::default()
+        };
+        let chunks = decode(&path, &unknown, &DecodeOptions::default()).unwrap();
+        assert_eq!(chunks.len(), 4);
+        let ancillary = Selector {
+            ancillary: true,
+            ..unknown
+        };
+        let chunks = decode(&path, &ancillary, &DecodeOptions::default()).unwrap();
+        assert_eq!(chunks[0].chunk_type.to_string(), "test");
+
+        let regex = Selector {
+            type_regex: Some(Regex::new("^[A-Z]{4}$").unwrap()),
+            all: true,
+            ..Default::default()
+        };
+        let removed = remove(&path, &regex, &WriteOptions::default()).unwrap();
+        assert_eq!(removed.len(), 3);
+
+        remove(&path, &all, &WriteOptions::default()).unwrap();
+        assert!(matches!(
+            decode(&path, &all, &DecodeOptions::default()),
+            Err(PngError::ChunkNotFound)
+        ));
+        let png = fs::file_path_to_png(&path).unwrap();
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    pub fn test_strip_command() {
+        let path = testing_file("strip");
+        let output = testing_file("strip-output");
+        let entry = TextEntry::new("Copyright", "me", pngme::TextKind::Text).unwrap();
+        text_add(&path, &entry, &WriteOptions::default()).unwrap();
+
+        let policy = StripPolicy {
+            keep_text: vec!["Copyright".to_string()],
+            ..Default::default()
+        };
+        let removed = strip(&path, Some(&output), &policy, &WriteOptions::default()).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "test");
+
+        let before = std::fs::metadata(&path).unwrap().len();
+        let after = std::fs::metadata(&output).unwrap().len();
+        assert_eq!(before - after, 12 + removed[0].length() as u64);
+        assert!(text_get(&output, "Copyright").is_ok());
+    }
+
+    #[test]
+    pub fn test_lsb_method() {
+        let path = testing_file("lsb");
+        let method = Method::Lsb(LsbOptions {
+            bits: 2,
+            key: Some(Zeroizing::new(b"secret".to_vec())),
+            ..Default::default()
+        });
+        encode(
+            &path.to_str(),
+            &None,
+            None,
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions {
+                method: method.clone(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(fs::file_path_to_png(&path)
+            .unwrap()
+            .chunk_by_type("RuSt")
+            .is_none());
+
+        // the message is in the pixels, stripping every chunk leaves it there
+        strip(
+            &path,
+            None,
+            &StripPolicy::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        let options = DecodeOptions {
+            method,
+            ..Default::default()
+        };
+        let chunk = decode(&path, &Selector::default(), &options)
+            .unwrap()
+            .remove(0);
+        assert_eq!(chunk.data(), TEST_DATA.as_bytes());
+        assert_eq!(chunk.chunk_type.to_string(), lsb::CHUNK_TYPE);
+
+        let options = DecodeOptions {
+            method: Method::Lsb(LsbOptions::default()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            decode(&path, &Selector::default(), &options),
+            Err(PngError::HiddenPayloadNotFound)
+        ));
+    }
+
+    #[test]
+    pub fn test_chunk_method_needs_chunk_type() {
+        let path = testing_file("no-chunk-type");
+        assert!(matches!(
+            encode(
+                &path.to_str(),
+                &None,
+                None,
+                TEST_DATA.as_bytes(),
+                &None,
+                &EncodeOptions::default()
+            ),
+            Err(PngError::ChunkTypeNotSpecified)
+        ));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    pub fn test_lsb_method_encrypted() {
+        let path = testing_file("lsb-encrypted");
+        let method = Method::Lsb(LsbOptions::default());
+        encode(
+            &path.to_str(),
+            &None,
+            None,
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions {
+                method: method.clone(),
+                encryption: Some(Encryption::Passphrase(passphrase("hunter2"))),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let options = DecodeOptions {
+            method,
+            decryption: Some(Decryption::Passphrase(passphrase("hunter2"))),
+            ..Default::default()
+        };
+        let payload = decode(&path, &Selector::default(), &options)
+            .unwrap()
+            .remove(0);
+        assert_eq!(payload.data(), TEST_DATA.as_bytes());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    pub fn test_capacity_command() {
+        let path = testing_file("capacity");
+        let processing = Processing {
+            encryption: Some(capacity::EncryptionKind::Passphrase),
+            ..Default::default()
+        };
+        let report = capacity(&path, Channels::COLOR, &processing, Image::DEFAULT_LIMIT).unwrap();
+        let room = match report.lsb[0].1 {
+            capacity::Room::Bytes(room) => room,
+            _ => panic!("res.png is rgba"),
+        };
+
+        // the whole room is usable, not a byte more
+        let method = Method::Lsb(LsbOptions::default());
+        let encode_options = |method: &Method| EncodeOptions {
+            method: method.clone(),
+            encryption: Some(Encryption::Passphrase(passphrase("hunter2"))),
+            ..Default::default()
+        };
+        let payload = vec![b'x'; room + 1];
+        assert!(matches!(
+            encode(
+                &path.to_str(),
+                &None,
+                None,
+                &payload,
+                &None,
+                &encode_options(&method)
+            ),
+            Err(PngError::PayloadTooLarge { .. })
+        ));
+        encode(
+            &path.to_str(),
+            &None,
+            None,
+            &payload[..room],
+            &None,
+            &encode_options(&method),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    pub fn test_reencode_command() {
+        let path = testing_file("reencode");
+        let output = std::env::temp_dir().join("pngme-commands-reencode-output.png");
+        let image = fs::file_path_to_png(&path).unwrap().image().unwrap();
+
+        let options = IdatOptions {
+            chunk_size: 8192,
+            ..Default::default()
+        };
+        let (_, after) = reencode(
+            &path,
+            Some(&output),
+            &options,
+            Image::DEFAULT_LIMIT,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        let png = fs::file_path_to_png(&output).unwrap();
+        assert_eq!(png.image().unwrap(), image);
+        assert_eq!(
+            png.chunks_by_type("IDAT").count() as u64,
+            after.div_ceil(8192)
+        );
+        // the hidden chunk after IEND is still there
+        assert!(png.chunk_by_type("test").is_some());
+    }
+
+    #[test]
+    pub fn test_repair_command() {
+        let path = testing_file("repair");
+        let mut bytes = std::fs::read(&path).unwrap();
+        // damage the crc of IHDR and add garbage after IEND
+        bytes[29] ^= 0xff;
+        bytes.extend_from_slice(b"garbage");
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(print(&path, &Selector::default(), &PrintOptions::default()).is_err());
+        // structured formats show the wrong crc instead, the garbage still
+        // needs a lenient parse mode
+        let options = PrintOptions {
+            format: OutputFormat::Json,
+            parse_mode: ParseMode::Resync,
+            ..Default::default()
+        };
+        let selector = Selector {
+            chunk_types: vec!["IHDR".to_string(), "IEND".to_string()],
+            ..Default::default()
+        };
+        assert!(print(&path, &selector, &options).is_ok());
+
+        let fixes = repair(&path, None, true, &WriteOptions::default()).unwrap();
+        assert_eq!(fixes.len(), 2);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        repair(&path, None, false, &WriteOptions::default()).unwrap();
+        assert!(print(&path, &Selector::default(), &PrintOptions::default()).is_ok());
+        assert!(repair(&path, None, false, &WriteOptions::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_backup_and_preserve_mtime() {
+        let path = testing_file("backup");
+        let backup_path = path.with_extension("png.bak");
+        let _ = std::fs::remove_file(&backup_path);
+        let before = std::fs::read(&path).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let write = WriteOptions {
+            backup: true,
+            preserve_mtime: true,
+        };
+        remove(&path, &of_type("test"), &write).unwrap();
+        assert_eq!(std::fs::read(&backup_path).unwrap(), before);
+        assert_ne!(std::fs::read(&path).unwrap(), before);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+
+        // a failed change leaves the file, its backup and no temporary file
+        assert!(remove(&path, &of_type("test"), &write).is_err());
+        assert_eq!(std::fs::read(&backup_path).unwrap(), before);
+        let temp_files = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(".pngme-commands-backup.png.") && name.ends_with(".pngme.tmp")
+            })
+            .count();
+        assert_eq!(temp_files, 0);
+    }
+
+    #[test]
+    pub fn test_lenient_decode() {
+        let path = testing_file("lenient");
+        encode(
+            &path.to_str(),
+            &None,
+            Some("RuSt"),
+            TEST_DATA.as_bytes(),
+            &None,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        // damage the crc of IHDR, before the hidden chunk
+        bytes[29] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(decode(&path, &of_type("RuSt"), &DecodeOptions::default()).is_err());
+
+        for parse_mode in [ParseMode::SkipBadCrc, ParseMode::Resync] {
+            let options = DecodeOptions {
+                parse_mode,
+                ..Default::default()
+            };
+            let chunks = decode(&path, &of_type("RuSt"), &options).unwrap();
+            assert_eq!(chunks[0].data(), TEST_DATA.as_bytes());
+        }
     }
 }
@@ -0,0 +1,253 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::{PngError, Result};
+
+/// Algorithm used to compress a payload before embedding it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    /// zlib stream, always available.
+    Deflate,
+    /// Needs the `zstd` feature.
+    Zstd,
+    /// Needs the `brotli` feature.
+    Brotli,
+}
+
+impl FromStr for Compression {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deflate" => Ok(Compression::Deflate),
+            "zstd" => Ok(Compression::Zstd),
+            "brotli" => Ok(Compression::Brotli),
+            _ => Err(PngError::InvalidCompressedPayload),
+        }
+    }
+}
+
+impl Compression {
+    /// First bytes of a payload produced by [`compress`].
+    pub const MAGIC: [u8; 4] = *b"PMCZ";
+    const VERSION: u8 = 1;
+    /// magic, version, algorithm and decompressed length
+    pub const HEADER_LENGTH: usize = 4 + 1 + 1 + 8;
+
+    /// Payloads are not decompressed past this size unless asked otherwise.
+    pub const DEFAULT_LIMIT: u64 = 256 * 1024 * 1024;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Deflate => "deflate",
+            Compression::Zstd => "zstd",
+            Compression::Brotli => "brotli",
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Compression::Deflate => 1,
+            Compression::Zstd => 2,
+            Compression::Brotli => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Compression> {
+        match id {
+            1 => Ok(Compression::Deflate),
+            2 => Ok(Compression::Zstd),
+            3 => Ok(Compression::Brotli),
+            _ => Err(PngError::InvalidCompressedPayload),
+        }
+    }
+}
+
+/// Whether the chunk data starts with a compression header.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.len() >= Compression::HEADER_LENGTH
+        && data[0..4] == Compression::MAGIC
+        && data[4] == Compression::VERSION
+}
+
+/// Compresses `payload` behind a header recording the algorithm and the
+/// decompressed length.
+pub fn compress(payload: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(Compression::HEADER_LENGTH + payload.len() / 2);
+    data.extend_from_slice(&Compression::MAGIC);
+    data.push(Compression::VERSION);
+    data.push(compression.id());
+    data.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+
+    match compression {
+        Compression::Deflate => {
+            let mut encoder = ZlibEncoder::new(data, flate2::Compression::best());
+            encoder.write_all(payload)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Zstd => compress_zstd(payload, data),
+        Compression::Brotli => compress_brotli(payload, data),
+    }
+}
+
+/// Decompresses a payload produced by [`compress`]. Fails with
+/// [`PngError::DecompressionLimitExceeded`] rather than writing more than
+/// `limit` bytes, whatever the header claims.
+pub fn decompress(data: &[u8], limit: u64) -> Result<Vec<u8>> {
+    if !is_compressed(data) {
+        return Err(PngError::InvalidCompressedPayload);
+    }
+    let compression = Compression::from_id(data[5])?;
+    let length = u64::from_be_bytes(data[6..14].try_into().expect("8 bytes"));
+    if length > limit {
+        return Err(PngError::DecompressionLimitExceeded { limit });
+    }
+
+    let compressed = &data[Compression::HEADER_LENGTH..];
+    let reader: Box<dyn Read + '_> = match compression {
+        Compression::Deflate => Box::new(ZlibDecoder::new(compressed)),
+        Compression::Zstd => zstd_reader(compressed)?,
+        Compression::Brotli => brotli_reader(compressed)?,
+    };
+
+    // the length comes from the file, the buffer only grows with what the
+    // stream really holds
+    let mut payload = Vec::new();
+    reader
+        .take(limit + 1)
+        .read_to_end(&mut payload)
+        .map_err(|_| PngError::InvalidCompressedPayload)?;
+
+    if payload.len() as u64 > limit {
+        return Err(PngError::DecompressionLimitExceeded { limit });
+    }
+    if payload.len() as u64 != length {
+        return Err(PngError::InvalidCompressedPayload);
+    }
+    Ok(payload)
+}
+
+#[cfg(feature = "zstd")]
+fn compress_zstd(payload: &[u8], data: Vec<u8>) -> Result<Vec<u8>> {
+    let mut encoder = zstd::Encoder::new(data, 19)?;
+    encoder.write_all(payload)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress_zstd(_payload: &[u8], _data: Vec<u8>) -> Result<Vec<u8>> {
+    Err(PngError::CompressionDisabled("zstd"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_reader(compressed: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Ok(Box::new(zstd::Decoder::new(compressed)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_reader(_compressed: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Err(PngError::CompressionDisabled("zstd"))
+}
+
+#[cfg(feature = "brotli")]
+fn compress_brotli(payload: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>> {
+    {
+        let mut encoder = brotli::CompressorWriter::new(&mut data, 4096, 11, 22);
+        encoder.write_all(payload)?;
+    }
+    Ok(data)
+}
+
+#[cfg(not(feature = "brotli"))]
+fn compress_brotli(_payload: &[u8], _data: Vec<u8>) -> Result<Vec<u8>> {
+    Err(PngError::CompressionDisabled("brotli"))
+}
+
+#[cfg(feature = "brotli")]
+fn brotli_reader(compressed: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Ok(Box::new(brotli::Decompressor::new(compressed, 4096)))
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli_reader(_compressed: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Err(PngError::CompressionDisabled("brotli"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn algorithms() -> Vec<Compression> {
+        let mut algorithms = vec![Compression::Deflate];
+        if cfg!(feature = "zstd") {
+            algorithms.push(Compression::Zstd);
+        }
+        if cfg!(feature = "brotli") {
+            algorithms.push(Compression::Brotli);
+        }
+        algorithms
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = b"2024-01-01 INFO started\n".repeat(200);
+        for compression in algorithms() {
+            let data = compress(&payload, compression).unwrap();
+
+            assert!(is_compressed(&data));
+            assert!(data.len() < payload.len() / 10, "{}", compression.name());
+            assert_eq!(
+                decompress(&data, Compression::DEFAULT_LIMIT).unwrap(),
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn test_bomb() {
+        let payload = vec![0; 1024 * 1024];
+        for compression in algorithms() {
+            let data = compress(&payload, compression).unwrap();
+            assert!(matches!(
+                decompress(&data, 1024),
+                Err(PngError::DecompressionLimitExceeded { limit: 1024 })
+            ));
+
+            // a header lying about the length does not get past the limit
+            let mut lying = data.clone();
+            lying[6..14].copy_from_slice(&10_u64.to_be_bytes());
+            assert!(matches!(
+                decompress(&lying, 1024),
+                Err(PngError::DecompressionLimitExceeded { limit: 1024 })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        assert!(!is_compressed(b"plain"));
+        assert!(decompress(b"plain", 1024).is_err());
+
+        let mut data = compress(b"payload", Compression::Deflate).unwrap();
+        data[5] = 9;
+        assert!(decompress(&data, 1024).is_err());
+
+        let mut data = compress(b"payload", Compression::Deflate).unwrap();
+        data.truncate(data.len() - 2);
+        assert!(decompress(&data, 1024).is_err());
+
+        // a bare header claiming the largest length allowed
+        let mut data = compress(b"", Compression::Deflate).unwrap();
+        data[6..14].copy_from_slice(&Compression::DEFAULT_LIMIT.to_be_bytes());
+        assert!(matches!(
+            decompress(
+                &data[..Compression::HEADER_LENGTH],
+                Compression::DEFAULT_LIMIT
+            ),
+            Err(PngError::InvalidCompressedPayload)
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{PngError, Result};
+
+/// How pixels are stored, as found in [`Ihdr`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(invalid_data("IHDR", "unknown color type")),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "rgb",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale + alpha",
+            ColorType::Rgba => "rgb + alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ColorType {
+    /// Value stored in the `IHDR` chunk.
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples per pixel.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths allowed by the specification for this color type.
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+/// The image header, always the first chunk of a png file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression: u8,
+    pub filter: u8,
+    pub interlace: u8,
+}
+
+impl Ihdr {
+    pub const CHUNK_TYPE: &'static str = "IHDR";
+    pub const LENGTH: usize = 13;
+
+    /// Number of bits used by one pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace == 1
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(Ihdr::LENGTH);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type.value(),
+            self.compression,
+            self.filter,
+            self.interlace,
+        ]);
+        Chunk::new(chunk_type(Ihdr::CHUNK_TYPE), data)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        check_chunk_type(chunk, Ihdr::CHUNK_TYPE)?;
+        let data = chunk.data();
+        if data.len() != Ihdr::LENGTH {
+            return Err(invalid_data(Ihdr::CHUNK_TYPE, "length must be 13 bytes"));
+        }
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: ColorType::try_from(data[9])?,
+            compression: data[10],
+            filter: data[11],
+            interlace: data[12],
+        };
+
+        if ihdr.width == 0
+            || ihdr.height == 0
+            || ihdr.width > i32::MAX as u32
+            || ihdr.height > i32::MAX as u32
+        {
+            return Err(invalid_data(
+                Ihdr::CHUNK_TYPE,
+                "dimensions must be between 1 and 2^31-1",
+            ));
+        }
+        if !ihdr
+            .color_type
+            .allowed_bit_depths()
+            .contains(&ihdr.bit_depth)
+        {
+            return Err(invalid_data(
+                Ihdr::CHUNK_TYPE,
+                "bit depth not allowed for this color type",
+            ));
+        }
+        if ihdr.compression != 0 || ihdr.filter != 0 {
+            return Err(invalid_data(
+                Ihdr::CHUNK_TYPE,
+                "unknown compression or filter method",
+            ));
+        }
+        if ihdr.interlace > 1 {
+            return Err(invalid_data(Ihdr::CHUNK_TYPE, "unknown interlace method"));
+        }
+
+        Ok(ihdr)
+    }
+}
+
+/// One palette entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// The palette of an indexed image, 1 to 256 entries.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Plte(pub Vec<Rgb>);
+
+impl Plte {
+    pub const CHUNK_TYPE: &'static str = "PLTE";
+    pub const MAX_ENTRIES: usize = 256;
+
+    pub fn entries(&self) -> &[Rgb] {
+        &self.0
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = self
+            .0
+            .iter()
+            .flat_map(|rgb| [rgb.r, rgb.g, rgb.b])
+            .collect();
+        Chunk::new(chunk_type(Plte::CHUNK_TYPE), data)
+    }
+}
+
+impl TryFrom<&Chunk> for Plte {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        check_chunk_type(chunk, Plte::CHUNK_TYPE)?;
+        let data = chunk.data();
+        if data.is_empty() || !data.len().is_multiple_of(3) || data.len() / 3 > Plte::MAX_ENTRIES {
+            return Err(invalid_data(
+                Plte::CHUNK_TYPE,
+                "length must be a multiple of 3 with 1 to 256 entries",
+            ));
+        }
+
+        let entries = data
+            .chunks_exact(3)
+            .map(|rgb| Rgb {
+                r: rgb[0],
+                g: rgb[1],
+                b: rgb[2],
+            })
+            .collect();
+        Ok(Plte(entries))
+    }
+}
+
+/// The image trailer, always the last chunk of a png file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Iend;
+
+impl Iend {
+    pub const CHUNK_TYPE: &'static str = "IEND";
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(chunk_type(Iend::CHUNK_TYPE), Vec::new())
+    }
+}
+
+impl TryFrom<&Chunk> for Iend {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        check_chunk_type(chunk, Iend::CHUNK_TYPE)?;
+        if !chunk.data().is_empty() {
+            return Err(invalid_data(Iend::CHUNK_TYPE, "must be empty"));
+        }
+        Ok(Iend)
+    }
+}
+
+fn chunk_type(chunk_type: &str) -> ChunkType {
+    // only called with the valid constants above
+    ChunkType::from_str(chunk_type).expect("valid chunk type")
+}
+
+fn check_chunk_type(chunk: &Chunk, expected: &'static str) -> Result<()> {
+    if chunk.chunk_type().to_string() != expected {
+        return Err(invalid_data(expected, "wrong chunk type"));
+    }
+    Ok(())
+}
+
+fn invalid_data(chunk_type: &'static str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_ihdr() -> Ihdr {
+        Ihdr {
+            width: 50,
+            height: 40,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            compression: 0,
+            filter: 0,
+            interlace: 0,
+        }
+    }
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let ihdr = testing_ihdr();
+        let chunk = ihdr.to_chunk();
+
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), ihdr);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        let mut ihdr = testing_ihdr();
+        ihdr.bit_depth = 4;
+        assert!(Ihdr::try_from(&ihdr.to_chunk()).is_err());
+
+        let mut ihdr = testing_ihdr();
+        ihdr.width = 0;
+        assert!(Ihdr::try_from(&ihdr.to_chunk()).is_err());
+
+        let mut ihdr = testing_ihdr();
+        ihdr.interlace = 2;
+        assert!(Ihdr::try_from(&ihdr.to_chunk()).is_err());
+
+        let short = Chunk::new(chunk_type("IHDR"), vec![0; 12]);
+        assert!(Ihdr::try_from(&short).is_err());
+
+        let color_type = Chunk::new(chunk_type("IHDR"), {
+            let mut data = testing_ihdr().to_chunk().data().to_vec();
+            data[9] = 5;
+            data
+        });
+        assert!(Ihdr::try_from(&color_type).is_err());
+    }
+
+    #[test]
+    fn test_plte_round_trip() {
+        let plte = Plte(vec![Rgb { r: 1, g: 2, b: 3 }, Rgb { r: 4, g: 5, b: 6 }]);
+        let chunk = plte.to_chunk();
+
+        assert_eq!(chunk.data(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(Plte::try_from(&chunk).unwrap(), plte);
+    }
+
+    #[test]
+    fn test_invalid_plte() {
+        assert!(Plte::try_from(&Chunk::new(chunk_type("PLTE"), vec![1, 2])).is_err());
+        assert!(Plte::try_from(&Chunk::new(chunk_type("PLTE"), vec![])).is_err());
+        assert!(Plte::try_from(&Chunk::new(chunk_type("PLTE"), vec![0; 257 * 3])).is_err());
+    }
+
+    #[test]
+    fn test_iend() {
+        assert_eq!(Iend::try_from(&Iend.to_chunk()).unwrap(), Iend);
+        assert!(Iend::try_from(&Chunk::new(chunk_type("IEND"), vec![0])).is_err());
+        assert!(Iend::try_from(&testing_ihdr().to_chunk()).is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use age::secrecy::ExposeSecret;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+use crate::chunk_type::ChunkType;
+use crate::{PngError, Result};
+
+pub use age::x25519::{Identity, Recipient};
+
+/// Cost parameters of the Argon2id key derivation, stored in every payload.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KdfParams {
+    /// Memory size in KiB.
+    pub memory_cost: u32,
+    /// Number of iterations.
+    pub time_cost: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// The OWASP recommended minimum for Argon2id: 19 MiB, 2 iterations.
+    fn default() -> Self {
+        KdfParams {
+            memory_cost: 19 * 1024,
+            time_cost: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    // upper bounds accepted when decrypting, so a crafted file can make us
+    // allocate at most 256 MiB, over at most 64 passes
+    const MAX_MEMORY_COST: u32 = 256 * 1024;
+    const MAX_TIME_COST: u32 = 64;
+    const MAX_PARALLELISM: u32 = 16;
+
+    fn argon2(&self) -> Result<Argon2<'static>> {
+        if self.memory_cost > Self::MAX_MEMORY_COST
+            || self.time_cost > Self::MAX_TIME_COST
+            || self.parallelism > Self::MAX_PARALLELISM
+        {
+            return Err(PngError::InvalidEncryptedPayload);
+        }
+        let params = Params::new(
+            self.memory_cost,
+            self.time_cost,
+            self.parallelism,
+            Some(KEY_LENGTH),
+        )
+        .map_err(|_| PngError::InvalidEncryptedPayload)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// First bytes of a passphrase encrypted payload.
+pub const MAGIC: [u8; 4] = *b"PMPW";
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+/// magic, version, 3 kdf params, salt and nonce
+const HEADER_LENGTH: usize = 4 + 1 + 12 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Whether the chunk data looks like a payload produced by [`encrypt`].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= HEADER_LENGTH && data[0..4] == MAGIC
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase` by Argon2id,
+/// using XChaCha20-Poly1305. The chunk type and the header are authenticated,
+/// so the payload can't be moved to another chunk type unnoticed.
+///
+/// Layout: magic, version, memory cost, time cost, parallelism (u32 big
+/// endian), salt, nonce, then the ciphertext and its tag.
+pub fn encrypt(passphrase: &[u8], chunk_type: &ChunkType, plaintext: &[u8]) -> Result<Vec<u8>> {
+    encrypt_with(passphrase, chunk_type, plaintext, KdfParams::default())
+}
+
+/// Same as [`encrypt`], with custom key derivation costs.
+pub fn encrypt_with(
+    passphrase: &[u8],
+    chunk_type: &ChunkType,
+    plaintext: &[u8],
+    params: KdfParams,
+) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut data = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + 16);
+    data.extend_from_slice(&MAGIC);
+    data.push(VERSION);
+    data.extend_from_slice(&params.memory_cost.to_be_bytes());
+    data.extend_from_slice(&params.time_cost.to_be_bytes());
+    data.extend_from_slice(&params.parallelism.to_be_bytes());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let cipher = cipher(passphrase, &salt, params)?;
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &associated_data(chunk_type, &data),
+            },
+        )
+        .map_err(|_| PngError::InvalidEncryptedPayload)?;
+
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+/// Decrypts a payload produced by [`encrypt`]. Fails with
+/// [`PngError::DecryptionFailed`] on a wrong passphrase, a different chunk
+/// type or altered data.
+pub fn decrypt(
+    passphrase: &[u8],
+    chunk_type: &ChunkType,
+    data: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    if !is_encrypted(data) || data[4] != VERSION {
+        return Err(PngError::InvalidEncryptedPayload);
+    }
+
+    let u32_at = |offset: usize| {
+        u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+    let params = KdfParams {
+        memory_cost: u32_at(5),
+        time_cost: u32_at(9),
+        parallelism: u32_at(13),
+    };
+    let salt = &data[17..17 + SALT_LENGTH];
+    let nonce = XNonce::from_slice(&data[17 + SALT_LENGTH..HEADER_LENGTH]);
+
+    let cipher = cipher(passphrase, salt, params)?;
+    let plaintext = cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: &data[HEADER_LENGTH..],
+                aad: &associated_data(chunk_type, &data[..HEADER_LENGTH]),
+            },
+        )
+        .map_err(|_| PngError::DecryptionFailed)?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+fn cipher(passphrase: &[u8], salt: &[u8], params: KdfParams) -> Result<XChaCha20Poly1305> {
+    let mut key = Zeroizing::new([0; KEY_LENGTH]);
+    params
+        .argon2()?
+        .hash_password_into(passphrase, salt, key.as_mut())
+        .map_err(|_| PngError::InvalidEncryptedPayload)?;
+    XChaCha20Poly1305::new_from_slice(key.as_ref()).map_err(|_| PngError::InvalidEncryptedPayload)
+}
+
+fn associated_data(chunk_type: &ChunkType, header: &[u8]) -> Vec<u8> {
+    chunk_type.bytes().iter().chain(header).copied().collect()
+}
+
+/// First line of an age encrypted file.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+/// Whether the chunk data looks like a payload produced by
+/// [`encrypt_to_recipients`].
+pub fn is_age_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_MAGIC)
+}
+
+/// Encrypts `plaintext` to X25519 recipients: any of them can decrypt it with
+/// their identity. The chunk data is a binary age file, so `age --decrypt`
+/// reads it too. Unlike [`encrypt`] the chunk type is not authenticated, the
+/// age format has no room for associated data.
+pub fn encrypt_to_recipients(recipients: &[Recipient], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| recipient as &dyn age::Recipient);
+    let encryptor =
+        age::Encryptor::with_recipients(recipients).map_err(|_| PngError::InvalidRecipient)?;
+
+    let mut data = Vec::with_capacity(plaintext.len());
+    let mut writer = encryptor.wrap_output(&mut data)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(data)
+}
+
+/// Decrypts a payload produced by [`encrypt_to_recipients`] (or by `age`)
+/// with the first identity that matches one of its recipients.
+pub fn decrypt_with_identities(identities: &[Identity], data: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let decryptor = age::Decryptor::new_buffered(data).map_err(decrypt_error)?;
+    let identities = identities
+        .iter()
+        .map(|identity| identity as &dyn age::Identity);
+    let mut reader = decryptor.decrypt(identities).map_err(decrypt_error)?;
+
+    let mut plaintext = Zeroizing::new(Vec::new());
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(|_| PngError::DecryptionFailed)?;
+    Ok(plaintext)
+}
+
+/// Parses an `age1...` public key.
+pub fn parse_recipient(recipient: &str) -> Result<Recipient> {
+    Recipient::from_str(recipient.trim()).map_err(|_| PngError::InvalidRecipient)
+}
+
+/// Parses the identities of an age identity file: one `AGE-SECRET-KEY-1...`
+/// per line, blank lines and `#` comments are ignored.
+pub fn parse_identities(contents: &str) -> Result<Vec<Identity>> {
+    let identities = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Identity::from_str(line).map_err(|_| PngError::InvalidIdentity))
+        .collect::<Result<Vec<_>>>()?;
+
+    if identities.is_empty() {
+        return Err(PngError::InvalidIdentity);
+    }
+    Ok(identities)
+}
+
+/// Contents of an identity file for `identity`, in the format written by
+/// `age-keygen`.
+pub fn identity_file(identity: &Identity) -> Zeroizing<String> {
+    Zeroizing::new(format!(
+        "# public key: {}\n{}\n",
+        identity.to_public(),
+        identity.to_string().expose_secret()
+    ))
+}
+
+fn decrypt_error(error: age::DecryptError) -> PngError {
+    match error {
+        age::DecryptError::DecryptionFailed
+        | age::DecryptError::KeyDecryptionFailed
+        | age::DecryptError::InvalidMac
+        | age::DecryptError::NoMatchingKeys => PngError::DecryptionFailed,
+        age::DecryptError::Io(error) => error.into(),
+        _ => PngError::InvalidEncryptedPayload,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap parameters, the defaults are slow in debug builds
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_cost: 64,
+        time_cost: 1,
+        parallelism: 1,
+    };
+
+    fn chunk_type(s: &str) -> ChunkType {
+        ChunkType::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = encrypt_with(b"hunter2", &chunk_type("RuSt"), b"secret", TEST_PARAMS).unwrap();
+
+        assert!(is_encrypted(&data));
+        assert!(!data.windows(6).any(|window| window == b"secret"));
+        let plaintext = decrypt(b"hunter2", &chunk_type("RuSt"), &data).unwrap();
+        assert_eq!(plaintext.as_slice(), b"secret");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let data = encrypt_with(b"hunter2", &chunk_type("RuSt"), b"secret", TEST_PARAMS).unwrap();
+        assert!(matches!(
+            decrypt(b"hunter3", &chunk_type("RuSt"), &data),
+            Err(PngError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_bound_to_chunk_type() {
+        let data = encrypt_with(b"hunter2", &chunk_type("RuSt"), b"secret", TEST_PARAMS).unwrap();
+        assert!(matches!(
+            decrypt(b"hunter2", &chunk_type("RuSz"), &data),
+            Err(PngError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_altered_payload() {
+        let mut data =
+            encrypt_with(b"hunter2", &chunk_type("RuSt"), b"secret", TEST_PARAMS).unwrap();
+        // changing the kdf cost must not go unnoticed either
+        data[8] ^= 1;
+        assert!(decrypt(b"hunter2", &chunk_type("RuSt"), &data).is_err());
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        assert!(matches!(
+            decrypt(b"hunter2", &chunk_type("RuSt"), b"plain message"),
+            Err(PngError::InvalidEncryptedPayload)
+        ));
+
+        let mut data =
+            encrypt_with(b"hunter2", &chunk_type("RuSt"), b"secret", TEST_PARAMS).unwrap();
+        data[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decrypt(b"hunter2", &chunk_type("RuSt"), &data),
+            Err(PngError::InvalidEncryptedPayload)
+        ));
+        // 1 GiB is over the limit
+        data[5..9].copy_from_slice(&(1024 * 1024_u32).to_be_bytes());
+        assert!(matches!(
+            decrypt(b"hunter2", &chunk_type("RuSt"), &data),
+            Err(PngError::InvalidEncryptedPayload)
+        ));
+    }
+
+    #[test]
+    fn test_recipients_round_trip() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+
+        let data = encrypt_to_recipients(&[alice.to_public(), bob.to_public()], b"secret").unwrap();
+        assert!(is_age_encrypted(&data));
+
+        for identity in [alice, bob] {
+            let plaintext = decrypt_with_identities(&[identity], &data).unwrap();
+            assert_eq!(plaintext.as_slice(), b"secret");
+        }
+        assert!(matches!(
+            decrypt_with_identities(&[eve], &data),
+            Err(PngError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_no_recipient() {
+        assert!(matches!(
+            encrypt_to_recipients(&[], b"secret"),
+            Err(PngError::InvalidRecipient)
+        ));
+    }
+
+    #[test]
+    fn test_identity_file() {
+        let identity = Identity::generate();
+        let contents = identity_file(&identity);
+        let public_key = identity.to_public().to_string();
+
+        assert!(contents.contains(&public_key));
+        let parsed = parse_identities(&contents).unwrap();
+        assert_eq!(parsed[0].to_public().to_string(), public_key);
+        assert_eq!(
+            parse_recipient(&public_key).unwrap().to_string(),
+            public_key
+        );
+
+        assert!(parse_identities("# only a comment\n").is_err());
+        assert!(parse_identities("AGE-SECRET-KEY-1NOTAKEY").is_err());
+        assert!(parse_recipient("age1notakey").is_err());
+    }
+}
@@ -1,14 +1,31 @@
+use std::io::Read;
+
 use crate::png::Png;
 use crate::{PngError, Result};
 
+/// Largest png file [`download_png_from_url`] accepts, 64 MiB.
+pub const MAX_DOWNLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
 pub fn download_png_from_url(url: &str) -> Result<Png> {
-    let resp = reqwest::blocking::get(url).unwrap();
-
-    if resp.status().is_success() {
-        let bytes = resp.bytes().unwrap();
-        println!("Downloaded {} bytes", bytes.len());
-        Ok(Png::try_from(bytes.as_ref()).unwrap())
-    } else {
-        Err(PngError::DownloadFailed)
+    let resp = reqwest::blocking::get(url)?;
+
+    if !resp.status().is_success() {
+        return Err(PngError::HttpStatus(resp.status().as_u16()));
+    }
+    let too_large = PngError::DownloadTooLarge {
+        limit: MAX_DOWNLOAD_SIZE,
+    };
+    if resp
+        .content_length()
+        .is_some_and(|length| length > MAX_DOWNLOAD_SIZE)
+    {
+        return Err(too_large);
+    }
+    // the announced length may be missing or wrong
+    let mut bytes = Vec::new();
+    resp.take(MAX_DOWNLOAD_SIZE + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_DOWNLOAD_SIZE {
+        return Err(too_large);
     }
+    Png::try_from(bytes.as_ref())
 }
@@ -1,50 +1,415 @@
 use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use crate::chunk_type::ChunkType;
 
 pub type Result<T> = std::result::Result<T, PngError>;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum PngError {
     ChunkNotFound,
+    InvalidChunkPosition,
     InvalidPngFile,
-    InvalidCrc,
-    EmptyCrc,
+    /// The crc stored after a chunk does not match its type and data.
+    InvalidCrc {
+        expected: u32,
+        found: u32,
+    },
     InvalidSegment,
     ShortChunk,
+    InvalidChunkLength,
+    InvalidChunkType,
     ChunkDataUTF8Error,
-    FileNotFound,
-    WrongUrl,
-    DownloadFailed,
+    InvalidKeyword,
+    InvalidText,
+    /// A payload is not valid base64 or hex.
+    InvalidEncoding,
+    /// The payload does not start with a valid compression header.
+    InvalidCompressedPayload,
+    /// Decompressing the payload would go past the size limit.
+    DecompressionLimitExceeded {
+        limit: u64,
+    },
+    /// The decoded pixels of the image would go past the size limit.
+    ImageTooLarge {
+        limit: u64,
+    },
+    /// The compression algorithm was left out of this build.
+    CompressionDisabled(&'static str),
+    /// The chunk type regex of a selector does not compile.
+    InvalidTypeRegex,
+    /// Not one of strict, skip-bad-crc or resync.
+    InvalidParseMode,
+    /// Not one of the formats `print` knows.
+    InvalidOutputFormat,
+    /// Not one of none, sub, up, average, paeth or adaptive.
+    InvalidFilter,
+    /// Not letters among r, g, b and a.
+    InvalidChannels,
+    /// The image or the options can't hide a payload in the pixels.
+    LsbUnsupported(&'static str),
+    /// The payload does not fit in the pixels.
+    PayloadTooLarge {
+        capacity: usize,
+    },
+    /// No payload in the pixels, or not with these options and key.
+    HiddenPayloadNotFound,
+    /// The two keys typed for `--key` differ.
+    KeyMismatch,
+    /// The maximum chunk size can't hold a piece of a split payload.
+    InvalidChunkSize,
+    /// A piece of a split payload is not in the file.
+    MissingPiece {
+        sequence: u32,
+        total: u32,
+    },
+    /// A piece of a split payload is in the file more than once.
+    DuplicatePiece {
+        sequence: u32,
+    },
+    /// The data of a standard chunk does not follow the specification.
+    InvalidChunkData {
+        chunk_type: &'static str,
+        reason: &'static str,
+    },
+    /// An error that happened while parsing a chunk, with where it happened.
+    Chunk {
+        /// Index of the chunk in the file.
+        index: usize,
+        /// Offset in bytes of the start of the chunk in the file.
+        offset: u64,
+        /// Type of the chunk, if it could be read.
+        chunk_type: Option<ChunkType>,
+        source: Box<PngError>,
+    },
+    /// `lint` found problems in the file.
+    ValidationFailed {
+        errors: usize,
+        warnings: usize,
+    },
+    /// `analyze` scored the file at or above the threshold.
+    Suspicious {
+        score: u32,
+    },
+    /// Wrong passphrase, or the encrypted chunk was altered or moved.
+    DecryptionFailed,
+    /// The chunk data is not an encrypted payload.
+    InvalidEncryptedPayload,
+    PassphraseMismatch,
+    /// An age recipient is not a valid `age1...` public key.
+    InvalidRecipient,
+    /// An identity file holds no valid `AGE-SECRET-KEY-1...` key.
+    InvalidIdentity,
+    /// A signing key file holds no valid `ED25519-SECRET-KEY:...` key.
+    InvalidSigningKey,
+    /// A public key is not `ed25519:` followed by 64 hex digits.
+    InvalidVerifyingKey,
+    /// A signature is invalid, untrusted or covers altered chunks.
+    SignatureVerificationFailed,
+    EncryptionDisabled,
+    FileNotFound(io::Error),
+    Io(io::Error),
+    #[cfg(feature = "download")]
+    Http(reqwest::Error),
+    HttpStatus(u16),
+    /// The downloaded file is larger than the download limit.
+    DownloadTooLarge {
+        limit: u64,
+    },
+    DownloadDisabled,
     NoSource,
     OutputFileNotSpecified,
+    ChunkTypeNotSpecified,
+    MessageNotSpecified,
 }
 
 use PngError::*;
 
-impl std::error::Error for PngError {}
+impl PngError {
+    /// Process exit code for this error, following the BSD `sysexits.h`
+    /// conventions so scripts can tell error classes apart:
+    ///
+    /// | code | class                                  |
+    /// |------|----------------------------------------|
+    /// | 1    | `lint` or `analyze` rejected the file  |
+    /// | 3    | requested chunk not found              |
+    /// | 64   | bad command line usage                 |
+    /// | 65   | malformed png data                     |
+    /// | 66   | input file not found                   |
+    /// | 69   | download failed                        |
+    /// | 74   | other I/O errors                       |
+    /// | 77   | decryption or signature check failed   |
+    /// | 78   | invalid key or key file                |
+    ///
+    /// Command line parsing errors reported by clap exit with 2.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Chunk { source, .. } => source.exit_code(),
+            ValidationFailed { .. } | Suspicious { .. } => 1,
+            ChunkNotFound | HiddenPayloadNotFound => 3,
+            InvalidChunkPosition
+            | DownloadDisabled
+            | EncryptionDisabled
+            | PassphraseMismatch
+            | KeyMismatch
+            | InvalidRecipient
+            | InvalidChunkSize
+            | CompressionDisabled(_)
+            | InvalidTypeRegex
+            | InvalidParseMode
+            | InvalidOutputFormat
+            | InvalidFilter
+            | InvalidChannels
+            | LsbUnsupported(_)
+            | PayloadTooLarge { .. }
+            | NoSource
+            | OutputFileNotSpecified
+            | ChunkTypeNotSpecified
+            | MessageNotSpecified => 64,
+            InvalidPngFile
+            | InvalidCrc { .. }
+            | InvalidSegment
+            | ShortChunk
+            | InvalidChunkLength
+            | InvalidChunkType
+            | ChunkDataUTF8Error
+            | InvalidKeyword
+            | InvalidText
+            | InvalidEncoding
+            | InvalidCompressedPayload
+            | DecompressionLimitExceeded { .. }
+            | ImageTooLarge { .. }
+            | MissingPiece { .. }
+            | DuplicatePiece { .. }
+            | InvalidChunkData { .. }
+            | InvalidEncryptedPayload => 65,
+            FileNotFound(_) => 66,
+            #[cfg(feature = "download")]
+            Http(_) => 69,
+            HttpStatus(_) => 69,
+            DownloadTooLarge { .. } => 69,
+            Io(_) => 74,
+            DecryptionFailed | SignatureVerificationFailed => 77,
+            InvalidIdentity | InvalidSigningKey | InvalidVerifyingKey => 78,
+        }
+    }
+
+    /// Adds where a chunk parsing error happened.
+    pub(crate) fn in_chunk(self, index: usize, offset: u64, chunk_type: Option<ChunkType>) -> Self {
+        Chunk {
+            index,
+            offset,
+            chunk_type,
+            source: Box::new(self),
+        }
+    }
+}
+
+impl std::error::Error for PngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chunk { source, .. } => Some(source.as_ref()),
+            FileNotFound(e) | Io(e) => Some(e),
+            #[cfg(feature = "download")]
+            Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl Display for PngError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let error_message = match self {
             ChunkNotFound => "Chunk not found",
+            InvalidChunkPosition => "Invalid chunk position",
             InvalidPngFile => "Invalid png file",
-            InvalidCrc => "Invalid crc",
-            EmptyCrc => "Empty crc",
+            InvalidCrc { expected, found } => {
+                return write!(
+                    f,
+                    "Invalid crc: expected {:#010x}, found {:#010x}",
+                    expected, found
+                )
+            }
             InvalidSegment => "Invalid segment",
             ShortChunk => "Short chunk",
+            InvalidChunkLength => "Chunk length does not match its data",
+            InvalidChunkType => "Invalid chunk type",
             ChunkDataUTF8Error => "Chunk data is not UTF-8",
-            FileNotFound => "File not found",
-            WrongUrl => "Wrong url",
-            DownloadFailed => "Download failed",
+            InvalidKeyword => "Invalid keyword: must be 1 to 79 printable Latin-1 characters",
+            InvalidText => "Invalid text: tEXt and zTXt only store Latin-1 without null characters",
+            InvalidEncoding => "Invalid payload: not valid base64 or hex",
+            InvalidCompressedPayload => "Invalid compressed payload",
+            DecompressionLimitExceeded { limit } => {
+                return write!(
+                    f,
+                    "Decompressed payload exceeds the limit of {} bytes",
+                    limit
+                )
+            }
+            ImageTooLarge { limit } => {
+                return write!(
+                    f,
+                    "Image too large: its decoded pixels exceed the limit of {} bytes",
+                    limit
+                )
+            }
+            CompressionDisabled(algorithm) => {
+                return write!(f, "{} compression support is disabled", algorithm)
+            }
+            InvalidTypeRegex => "Invalid chunk type regex",
+            InvalidParseMode => "Invalid parse mode: expected strict, skip-bad-crc or resync",
+            InvalidOutputFormat => {
+                "Invalid output format: expected text, json, yaml, table, tree or hexdump"
+            }
+            InvalidFilter => "Invalid filter: expected none, sub, up, average, paeth or adaptive",
+            InvalidChannels => "Invalid channels: expected letters among r, g, b and a",
+            LsbUnsupported(reason) => {
+                return write!(f, "Can't hide the payload in the pixels: {}", reason)
+            }
+            PayloadTooLarge { capacity } => {
+                return write!(
+                    f,
+                    "Payload too large: the image holds at most {} bytes",
+                    capacity
+                )
+            }
+            HiddenPayloadNotFound => "No payload hidden in the pixels with these options and key",
+            KeyMismatch => "Keys do not match",
+            InvalidChunkSize => {
+                "Invalid maximum chunk size: must be positive, fit the header of split payloads and stay below 2^31 bytes"
+            }
+            MissingPiece { sequence, total } => {
+                return write!(
+                    f,
+                    "Piece {} of {} of the payload is missing",
+                    sequence + 1,
+                    total
+                )
+            }
+            DuplicatePiece { sequence } => {
+                return write!(f, "Piece {} of the payload is duplicated", sequence + 1)
+            }
+            InvalidChunkData { chunk_type, reason } => {
+                return write!(f, "Invalid {} chunk: {}", chunk_type, reason)
+            }
+            Chunk {
+                index,
+                offset,
+                chunk_type: Some(chunk_type),
+                ..
+            } => {
+                return write!(
+                    f,
+                    "Invalid chunk #{} ({}) at offset {}",
+                    index, chunk_type, offset
+                )
+            }
+            Chunk { index, offset, .. } => {
+                return write!(f, "Invalid chunk #{} at offset {}", index, offset)
+            }
+            ValidationFailed { errors, warnings } => {
+                return write!(
+                    f,
+                    "Validation failed with {} errors and {} warnings",
+                    errors, warnings
+                )
+            }
+            Suspicious { score } => {
+                return write!(f, "Suspicious file: scored {} out of 100", score)
+            }
+            DecryptionFailed => "Decryption failed: wrong passphrase or altered chunk",
+            InvalidEncryptedPayload => "Chunk data is not an encrypted message",
+            PassphraseMismatch => "Passphrases do not match",
+            InvalidRecipient => "Invalid recipient: expected an age1... public key",
+            InvalidIdentity => "Invalid identity file: expected AGE-SECRET-KEY-1... keys",
+            InvalidSigningKey => "Invalid signing key file: expected an ED25519-SECRET-KEY:... key",
+            InvalidVerifyingKey => {
+                "Invalid public key: expected ed25519: followed by 64 hex digits"
+            }
+            SignatureVerificationFailed => "Signature verification failed",
+            FileNotFound(_) => "File not found",
+            Io(_) => "I/O error",
+            #[cfg(feature = "download")]
+            Http(_) => "Download failed",
+            HttpStatus(status) => return write!(f, "Download failed with http status {}", status),
+            DownloadTooLarge { limit } => {
+                return write!(f, "Download failed: the file exceeds the limit of {} bytes", limit)
+            }
+            DownloadDisabled => "Download support is disabled",
+            EncryptionDisabled => "Encryption and signature support is disabled",
             NoSource => "No source",
             OutputFileNotSpecified => "Output file not specified",
+            ChunkTypeNotSpecified => "Chunk type not specified",
+            MessageNotSpecified => "Message not specified",
         };
 
         write!(f, "{}", error_message)
     }
 }
 
-impl From<std::io::Error> for PngError {
-    fn from(_: std::io::Error) -> Self {
-        PngError::FileNotFound
+impl From<io::Error> for PngError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => FileNotFound(error),
+            _ => Io(error),
+        }
+    }
+}
+
+#[cfg(feature = "download")]
+impl From<reqwest::Error> for PngError {
+    fn from(error: reqwest::Error) -> Self {
+        Http(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_io_error_kinds() {
+        let not_found: PngError = io::Error::from(io::ErrorKind::NotFound).into();
+        let denied: PngError = io::Error::from(io::ErrorKind::PermissionDenied).into();
+
+        assert!(matches!(not_found, FileNotFound(_)));
+        assert!(matches!(denied, Io(_)));
+        assert_ne!(not_found.exit_code(), denied.exit_code());
+        assert!(denied.source().is_some());
+    }
+
+    #[test]
+    fn test_chunk_error_display() {
+        let error = InvalidCrc {
+            expected: 1,
+            found: 2,
+        }
+        .in_chunk(3, 42, Some(ChunkType::from_str("RuSt").unwrap()));
+
+        assert_eq!(error.to_string(), "Invalid chunk #3 (RuSt) at offset 42");
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "Invalid crc: expected 0x00000001, found 0x00000002"
+        );
+        assert_eq!(error.exit_code(), 65);
+    }
+
+    #[test]
+    fn test_key_and_verdict_exit_codes() {
+        let bad_png = InvalidPngFile.exit_code();
+        assert_ne!(InvalidIdentity.exit_code(), bad_png);
+        assert_ne!(InvalidSigningKey.exit_code(), bad_png);
+        assert_ne!(Suspicious { score: 90 }.exit_code(), bad_png);
+        assert_ne!(
+            ValidationFailed {
+                errors: 1,
+                warnings: 0
+            }
+            .exit_code(),
+            bad_png
+        );
     }
 }
@@ -0,0 +1,346 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use pngme::inspect::ChunkRecord;
+use pngme::{PngError, Result};
+
+/// How `print` shows the chunks of a file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OutputFormat {
+    /// Every field and byte of the chunks, as `Png` displays them.
+    #[default]
+    Text,
+    /// A [`Report`], see `docs/print.schema.json`.
+    Json,
+    /// The same [`Report`] as yaml.
+    Yaml,
+    /// One line per chunk.
+    Table,
+    /// The chunks under the file, their properties under them.
+    Tree,
+    /// The bytes of every chunk, with their offsets in the file.
+    Hexdump,
+}
+
+impl FromStr for OutputFormat {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "table" => Ok(OutputFormat::Table),
+            "tree" => Ok(OutputFormat::Tree),
+            "hexdump" => Ok(OutputFormat::Hexdump),
+            _ => Err(PngError::InvalidOutputFormat),
+        }
+    }
+}
+
+/// The json and yaml output of `print`, described by
+/// `docs/print.schema.json`.
+#[derive(Serialize)]
+pub struct Report {
+    /// Bumped on any change that is not adding a field.
+    pub version: u32,
+    pub file: String,
+    /// Size of the file in bytes.
+    pub size: u64,
+    pub chunks: Vec<ChunkReport>,
+}
+
+#[derive(Serialize)]
+pub struct ChunkReport {
+    pub index: usize,
+    pub offset: u64,
+    pub length: u32,
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+    /// Whether the PNG specification defines the chunk type.
+    pub known: bool,
+    /// Crc stored in the file.
+    pub crc: u32,
+    pub crc_valid: bool,
+    /// The first bytes of the data, non printable ones escaped as `\xNN`.
+    pub preview: String,
+    /// Whether the data is longer than the preview.
+    pub truncated: bool,
+}
+
+impl Report {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(
+        file_path: &Path,
+        size: u64,
+        records: &[ChunkRecord],
+        preview_length: usize,
+    ) -> Report {
+        Report {
+            version: Report::VERSION,
+            file: file_path.display().to_string(),
+            size,
+            chunks: records
+                .iter()
+                .map(|record| ChunkReport::new(record, preview_length))
+                .collect(),
+        }
+    }
+}
+
+impl ChunkReport {
+    pub fn new(record: &ChunkRecord, preview_length: usize) -> ChunkReport {
+        let chunk_type = record.chunk.chunk_type();
+        let data = record.chunk.data();
+        let preview = &data[..data.len().min(preview_length)];
+        ChunkReport {
+            index: record.index,
+            offset: record.offset,
+            length: record.chunk.length(),
+            chunk_type: chunk_type.to_string(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            known: chunk_type.is_known(),
+            crc: record.crc,
+            crc_valid: record.crc_valid(),
+            preview: preview.escape_ascii().to_string(),
+            truncated: preview.len() < data.len(),
+        }
+    }
+
+    fn properties(&self) -> String {
+        let mut properties = vec![
+            if self.critical {
+                "critical"
+            } else {
+                "ancillary"
+            },
+            if self.public { "public" } else { "private" },
+            if self.safe_to_copy {
+                "safe to copy"
+            } else {
+                "unsafe to copy"
+            },
+        ];
+        if !self.reserved_bit_valid {
+            properties.push("invalid reserved bit");
+        }
+        if !self.known {
+            properties.push("unknown");
+        }
+        properties.join(", ")
+    }
+
+    fn crc_status(&self) -> &'static str {
+        if self.crc_valid {
+            "ok"
+        } else {
+            "BAD"
+        }
+    }
+
+    fn preview(&self) -> String {
+        if self.truncated {
+            format!("{}...", self.preview)
+        } else {
+            self.preview.clone()
+        }
+    }
+}
+
+/// Writes the chunks of the file at `file_path`, of `size` bytes, in
+/// `format`.
+pub fn render(
+    format: OutputFormat,
+    file_path: &Path,
+    size: u64,
+    records: &[ChunkRecord],
+    preview_length: usize,
+) -> String {
+    let report = Report::new(file_path, size, records, preview_length);
+    match format {
+        OutputFormat::Text => records
+            .iter()
+            .map(|record| record.chunk.to_string())
+            .collect(),
+        OutputFormat::Json => {
+            let mut json = serde_json::to_string_pretty(&report).expect("serializable report");
+            json.push('\n');
+            json
+        }
+        OutputFormat::Yaml => serde_yaml_ng::to_string(&report).expect("serializable report"),
+        OutputFormat::Table => table(&report),
+        OutputFormat::Tree => tree(&report),
+        OutputFormat::Hexdump => records.iter().map(hexdump).collect::<Vec<_>>().join("\n"),
+    }
+}
+
+fn table(report: &Report) -> String {
+    let mut table = format!(
+        "{:>5}  {:>10}  {:>10}  {:<4}  {:<9}  {:<7}  {:<6}  {:<10}  {:<3}  {}\n",
+        "INDEX", "OFFSET", "LENGTH", "TYPE", "KIND", "SCOPE", "COPY", "CRC", "", "PREVIEW"
+    );
+    for chunk in report.chunks.iter() {
+        let line = format!(
+            "{:>5}  {:>10}  {:>10}  {:<4}  {:<9}  {:<7}  {:<6}  0x{:08x}  {:<3}  {}",
+            chunk.index,
+            chunk.offset,
+            chunk.length,
+            chunk.chunk_type,
+            if chunk.critical {
+                "critical"
+            } else {
+                "ancillary"
+            },
+            if chunk.public { "public" } else { "private" },
+            if chunk.safe_to_copy { "safe" } else { "unsafe" },
+            chunk.crc,
+            chunk.crc_status(),
+            chunk.preview()
+        );
+        writeln!(table, "{}", line.trim_end()).expect("writing to a string");
+    }
+    table
+}
+
+fn tree(report: &Report) -> String {
+    let mut tree = format!(
+        "{} ({} bytes, {} chunks)\n",
+        report.file,
+        report.size,
+        report.chunks.len()
+    );
+    for (position, chunk) in report.chunks.iter().enumerate() {
+        let (branch, indent) = if position + 1 == report.chunks.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        writeln!(
+            tree,
+            "{}#{} {} at {}, {} bytes",
+            branch, chunk.index, chunk.chunk_type, chunk.offset, chunk.length
+        )
+        .and_then(|_| writeln!(tree, "{}├── {}", indent, chunk.properties()))
+        .and_then(|_| {
+            writeln!(
+                tree,
+                "{}├── crc 0x{:08x} {}",
+                indent,
+                chunk.crc,
+                chunk.crc_status()
+            )
+        })
+        .and_then(|_| {
+            let preview = format!("{}└── preview: {}", indent, chunk.preview());
+            writeln!(tree, "{}", preview.trim_end())
+        })
+        .expect("writing to a string");
+    }
+    tree
+}
+
+/// The whole chunk, length to crc, 16 bytes per line.
+fn hexdump(record: &ChunkRecord) -> String {
+    let status = if record.crc_valid() { "ok" } else { "BAD" };
+    let mut dump = format!(
+        "#{} {} at {}, {} bytes, crc {}\n",
+        record.index,
+        record.chunk.chunk_type(),
+        record.offset,
+        record.chunk.length(),
+        status
+    );
+    for (line, bytes) in record.as_bytes().chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (position, byte) in bytes.iter().enumerate() {
+            if position == 8 {
+                hex.push(' ');
+            }
+            write!(hex, "{:02x} ", byte).expect("writing to a string");
+        }
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            dump,
+            "{:08x}  {:<49} |{}|",
+            record.offset + line as u64 * 16,
+            hex,
+            text
+        )
+        .expect("writing to a string");
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::inspect::inspect;
+    use pngme::{Chunk, ChunkType, Iend, ParseMode, Png};
+
+    fn testing_records() -> Vec<ChunkRecord> {
+        let png = Png::from_chunks(vec![
+            Chunk::new(
+                ChunkType::from_str("RuSt").unwrap(),
+                b"hidden\nmessage".to_vec(),
+            ),
+            Iend.to_chunk(),
+        ]);
+        inspect(&png.as_bytes(), ParseMode::Strict).unwrap().chunks
+    }
+
+    #[test]
+    fn test_json_report() {
+        let output = render(
+            OutputFormat::Json,
+            Path::new("image.png"),
+            45,
+            &testing_records(),
+            8,
+        );
+        let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(report["version"], 1);
+        assert_eq!(report["chunks"][0]["type"], "RuSt");
+        assert_eq!(report["chunks"][0]["offset"], 8);
+        assert_eq!(report["chunks"][0]["public"], false);
+        assert_eq!(report["chunks"][0]["crc_valid"], true);
+        assert_eq!(report["chunks"][0]["preview"], "hidden\\nm");
+        assert_eq!(report["chunks"][0]["truncated"], true);
+        assert_eq!(report["chunks"][1]["truncated"], false);
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let output = render(
+            OutputFormat::Hexdump,
+            Path::new("image.png"),
+            45,
+            &testing_records()[1..],
+            8,
+        );
+        assert_eq!(
+            output,
+            "#1 IEND at 34, 0 bytes, crc ok\n\
+             00000022  00 00 00 00 49 45 4e 44  ae 42 60 82              |....IEND.B`.|\n"
+        );
+    }
+}
@@ -1,23 +1,300 @@
-pub use std::fs::{self, File, OpenOptions};
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
-use crate::Result;
 use crate::png::Png;
+use crate::stream::{ChunkReader, ChunkWriter};
+use crate::Result;
 
-pub fn png_file_to_vec(file_path: &PathBuf) -> Result<Vec<u8>> {
+pub fn png_file_to_vec(file_path: &Path) -> Result<Vec<u8>> {
     Ok(fs::read(file_path)?)
 }
 
-pub fn vec_to_new_png_file(file_path: &PathBuf, contents: &Vec<u8>) -> Result<()> {
-    Ok(fs::write(file_path, contents)?)
+pub fn vec_to_new_png_file(file_path: &Path, contents: &[u8]) -> Result<()> {
+    write_png_file(file_path, contents, &WriteOptions::default())
+}
+
+/// How files are replaced when pngme writes them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct WriteOptions {
+    /// Keeps the previous file next to the new one, as `<name>.bak`.
+    pub backup: bool,
+    /// Gives the new file the modification time of the previous one.
+    pub preserve_mtime: bool,
+}
+
+/// An advisory exclusive lock on a file, released when dropped.
+pub struct FileLock {
+    _file: File,
+}
+
+/// Waits for an exclusive advisory lock on the file at `file_path`, if it
+/// exists. Every pngme write takes it, so concurrent runs on the same file
+/// wait for each other instead of losing changes.
+pub fn lock_file(file_path: &Path) -> Result<Option<FileLock>> {
+    loop {
+        let file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        file.lock()?;
+        // the file may have been replaced while waiting, its lock is then
+        // held on the previous version
+        if is_same_file(&file, file_path)? {
+            return Ok(Some(FileLock { _file: file }));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, file_path: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let locked = file.metadata()?;
+    Ok(match fs::metadata(file_path) {
+        Ok(current) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        Err(_) => false,
+    })
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _file_path: &Path) -> Result<bool> {
+    Ok(true)
 }
 
-pub fn file_path_to_png(file_path: &PathBuf) -> Result<Png> {
+/// Replaces the file at `file_path` with `contents` atomically, under its
+/// lock, or writes them to stdout for `-`.
+pub fn write_png_file(file_path: &Path, contents: &[u8], options: &WriteOptions) -> Result<()> {
+    if file_path == Path::new("-") {
+        return write_file(file_path, contents);
+    }
+    let _lock = lock_file(file_path)?;
+    replace_file(file_path, contents, options)
+}
+
+/// Replaces the file at `file_path` with `contents` without locking it. Hold
+/// the lock from [`lock_file`] when the contents were read from the file.
+pub fn replace_file(file_path: &Path, contents: &[u8], options: &WriteOptions) -> Result<()> {
+    replace_with(file_path, options, |mut writer| {
+        writer.write_all(contents)?;
+        Ok(writer.flush()?)
+    })
+}
+
+/// Reads the png file at `file_path`, lets `f` modify it and writes it back,
+/// holding the lock of the file all along.
+pub fn modify_png_file<T, F>(file_path: &Path, options: &WriteOptions, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Png) -> Result<T>,
+{
+    let _lock = lock_file(file_path)?;
+    let mut png = file_path_to_png(file_path)?;
+    let result = f(&mut png)?;
+    replace_file(file_path, &png.as_bytes(), options)?;
+    Ok(result)
+}
+
+/// Reads and parses the png file at `file_path`.
+pub fn file_path_to_png(file_path: &Path) -> Result<Png> {
     let file_in_vec = png_file_to_vec(file_path)?;
     let png = Png::try_from(file_in_vec.as_ref())?;
     Ok(png)
 }
 
-pub fn rewrite_png_file(file_path: &PathBuf, contents: &Vec<u8>) -> Result<()> {
-    vec_to_new_png_file(file_path, contents)
+pub fn rewrite_png_file(file_path: &Path, contents: &[u8]) -> Result<()> {
+    write_png_file(file_path, contents, &WriteOptions::default())
+}
+
+/// Writes a new file only readable by its owner, failing if it exists, for
+/// secret keys.
+pub fn write_private_file(file_path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(file_path)?;
+    file.write_all(contents)?;
+    Ok(file.sync_all()?)
+}
+
+/// Opens the file at `file_path` for buffered reading, or stdin for `-`.
+pub fn open_file_reader(file_path: &Path) -> Result<Box<dyn Read>> {
+    if file_path == Path::new("-") {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(file_path)?)))
+    }
+}
+
+/// Reads the whole file at `file_path`, or stdin for `-`.
+pub fn read_file(file_path: &Path) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    open_file_reader(file_path)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Writes `contents` to the file at `file_path`, or to stdout for `-`.
+pub fn write_file(file_path: &Path, contents: &[u8]) -> Result<()> {
+    if file_path == Path::new("-") {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(contents)?;
+        Ok(stdout.flush()?)
+    } else {
+        Ok(fs::write(file_path, contents)?)
+    }
+}
+
+/// Opens a chunk reader on the file at `file_path`, or on stdin for `-`.
+pub fn open_png_reader(file_path: &Path) -> Result<ChunkReader<Box<dyn Read>>> {
+    ChunkReader::new(open_file_reader(file_path)?)
+}
+
+/// Streams the png at `input_path` through `f` into `output_path` (stdout for
+/// `-`), with one chunk at a time in memory.
+///
+/// The output goes to a temporary file next to `output_path` which replaces it
+/// only once `f` succeeded, so both paths can be the same file.
+pub fn stream_png_file<T, F>(input_path: &Path, output_path: &Path, f: F) -> Result<T>
+where
+    F: FnOnce(&mut ChunkReader<Box<dyn Read>>, &mut ChunkWriter<Box<dyn Write>>) -> Result<T>,
+{
+    stream_png_file_with(input_path, output_path, &WriteOptions::default(), f)
+}
+
+/// Same as [`stream_png_file`], replacing `output_path` as `options` says.
+/// The output file is locked until it is replaced.
+pub fn stream_png_file_with<T, F>(
+    input_path: &Path,
+    output_path: &Path,
+    options: &WriteOptions,
+    f: F,
+) -> Result<T>
+where
+    F: FnOnce(&mut ChunkReader<Box<dyn Read>>, &mut ChunkWriter<Box<dyn Write>>) -> Result<T>,
+{
+    if output_path == Path::new("-") {
+        let mut reader = open_png_reader(input_path)?;
+        let mut writer = ChunkWriter::new(Box::new(io::stdout().lock()) as Box<dyn Write>)?;
+        let result = f(&mut reader, &mut writer)?;
+        writer.into_inner()?;
+        return Ok(result);
+    }
+
+    let _lock = lock_file(output_path)?;
+    let mut reader = open_png_reader(input_path)?;
+    replace_with(output_path, options, |writer| {
+        let mut writer = ChunkWriter::new(writer)?;
+        let result = f(&mut reader, &mut writer)?;
+        writer.into_inner()?;
+        Ok(result)
+    })
+}
+
+/// Writes a temporary file next to `file_path` with `write`, which must flush
+/// it, syncs it to disk and renames it over `file_path`. The new file keeps
+/// the permissions of the previous one.
+fn replace_with<T, F>(file_path: &Path, options: &WriteOptions, write: F) -> Result<T>
+where
+    F: FnOnce(Box<dyn Write>) -> Result<T>,
+{
+    let previous = fs::metadata(file_path).ok();
+    let (file, temp_path) = create_temp_file(file_path)?;
+    let written = write_temp_file(file, previous.as_ref(), options, write);
+
+    let result = match written {
+        Ok(result) => result,
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(error);
+        }
+    };
+    if options.backup && previous.is_some() {
+        backup(file_path)?;
+    }
+    fs::rename(&temp_path, file_path)?;
+    sync_parent(file_path)?;
+    Ok(result)
+}
+
+/// Writes the temporary file of [`replace_with`] and gives it the
+/// permissions and, as `options` say, the modification time of `previous`.
+fn write_temp_file<T, F>(
+    file: File,
+    previous: Option<&fs::Metadata>,
+    options: &WriteOptions,
+    write: F,
+) -> Result<T>
+where
+    F: FnOnce(Box<dyn Write>) -> Result<T>,
+{
+    let handle = file.try_clone()?;
+    let result = write(Box::new(BufWriter::new(file)))?;
+    if let Some(previous) = previous {
+        handle.set_permissions(previous.permissions())?;
+        if options.preserve_mtime {
+            handle.set_modified(previous.modified()?)?;
+        }
+    }
+    handle.sync_all()?;
+    Ok(result)
+}
+
+/// Keeps the file at `file_path` as `<name>.bak`, replacing an older backup.
+fn backup(file_path: &Path) -> Result<()> {
+    let mut backup_path = file_path.as_os_str().to_owned();
+    backup_path.push(".bak");
+    let backup_path = PathBuf::from(backup_path);
+
+    match fs::remove_file(&backup_path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+        _ => {}
+    }
+    // a hard link keeps the previous file as is once it is replaced
+    if fs::hard_link(file_path, &backup_path).is_err() {
+        fs::copy(file_path, &backup_path)?;
+    }
+    Ok(())
+}
+
+/// Makes the rename durable, the directory entry is not synced with the file.
+#[cfg(unix)]
+fn sync_parent(file_path: &Path) -> Result<()> {
+    let parent = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(File::open(parent)?.sync_all()?)
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_file_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Creates a temporary file next to `file_path` under a random name. It must
+/// not exist yet: a file or a symlink left there, maybe by someone else in a
+/// shared directory, is never opened, and concurrent runs don't share it.
+fn create_temp_file(file_path: &Path) -> Result<(File, PathBuf)> {
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    loop {
+        let temp_path = file_path.with_file_name(format!(
+            ".{}.{:08x}.pngme.tmp",
+            file_name,
+            rand::random::<u32>()
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((file, temp_path)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error.into()),
+        }
+    }
 }
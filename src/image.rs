@@ -0,0 +1,875 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::critical::{ColorType, Ihdr, Plte};
+use crate::png::Png;
+use crate::validate::Validator;
+use crate::{PngError, Result};
+
+/// Start and spacing, in pixels, of the columns and rows of the seven Adam7
+/// passes: `(x0, y0, dx, dy)`.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// A scanline filter, stored before each scanline of the image data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    None,
+    /// Difference with the pixel on the left.
+    Sub,
+    /// Difference with the pixel above.
+    Up,
+    /// Difference with the average of the pixels on the left and above.
+    Average,
+    /// Difference with the closest of the pixels on the left, above and
+    /// upper left to their gradient.
+    Paeth,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 5] = [
+        Filter::None,
+        Filter::Sub,
+        Filter::Up,
+        Filter::Average,
+        Filter::Paeth,
+    ];
+
+    /// Filter type byte stored before the scanline.
+    pub fn value(&self) -> u8 {
+        match self {
+            Filter::None => 0,
+            Filter::Sub => 1,
+            Filter::Up => 2,
+            Filter::Average => 3,
+            Filter::Paeth => 4,
+        }
+    }
+}
+
+/// How [`Image::encode`] chooses the filter of each scanline.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FilterStrategy {
+    /// The same filter for every scanline.
+    Fixed(Filter),
+    /// The filter giving the smallest sum of absolute differences, as libpng
+    /// does. Indexed images and images under 8 bits are not filtered, which
+    /// compresses them better.
+    #[default]
+    Adaptive,
+}
+
+impl FromStr for FilterStrategy {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(FilterStrategy::Fixed(Filter::None)),
+            "sub" => Ok(FilterStrategy::Fixed(Filter::Sub)),
+            "up" => Ok(FilterStrategy::Fixed(Filter::Up)),
+            "average" => Ok(FilterStrategy::Fixed(Filter::Average)),
+            "paeth" => Ok(FilterStrategy::Fixed(Filter::Paeth)),
+            "adaptive" => Ok(FilterStrategy::Adaptive),
+            _ => Err(PngError::InvalidFilter),
+        }
+    }
+}
+
+/// How [`Image::encode`] writes the image data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IdatOptions {
+    pub filter: FilterStrategy,
+    /// Deflate level, from 0 (stored) to 9 (smallest).
+    pub level: u32,
+    /// Maximum length of the data of each `IDAT` chunk.
+    pub chunk_size: usize,
+}
+
+impl Default for IdatOptions {
+    fn default() -> Self {
+        IdatOptions {
+            filter: FilterStrategy::default(),
+            level: 6,
+            chunk_size: IdatOptions::DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+impl IdatOptions {
+    pub const DEFAULT_CHUNK_SIZE: usize = 65536;
+}
+
+/// The pixels of a png file, decoded from its `IDAT` chunks.
+///
+/// Samples are kept at the bit depth of the file, from 0 to
+/// `2^bit_depth - 1`, whatever the color type: indexed images hold palette
+/// indexes and no color conversion happens.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    ihdr: Ihdr,
+    palette: Option<Plte>,
+    samples: Vec<u16>,
+}
+
+impl Image {
+    /// Bytes the decoded samples may take by default, 2 per sample whatever
+    /// the bit depth: 256 MiB, e.g. 8192 × 4096 rgba pixels.
+    pub const DEFAULT_LIMIT: u64 = 256 * 1024 * 1024;
+
+    /// Same as [`Image::decode_with_limit`], with [`Image::DEFAULT_LIMIT`].
+    pub fn decode(png: &Png) -> Result<Image> {
+        Image::decode_with_limit(png, Image::DEFAULT_LIMIT)
+    }
+
+    /// Concatenates the `IDAT` chunks of `png`, inflates them, reverses the
+    /// scanline filters and Adam7 interlacing. Fails with
+    /// [`PngError::ImageTooLarge`] before inflating anything if the samples
+    /// would take more than `limit` bytes; the inflated data takes at most as
+    /// much again.
+    pub fn decode_with_limit(png: &Png, limit: u64) -> Result<Image> {
+        let ihdr = Ihdr::try_from(
+            png.chunk_by_type(Ihdr::CHUNK_TYPE)
+                .ok_or(PngError::ChunkNotFound)?,
+        )?;
+        // a few bytes of IHDR can describe billions of pixels
+        let size = (ihdr.width as u64)
+            .checked_mul(ihdr.height as u64)
+            .and_then(|pixels| pixels.checked_mul(ihdr.color_type.channels() as u64 * 2));
+        if size.is_none_or(|size| size > limit) {
+            return Err(PngError::ImageTooLarge { limit });
+        }
+        let palette = png
+            .chunk_by_type(Plte::CHUNK_TYPE)
+            .map(Plte::try_from)
+            .transpose()?;
+        if ihdr.color_type == ColorType::Indexed && palette.is_none() {
+            return Err(invalid_data("PLTE", "indexed images need a palette"));
+        }
+
+        let mut idat = png.chunks_by_type("IDAT").peekable();
+        if idat.peek().is_none() {
+            return Err(PngError::ChunkNotFound);
+        }
+        let compressed: Vec<u8> = idat.flat_map(|chunk| chunk.data()).copied().collect();
+        let expected =
+            filtered_length(&ihdr).ok_or(invalid_data("IHDR", "image too large to decode"))?;
+
+        // the size is known, a longer stream is invalid and not inflated further
+        let mut data = Vec::new();
+        ZlibDecoder::new(&compressed[..])
+            .take(expected as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|_| invalid_data("IDAT", "invalid zlib stream"))?;
+        if data.len() != expected {
+            return Err(invalid_data(
+                "IDAT",
+                "image data does not match the size in IHDR",
+            ));
+        }
+
+        let samples = unfilter_passes(&ihdr, &mut data)?;
+        Ok(Image {
+            ihdr,
+            palette,
+            samples,
+        })
+    }
+
+    /// An image from its samples, laid out as [`Image::samples`] says.
+    /// Indexed images need a palette.
+    pub fn new(ihdr: Ihdr, palette: Option<Plte>, samples: Vec<u16>) -> Result<Image> {
+        let length = (ihdr.width as usize)
+            .checked_mul(ihdr.height as usize)
+            .and_then(|pixels| pixels.checked_mul(ihdr.color_type.channels() as usize));
+        if length != Some(samples.len()) {
+            return Err(invalid_data(
+                "IDAT",
+                "sample count does not match the size in IHDR",
+            ));
+        }
+        if ihdr.color_type == ColorType::Indexed && palette.is_none() {
+            return Err(invalid_data("PLTE", "indexed images need a palette"));
+        }
+        Ok(Image {
+            ihdr,
+            palette,
+            samples,
+        })
+    }
+
+    /// Filters and deflates the image into `IDAT` chunks, interlaced if the
+    /// header says so. Fails if a sample does not fit the bit depth.
+    pub fn encode(&self, options: &IdatOptions) -> Result<Vec<Chunk>> {
+        if options.chunk_size == 0 || options.chunk_size > Validator::MAX_CHUNK_LENGTH as usize {
+            return Err(PngError::InvalidChunkSize);
+        }
+        let max = (1_u32 << self.bit_depth()) - 1;
+        if self.samples.iter().any(|&sample| sample as u32 > max) {
+            return Err(invalid_data(
+                "IDAT",
+                "sample value too large for the bit depth",
+            ));
+        }
+
+        let strategy = match options.filter {
+            FilterStrategy::Adaptive
+                if self.color_type() == ColorType::Indexed || self.bit_depth() < 8 =>
+            {
+                FilterStrategy::Fixed(Filter::None)
+            }
+            strategy => strategy,
+        };
+        let filtered = filter_passes(&self.ihdr, &self.samples, strategy);
+
+        let mut encoder = ZlibEncoder::new(
+            Vec::with_capacity(filtered.len() / 2),
+            flate2::Compression::new(options.level.min(9)),
+        );
+        encoder.write_all(&filtered)?;
+        let compressed = encoder.finish()?;
+
+        let idat = ChunkType::from_str("IDAT").expect("valid chunk type");
+        Ok(compressed
+            .chunks(options.chunk_size)
+            .map(|data| Chunk::new(idat, data.to_vec()))
+            .collect())
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn width(&self) -> u32 {
+        self.ihdr.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.ihdr.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.ihdr.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.ihdr.color_type
+    }
+
+    /// The palette of the file, always there for indexed images.
+    pub fn palette(&self) -> Option<&Plte> {
+        self.palette.as_ref()
+    }
+
+    /// Number of samples per pixel.
+    pub fn channels(&self) -> usize {
+        self.ihdr.color_type.channels() as usize
+    }
+
+    /// Every sample, row by row from the top left pixel, `channels` per
+    /// pixel.
+    pub fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [u16] {
+        &mut self.samples
+    }
+
+    /// The samples of the pixel at column `x` and row `y`, from 0.
+    ///
+    /// # Panics
+    ///
+    /// If the pixel is outside the image.
+    pub fn pixel(&self, x: u32, y: u32) -> &[u16] {
+        assert!(x < self.width() && y < self.height(), "pixel out of bounds");
+        let channels = self.channels();
+        let start = (y as usize * self.width() as usize + x as usize) * channels;
+        &self.samples[start..start + channels]
+    }
+
+    /// Same as [`Image::pixel`], to change the pixel.
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut [u16] {
+        assert!(x < self.width() && y < self.height(), "pixel out of bounds");
+        let channels = self.channels();
+        let start = (y as usize * self.width() as usize + x as usize) * channels;
+        &mut self.samples[start..start + channels]
+    }
+}
+
+/// Columns and rows of each pass, with where the pass starts and steps.
+fn passes(ihdr: &Ihdr) -> impl Iterator<Item = (usize, usize, (usize, usize, usize, usize))> {
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let passes: &[_] = if ihdr.is_interlaced() {
+        &ADAM7
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    passes.iter().map(move |&(x0, y0, dx, dy)| {
+        let columns = width.saturating_sub(x0).div_ceil(dx);
+        let rows = height.saturating_sub(y0).div_ceil(dy);
+        (columns, rows, (x0, y0, dx, dy))
+    })
+}
+
+/// Length in bytes of a scanline of `columns` pixels, without its filter type.
+fn row_length(ihdr: &Ihdr, columns: usize) -> Option<usize> {
+    Some(columns.checked_mul(ihdr.bits_per_pixel())?.div_ceil(8))
+}
+
+/// Length of the inflated image data: every scanline of every pass, each
+/// with its filter type byte. Empty passes have no scanline.
+fn filtered_length(ihdr: &Ihdr) -> Option<usize> {
+    passes(ihdr).try_fold(0_usize, |length, (columns, rows, _)| {
+        if columns == 0 || rows == 0 {
+            return Some(length);
+        }
+        let scanline = row_length(ihdr, columns)?.checked_add(1)?;
+        length.checked_add(scanline.checked_mul(rows)?)
+    })
+}
+
+/// Unfilters the scanlines of every pass in place and gathers their samples
+/// at the place of their pixels in the image.
+fn unfilter_passes(ihdr: &Ihdr, data: &mut [u8]) -> Result<Vec<u16>> {
+    let width = ihdr.width as usize;
+    let channels = ihdr.color_type.channels() as usize;
+    let depth = ihdr.bit_depth;
+    // the byte before is the one of the previous pixel, or byte for packed
+    // pixels
+    let distance = (ihdr.bits_per_pixel() / 8).max(1);
+    let mut samples = vec![0; width * ihdr.height as usize * channels];
+
+    let mut pos = 0;
+    for (columns, rows, (x0, y0, dx, dy)) in passes(ihdr) {
+        if columns == 0 || rows == 0 {
+            continue;
+        }
+        let length = row_length(ihdr, columns).expect("checked with the data length");
+        let mut previous = vec![0; length];
+        for row in 0..rows {
+            let filter = data[pos];
+            let line = &mut data[pos + 1..pos + 1 + length];
+            unfilter(filter, line, &previous, distance)?;
+
+            let y = y0 + row * dy;
+            for column in 0..columns {
+                let x = x0 + column * dx;
+                for channel in 0..channels {
+                    samples[(y * width + x) * channels + channel] =
+                        read_sample(line, column * channels + channel, depth);
+                }
+            }
+            previous.copy_from_slice(line);
+            pos += 1 + length;
+        }
+    }
+    Ok(samples)
+}
+
+/// Packs the samples of every pass into filtered scanlines, the reverse of
+/// [`unfilter_passes`].
+fn filter_passes(ihdr: &Ihdr, samples: &[u16], strategy: FilterStrategy) -> Vec<u8> {
+    let width = ihdr.width as usize;
+    let channels = ihdr.color_type.channels() as usize;
+    let depth = ihdr.bit_depth;
+    let distance = (ihdr.bits_per_pixel() / 8).max(1);
+    let mut data = Vec::new();
+
+    for (columns, rows, (x0, y0, dx, dy)) in passes(ihdr) {
+        if columns == 0 || rows == 0 {
+            continue;
+        }
+        let length = row_length(ihdr, columns).expect("image in memory");
+        let mut previous = vec![0; length];
+        let mut line = vec![0; length];
+        let mut filtered = vec![0; length];
+        for row in 0..rows {
+            line.fill(0);
+            let y = y0 + row * dy;
+            for column in 0..columns {
+                let x = x0 + column * dx;
+                for channel in 0..channels {
+                    let sample = samples[(y * width + x) * channels + channel];
+                    write_sample(&mut line, column * channels + channel, depth, sample);
+                }
+            }
+
+            let filter = match strategy {
+                FilterStrategy::Fixed(filter) => filter,
+                FilterStrategy::Adaptive => *Filter::ALL
+                    .iter()
+                    .min_by_key(|filter| {
+                        apply_filter(**filter, &line, &previous, distance, &mut filtered);
+                        // bytes read as signed, the closest to 0 compress best
+                        filtered
+                            .iter()
+                            .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                            .sum::<u64>()
+                    })
+                    .expect("five filters"),
+            };
+            apply_filter(filter, &line, &previous, distance, &mut filtered);
+            data.push(filter.value());
+            data.extend_from_slice(&filtered);
+            std::mem::swap(&mut previous, &mut line);
+        }
+    }
+    data
+}
+
+/// Filters a scanline into `filtered`, given the scanline above it.
+fn apply_filter(
+    filter: Filter,
+    line: &[u8],
+    previous: &[u8],
+    distance: usize,
+    filtered: &mut [u8],
+) {
+    for i in 0..line.len() {
+        let (left, upper_left) = if i >= distance {
+            (line[i - distance], previous[i - distance])
+        } else {
+            (0, 0)
+        };
+        let predictor = match filter {
+            Filter::None => 0,
+            Filter::Sub => left,
+            Filter::Up => previous[i],
+            Filter::Average => ((left as u16 + previous[i] as u16) / 2) as u8,
+            Filter::Paeth => paeth(left, previous[i], upper_left),
+        };
+        filtered[i] = line[i].wrapping_sub(predictor);
+    }
+}
+
+/// Reverses the filter of a scanline, given the unfiltered one above it.
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8], distance: usize) -> Result<()> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in distance..line.len() {
+                line[i] = line[i].wrapping_add(line[i - distance]);
+            }
+        }
+        2 => {
+            for (byte, above) in line.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(*above);
+            }
+        }
+        3 => {
+            for i in 0..line.len() {
+                let left = if i >= distance { line[i - distance] } else { 0 };
+                let average = (left as u16 + previous[i] as u16) / 2;
+                line[i] = line[i].wrapping_add(average as u8);
+            }
+        }
+        4 => {
+            for i in 0..line.len() {
+                let (left, upper_left) = if i >= distance {
+                    (line[i - distance], previous[i - distance])
+                } else {
+                    (0, 0)
+                };
+                line[i] = line[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        }
+        _ => return Err(invalid_data("IDAT", "unknown scanline filter type")),
+    }
+    Ok(())
+}
+
+/// Whichever of the left, above and upper left bytes is closest to
+/// `left + above - upper_left`.
+fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_above = (estimate - above as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+    if distance_left <= distance_above && distance_left <= distance_upper_left {
+        left
+    } else if distance_above <= distance_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+/// The `index`th sample of an unfiltered scanline. Samples under 8 bits are
+/// packed from the most significant bit, 16 bit ones are big endian.
+fn read_sample(line: &[u8], index: usize, depth: u8) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([line[index * 2], line[index * 2 + 1]]),
+        8 => line[index] as u16,
+        _ => {
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            (line[bit / 8] >> shift) as u16 & ((1 << depth) - 1)
+        }
+    }
+}
+
+/// Writes the `index`th sample of a scanline cleared beforehand, the reverse
+/// of [`read_sample`].
+fn write_sample(line: &mut [u8], index: usize, depth: u8, sample: u16) {
+    match depth {
+        16 => line[index * 2..index * 2 + 2].copy_from_slice(&sample.to_be_bytes()),
+        8 => line[index] = sample as u8,
+        _ => {
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            line[bit / 8] |= (sample as u8) << shift;
+        }
+    }
+}
+
+fn invalid_data(chunk_type: &'static str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    /// Synthetic images, see `generate.py` there.
+    const CONFORMANCE: &str = "assets/conformance";
+    /// Images of the PngSuite.
+    const PNGSUITE: &str = "assets/pngsuite";
+
+    fn load(name: &str) -> Png {
+        let bytes = std::fs::read(Path::new(CONFORMANCE).join(name)).unwrap();
+        Png::try_from(bytes.as_ref()).unwrap()
+    }
+
+    fn png_names(directory: &str) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".png"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The samples of a file as decoded by the png crate, an independent
+    /// reference, laid out as [`Image::samples`].
+    fn reference_samples(path: &Path) -> Vec<u16> {
+        let file = std::fs::File::open(path).unwrap();
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer).unwrap();
+
+        let samples = frame.width as usize * frame.color_type.samples();
+        buffer[..frame.buffer_size()]
+            .chunks(frame.line_size)
+            .flat_map(|line| {
+                (0..samples).map(|index| read_sample(line, index, frame.bit_depth as u8))
+            })
+            .collect()
+    }
+
+    /// The sample values written by `assets/conformance/generate.py`.
+    fn expected_sample(image: &Image, x: usize, y: usize, channel: usize) -> u16 {
+        let colors = match image.palette() {
+            Some(palette) => palette.entries().len(),
+            None => 1 << image.bit_depth(),
+        };
+        ((x * 7 + y * 13 + channel * 29 + x * y) % colors) as u16
+    }
+
+    #[test]
+    fn test_pngsuite() {
+        let names = png_names(PNGSUITE);
+        assert!(names.len() >= 13);
+
+        for name in names {
+            let path = Path::new(PNGSUITE).join(&name);
+            let bytes = std::fs::read(&path).unwrap();
+            let image = Image::decode(&Png::try_from(bytes.as_ref()).unwrap()).unwrap();
+            // e.g. basi2c08: interlaced, color type 2, 8 bits
+            assert_eq!(image.ihdr().is_interlaced(), name.as_bytes()[3] == b'i');
+            assert_eq!(
+                format!("{}{:02}", image.color_type().value(), image.bit_depth()),
+                format!("{}{}", &name[4..5], &name[6..8]),
+                "{}",
+                name
+            );
+            assert!(image.samples() == reference_samples(&path), "{}", name);
+        }
+    }
+
+    /// The corrupted `x*` files of the PngSuite, made from `basn6a16.png` as
+    /// the suite describes them, must be rejected.
+    #[test]
+    fn test_pngsuite_corrupted() {
+        let bytes = std::fs::read(Path::new(PNGSUITE).join("basn6a16.png")).unwrap();
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        let decode = |bytes: &[u8]| Png::try_from(bytes).and_then(|png| png.image());
+        let with_ihdr = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut data = png.chunk_by_type("IHDR").unwrap().data().to_vec();
+            change(&mut data);
+            let chunks = png
+                .chunks()
+                .iter()
+                .map(|chunk| match chunk.chunk_type().to_string().as_str() {
+                    "IHDR" => Chunk::new(*chunk.chunk_type(), data.clone()),
+                    _ => Chunk::new(*chunk.chunk_type(), chunk.data().to_vec()),
+                })
+                .collect();
+            Png::from_chunks(chunks).as_bytes()
+        };
+        let changed = |index: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[index] = byte;
+            bytes
+        };
+
+        let cases: Vec<(&str, Vec<u8>)> = vec![
+            (
+                "xs1n: signature byte 1 MSBit reset to zero",
+                changed(0, 0x09),
+            ),
+            ("xs2n: signature byte 2 is a 'Q'", changed(1, b'Q')),
+            ("xs4n: signature byte 4 lowercase", changed(3, b'g')),
+            (
+                "xs7n: 7th byte a space instead of control-Z",
+                changed(6, b' '),
+            ),
+            (
+                "xcrn: added cr bytes",
+                [&bytes[..4], b"\r", &bytes[4..]].concat(),
+            ),
+            (
+                "xlfn: added lf bytes",
+                [&bytes[..6], b"\n", &bytes[6..]].concat(),
+            ),
+            (
+                "xhdn: incorrect IHDR checksum",
+                changed(8 + 8 + 13, bytes[29] ^ 1),
+            ),
+            ("xc1n: color type 1", with_ihdr(&|ihdr| ihdr[9] = 1)),
+            ("xc9n: color type 9", with_ihdr(&|ihdr| ihdr[9] = 9)),
+            ("xd0n: bit-depth 0", with_ihdr(&|ihdr| ihdr[8] = 0)),
+            ("xd3n: bit-depth 3", with_ihdr(&|ihdr| ihdr[8] = 3)),
+            ("xd9n: bit-depth 99", with_ihdr(&|ihdr| ihdr[8] = 99)),
+            ("xdtn: missing IDAT chunk", {
+                let mut png = Png::try_from(bytes.as_ref()).unwrap();
+                png.remove_chunk("IDAT").unwrap();
+                png.as_bytes()
+            }),
+            ("xcsn: incorrect IDAT checksum", {
+                let idat = png.chunk_by_type("IDAT").unwrap();
+                let end = bytes.len() - 12;
+                assert_eq!(&bytes[end - 4..end], idat.crc().to_be_bytes());
+                changed(end - 1, bytes[end - 1] ^ 1)
+            }),
+        ];
+        for (case, bytes) in cases {
+            assert!(decode(&bytes).is_err(), "{}", case);
+        }
+    }
+
+    #[test]
+    fn test_conformance_images() {
+        let names = png_names(CONFORMANCE);
+        assert!(names.len() >= 50);
+
+        for name in names {
+            let image = Image::decode(&load(&name)).unwrap();
+            // e.g. graya16-interlaced: color type 4, 16 bits
+            let kind = name.split(['-', '.']).next().unwrap();
+            let (color, depth) = kind.split_at(kind.find(|c: char| c.is_ascii_digit()).unwrap());
+            let color_type = ["gray", "", "rgb", "palette", "graya", "", "rgba"]
+                .iter()
+                .position(|other| *other == color)
+                .unwrap();
+            assert_eq!(image.color_type().value() as usize, color_type, "{}", name);
+            assert_eq!(image.bit_depth().to_string(), depth, "{}", name);
+            assert_eq!(image.ihdr().is_interlaced(), name.contains("-interlaced"));
+            let path = Path::new(CONFORMANCE).join(&name);
+            assert!(image.samples() == reference_samples(&path), "{}", name);
+
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    let pixel = image.pixel(x, y);
+                    for (channel, sample) in pixel.iter().enumerate() {
+                        let expected = expected_sample(&image, x as usize, y as usize, channel);
+                        assert_eq!(*sample, expected, "{} at {},{}", name, x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_real_image() {
+        let bytes = std::fs::read("assets/res.png").unwrap();
+        let image = Png::try_from(bytes.as_ref()).unwrap().image().unwrap();
+        assert_eq!(image.color_type(), ColorType::Rgba);
+        assert_eq!(
+            image.samples().len(),
+            (image.width() * image.height()) as usize * image.channels()
+        );
+    }
+
+    #[test]
+    fn test_damaged_image_data() {
+        let png = load("rgb8.png");
+        let ihdr = png.chunk_by_type("IHDR").unwrap();
+        let idat = png.chunk_by_type("IDAT").unwrap();
+        let rebuild = |data: Vec<u8>| {
+            Png::from_chunks(vec![
+                Chunk::new(*ihdr.chunk_type(), ihdr.data().to_vec()),
+                Chunk::new(ChunkType::from_str("IDAT").unwrap(), data),
+            ])
+        };
+
+        let mut truncated = idat.data().to_vec();
+        truncated.truncate(truncated.len() / 2);
+        assert!(Image::decode(&rebuild(truncated)).is_err());
+
+        // an unknown filter type on the first scanline
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(idat.data())
+            .read_to_end(&mut inflated)
+            .unwrap();
+        inflated[0] = 5;
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &inflated).unwrap();
+        assert!(matches!(
+            Image::decode(&rebuild(encoder.finish().unwrap())),
+            Err(PngError::InvalidChunkData {
+                chunk_type: "IDAT",
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            Image::decode(&Png::from_chunks(vec![Chunk::new(
+                *ihdr.chunk_type(),
+                ihdr.data().to_vec()
+            )])),
+            Err(PngError::ChunkNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_size_limit() {
+        // 10^10 pixels of 1 bit would take 20 GB as samples
+        let ihdr = Ihdr {
+            width: 100_000,
+            height: 100_000,
+            bit_depth: 1,
+            color_type: ColorType::Grayscale,
+            compression: 0,
+            filter: 0,
+            interlace: 0,
+        };
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+        ]);
+        assert!(matches!(
+            png.image(),
+            Err(PngError::ImageTooLarge {
+                limit: Image::DEFAULT_LIMIT
+            })
+        ));
+
+        let png = load("rgb8.png");
+        let size = 32 * 32 * 3 * 2;
+        assert!(png.image_with_limit(size).is_ok());
+        assert!(matches!(
+            png.image_with_limit(size - 1),
+            Err(PngError::ImageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let strategies = Filter::ALL
+            .iter()
+            .map(|filter| FilterStrategy::Fixed(*filter))
+            .chain([FilterStrategy::Adaptive]);
+        for strategy in strategies {
+            for name in [
+                "gray1.png",
+                "palette2-interlaced.png",
+                "rgb16.png",
+                "rgba8-interlaced.png",
+            ] {
+                let image = load(name).image().unwrap();
+                let options = IdatOptions {
+                    filter: strategy,
+                    ..Default::default()
+                };
+                let mut encoded = load(name);
+                encoded.replace_image(&image, &options).unwrap();
+                assert_eq!(encoded.image().unwrap(), image, "{} {:?}", name, strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_replace_image() {
+        let mut png = load("rgb8.png");
+        let text = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Title\0x".to_vec());
+        png.insert_chunk(text, crate::ChunkPosition::AfterIhdr)
+            .unwrap();
+        let mut image = png.image().unwrap();
+        image.pixel_mut(3, 4).copy_from_slice(&[255, 0, 255]);
+
+        let options = IdatOptions {
+            level: 9,
+            chunk_size: 100,
+            ..Default::default()
+        };
+        png.replace_image(&image, &options).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types[..2], ["IHDR", "tEXt"]);
+        assert_eq!(types.last().unwrap(), "IEND");
+        assert!(types[2..types.len() - 1]
+            .iter()
+            .all(|chunk_type| chunk_type == "IDAT"));
+        assert!(png
+            .chunks_by_type("IDAT")
+            .all(|chunk| chunk.length() <= 100));
+        assert_eq!(png.image().unwrap().pixel(3, 4), [255, 0, 255]);
+
+        image.pixel_mut(0, 0)[0] = 256;
+        assert!(png.replace_image(&image, &options).is_err());
+    }
+
+    #[test]
+    fn test_replace_image_failure_leaves_png_unchanged() {
+        let mut png = load("rgb8.png");
+        let image = load("gray1.png").image().unwrap();
+        png.remove_where(|_, chunk| chunk.chunk_type().to_string() == "IDAT");
+        let before = png.as_bytes();
+
+        assert!(png.replace_image(&image, &Default::default()).is_err());
+        assert_eq!(png.as_bytes(), before);
+    }
+}
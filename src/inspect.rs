@@ -0,0 +1,172 @@
+use crate::chunk::{chunk_header, find_chunk, Chunk, ParseMode, MAX_RESYNC_BYTES};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, Result};
+
+/// A chunk where it was found in the file, with the crc stored after it.
+#[derive(Debug)]
+pub struct ChunkRecord {
+    /// Index of the chunk in the file, from 0.
+    pub index: usize,
+    /// Offset in bytes of the start of the chunk in the file.
+    pub offset: u64,
+    pub chunk: Chunk,
+    /// Crc read from the file, which may not match the chunk.
+    pub crc: u32,
+}
+
+impl ChunkRecord {
+    pub fn crc_valid(&self) -> bool {
+        self.chunk.crc() == self.crc
+    }
+
+    /// The chunk as stored in the file, with its stored crc.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.chunk.as_bytes();
+        let crc = bytes.len() - 4;
+        bytes[crc..].copy_from_slice(&self.crc.to_be_bytes());
+        bytes
+    }
+}
+
+/// The chunks found by [`inspect`] and the invalid bytes it skipped.
+#[derive(Debug)]
+pub struct Inspection {
+    pub chunks: Vec<ChunkRecord>,
+    /// Bytes holding no chunk, skipped with [`ParseMode::Resync`].
+    pub diagnostics: Vec<PngError>,
+}
+
+/// Lists the chunks of a png file where they are, for tools showing its
+/// layout. Unlike [`Png::parse_with`], a wrong crc is not an error but
+/// recorded with the chunk. Other damage stops with an error, unless `mode`
+/// is [`ParseMode::Resync`].
+pub fn inspect(bytes: &[u8], mode: ParseMode) -> Result<Inspection> {
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return Err(PngError::InvalidPngFile);
+    }
+
+    let mut chunks = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut pos = Png::STANDARD_HEADER.len();
+    let mut resync_budget = MAX_RESYNC_BYTES;
+    while pos < bytes.len() {
+        match chunk_header(bytes, pos) {
+            Some((chunk_type, length))
+                if pos + length + Chunk::CHUNK_METADATA_LENGTH <= bytes.len() =>
+            {
+                let end = pos + length + Chunk::CHUNK_METADATA_LENGTH;
+                chunks.push(ChunkRecord {
+                    index: chunks.len(),
+                    offset: pos as u64,
+                    chunk: Chunk::new(chunk_type, bytes[pos + 8..end - 4].to_vec()),
+                    crc: u32::from_be_bytes(bytes[end - 4..end].try_into().expect("4 bytes")),
+                });
+                pos = end;
+                continue;
+            }
+            _ => {}
+        }
+
+        let error = header_error(&bytes[pos..]).in_chunk(
+            chunks.len(),
+            pos as u64,
+            chunk_type_at(bytes, pos),
+        );
+        if mode != ParseMode::Resync {
+            return Err(error);
+        }
+        diagnostics.push(error);
+        match find_chunk(bytes, pos + 1, &mut resync_budget) {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+
+    Ok(Inspection {
+        chunks,
+        diagnostics,
+    })
+}
+
+/// Why no chunk starts at the beginning of `rest`.
+fn header_error(rest: &[u8]) -> PngError {
+    if rest.len() < Chunk::CHUNK_METADATA_LENGTH {
+        return PngError::ShortChunk;
+    }
+    match crate::utils::segment4(&rest[4..8]).and_then(ChunkType::try_from) {
+        Ok(_) => PngError::ShortChunk,
+        Err(error) => error,
+    }
+}
+
+fn chunk_type_at(bytes: &[u8], pos: usize) -> Option<ChunkType> {
+    let bytes = bytes.get(pos + 4..pos + 8)?;
+    ChunkType::try_from(crate::utils::segment4(bytes).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::critical::Iend;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("RuSt", b"hidden message"),
+            Iend.to_chunk(),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_layout() {
+        let inspection = inspect(&testing_bytes(), ParseMode::Strict).unwrap();
+        let offsets: Vec<u64> = inspection
+            .chunks
+            .iter()
+            .map(|record| record.offset)
+            .collect();
+        assert_eq!(offsets, [8, 33, 59]);
+        assert!(inspection.chunks.iter().all(ChunkRecord::crc_valid));
+        assert!(inspection.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_wrong_crc_is_recorded() {
+        let mut bytes = testing_bytes();
+        bytes[33 + 8 + 14] ^= 0xff;
+
+        let inspection = inspect(&bytes, ParseMode::Strict).unwrap();
+        assert_eq!(inspection.chunks.len(), 3);
+        assert!(!inspection.chunks[1].crc_valid());
+        assert_eq!(inspection.chunks[1].as_bytes(), bytes[33..59]);
+    }
+
+    #[test]
+    fn test_damaged_header() {
+        let mut bytes = testing_bytes();
+        bytes[33..37].copy_from_slice(&1000_u32.to_be_bytes());
+        assert!(matches!(
+            inspect(&bytes, ParseMode::Strict),
+            Err(PngError::Chunk {
+                index: 1,
+                offset: 33,
+                ..
+            })
+        ));
+
+        let inspection = inspect(&bytes, ParseMode::Resync).unwrap();
+        let types: Vec<String> = inspection
+            .chunks
+            .iter()
+            .map(|record| record.chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IEND"]);
+        assert_eq!(inspection.diagnostics.len(), 1);
+    }
+}
@@ -0,0 +1,59 @@
+//! pngme is a small library to parse, edit and serialize the chunk stream
+//! of PNG files, used by the `pngme` CLI to hide messages in them.
+//!
+//! ```
+//! use pngme::{Chunk, ChunkType, Png};
+//! use std::str::FromStr;
+//!
+//! let mut png = Png::from_chunks(Vec::new());
+//! png.append_chunk(Chunk::new(ChunkType::from_str("RuSt")?, b"secret".to_vec()));
+//!
+//! let png = Png::try_from(png.as_bytes().as_ref())?;
+//! assert_eq!(png.chunk_by_type("RuSt").unwrap().data(), b"secret");
+//! # Ok::<(), pngme::PngError>(())
+//! ```
+//!
+//! Cargo features:
+//! - `cli` (default): builds the `pngme` binary.
+//! - `download` (default): enables [`download`] to fetch png files over http.
+//! - `crypto` (default): enables `crypto` to encrypt hidden messages with a
+//!   passphrase or to age recipients, and `signature` to sign them.
+//! - `zstd` and `brotli` (default): more algorithms for [`compression`].
+
+pub mod analyze;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_type;
+pub mod compression;
+pub mod critical;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod error;
+pub mod fs;
+pub mod image;
+pub mod inspect;
+pub mod lsb;
+pub mod png;
+pub mod repair;
+#[cfg(feature = "crypto")]
+pub mod signature;
+pub mod split;
+pub mod stream;
+pub mod strip;
+pub mod text;
+mod utils;
+pub mod validate;
+
+pub use crate::chunk::{Chunk, ChunkIterator, ParseMode};
+pub use crate::chunk_type::ChunkType;
+pub use crate::critical::{ColorType, Iend, Ihdr, Plte, Rgb};
+pub use crate::error::{PngError, Result};
+pub use crate::image::{Filter, FilterStrategy, IdatOptions, Image};
+pub use crate::lsb::{Channels, LsbOptions};
+pub use crate::png::{ChunkPosition, ParseOptions, Parsed, Png};
+pub use crate::stream::{ChunkReader, ChunkWriter};
+pub use crate::strip::{StripPolicy, StripScope};
+pub use crate::text::{TextEntry, TextKind};
+pub use crate::validate::{Finding, Severity};
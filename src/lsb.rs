@@ -0,0 +1,383 @@
+//! Hides a payload in the least significant bits of the pixels, where it
+//! survives anything keeping the pixels, such as removing every ancillary
+//! chunk or re-encoding the image data losslessly.
+//!
+//! The payload is stored after a header of magic and length, one bit at a
+//! time from the most significant bit of each byte, into the `bits` lowest
+//! bits of the selected samples. Pixels are visited row by row, or in an
+//! order shuffled from a key.
+
+use std::str::FromStr;
+
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::critical::ColorType;
+use crate::image::Image;
+use crate::{PngError, Result};
+
+/// Which channels of each pixel hold the payload. Grayscale images use their
+/// gray channel if any of red, green or blue is selected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Channels {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl Channels {
+    /// The color channels, leaving alpha alone: changing the alpha of fully
+    /// transparent pixels is easy to notice.
+    pub const COLOR: Channels = Channels {
+        red: true,
+        green: true,
+        blue: true,
+        alpha: false,
+    };
+
+    /// Indexes of the selected samples of a pixel of `color_type`.
+    fn indexes(&self, color_type: ColorType) -> Vec<usize> {
+        let gray = self.red || self.green || self.blue;
+        let selected: &[bool] = match color_type {
+            ColorType::Grayscale => &[gray],
+            ColorType::GrayscaleAlpha => &[gray, self.alpha],
+            ColorType::Rgb => &[self.red, self.green, self.blue],
+            ColorType::Rgba => &[self.red, self.green, self.blue, self.alpha],
+            ColorType::Indexed => &[],
+        };
+        (0..selected.len()).filter(|&i| selected[i]).collect()
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels::COLOR
+    }
+}
+
+impl FromStr for Channels {
+    type Err = PngError;
+
+    /// Letters among `r`, `g`, `b` and `a`, e.g. `rgb` or `a`.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(PngError::InvalidChannels);
+        }
+        let mut channels = Channels {
+            red: false,
+            green: false,
+            blue: false,
+            alpha: false,
+        };
+        for letter in s.chars() {
+            let channel = match letter {
+                'r' => &mut channels.red,
+                'g' => &mut channels.green,
+                'b' => &mut channels.blue,
+                'a' => &mut channels.alpha,
+                _ => return Err(PngError::InvalidChannels),
+            };
+            if *channel {
+                return Err(PngError::InvalidChannels);
+            }
+            *channel = true;
+        }
+        Ok(channels)
+    }
+}
+
+/// Where [`embed`] puts the payload, which [`extract`] needs again.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LsbOptions {
+    /// Low bits of each sample replaced, from 1 to 8 and at most the bit
+    /// depth of the image.
+    pub bits: u8,
+    pub channels: Channels,
+    /// Shuffles the order pixels are visited in, so the payload is spread
+    /// over the whole image and can't be read without the key. Wiped from
+    /// memory when dropped.
+    pub key: Option<Zeroizing<Vec<u8>>>,
+}
+
+impl Default for LsbOptions {
+    fn default() -> Self {
+        LsbOptions {
+            bits: 1,
+            channels: Channels::default(),
+            key: None,
+        }
+    }
+}
+
+pub const MAGIC: [u8; 4] = *b"PMLB";
+/// magic and payload length
+pub const HEADER_LENGTH: usize = 4 + 4;
+
+/// Chunk type the encryption of a payload in the pixels is bound to, and the
+/// one `decode` shows it with. No chunk of this type is written.
+pub const CHUNK_TYPE: &str = "pmLB";
+
+/// Number of payload bytes `image` can hold with `options`, after the
+/// header.
+pub fn capacity(image: &Image, options: &LsbOptions) -> Result<usize> {
+    let channels = check(image, options)?;
+    let slots = image.samples().len() / image.channels() * channels.len();
+    Ok((slots * options.bits as usize / 8).saturating_sub(HEADER_LENGTH))
+}
+
+/// Writes `payload` in the low bits of the pixels of `image`.
+pub fn embed(image: &mut Image, payload: &[u8], options: &LsbOptions) -> Result<()> {
+    let capacity = capacity(image, options)?;
+    if payload.len() > capacity || payload.len() > u32::MAX as usize {
+        return Err(PngError::PayloadTooLarge { capacity });
+    }
+
+    let mut data = Vec::with_capacity(HEADER_LENGTH + payload.len());
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    data.extend_from_slice(payload);
+    let mut bits = data
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |position| (byte >> position) & 1));
+
+    let slots = slots(image, options)?;
+    let samples = image.samples_mut();
+    'slots: for slot in slots {
+        for position in (0..options.bits).rev() {
+            let Some(bit) = bits.next() else {
+                break 'slots;
+            };
+            samples[slot] = samples[slot] & !(1 << position) | (bit as u16) << position;
+        }
+    }
+    Ok(())
+}
+
+/// Reads back the payload written by [`embed`] with the same options.
+pub fn extract(image: &Image, options: &LsbOptions) -> Result<Vec<u8>> {
+    let capacity = capacity(image, options)?;
+    let samples = image.samples();
+    let mut bits = slots(image, options)?.flat_map(|slot| {
+        (0..options.bits)
+            .rev()
+            .map(move |position| (samples[slot] >> position) as u8 & 1)
+    });
+    let mut read = |length: usize| -> Vec<u8> {
+        (0..length)
+            .map(|_| bits.by_ref().take(8).fold(0, |byte, bit| byte << 1 | bit))
+            .collect()
+    };
+
+    let header = read(HEADER_LENGTH);
+    let length = u32::from_be_bytes(header[4..].try_into().expect("4 bytes")) as usize;
+    if header[..4] != MAGIC || length > capacity {
+        return Err(PngError::HiddenPayloadNotFound);
+    }
+    Ok(read(length))
+}
+
+/// Checks `options` against `image` and returns the indexes of the selected
+/// samples of each pixel.
+fn check(image: &Image, options: &LsbOptions) -> Result<Vec<usize>> {
+    if image.color_type() == ColorType::Indexed {
+        return Err(PngError::LsbUnsupported(
+            "the low bits of palette indexes are unrelated colors",
+        ));
+    }
+    if options.bits == 0 || options.bits > 8 || options.bits > image.bit_depth() {
+        return Err(PngError::LsbUnsupported(
+            "bits per channel must be 1 to 8 and at most the bit depth",
+        ));
+    }
+    let channels = options.channels.indexes(image.color_type());
+    if channels.is_empty() {
+        return Err(PngError::LsbUnsupported(
+            "none of the selected channels is in the image",
+        ));
+    }
+    Ok(channels)
+}
+
+/// Indexes of the samples holding the payload, in order.
+fn slots<'a>(image: &Image, options: &'a LsbOptions) -> Result<impl Iterator<Item = usize> + 'a> {
+    let channels = check(image, options)?;
+    let pixels = image.samples().len() / image.channels();
+    let samples_per_pixel = image.channels();
+    Ok(
+        walk(pixels, options.key.as_ref().map(|key| key.as_slice())).flat_map(move |pixel| {
+            channels
+                .clone()
+                .into_iter()
+                .map(move |channel| pixel * samples_per_pixel + channel)
+        }),
+    )
+}
+
+/// The pixels in order, or shuffled with `key`.
+fn walk(pixels: usize, key: Option<&[u8]>) -> std::vec::IntoIter<usize> {
+    let mut order: Vec<usize> = (0..pixels).collect();
+    if let Some(key) = key {
+        // Fisher-Yates, drawing from the key stream
+        let mut stream = KeyStream::new(key);
+        for i in (1..pixels).rev() {
+            let j = ((stream.next_u64() as u128 * (i as u128 + 1)) >> 64) as usize;
+            order.swap(i, j);
+        }
+    }
+    order.into_iter()
+}
+
+/// SHA-256 in counter mode over a hash of the key.
+struct KeyStream {
+    seed: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    position: usize,
+}
+
+impl KeyStream {
+    fn new(key: &[u8]) -> KeyStream {
+        let seed = Sha256::new()
+            .chain_update(b"pngme lsb walk")
+            .chain_update(key)
+            .finalize()
+            .into();
+        KeyStream {
+            seed,
+            counter: 0,
+            block: [0; 32],
+            position: 32,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.position == self.block.len() {
+            self.block = Sha256::new()
+                .chain_update(self.seed)
+                .chain_update(self.counter.to_be_bytes())
+                .finalize()
+                .into();
+            self.counter += 1;
+            self.position = 0;
+        }
+        let bytes = &self.block[self.position..self.position + 8];
+        self.position += 8;
+        u64::from_be_bytes(bytes.try_into().expect("8 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use crate::strip::StripPolicy;
+
+    fn load(name: &str) -> Png {
+        let bytes = std::fs::read(std::path::Path::new("assets").join(name)).unwrap();
+        Png::try_from(bytes.as_ref()).unwrap()
+    }
+
+    fn keyed(bits: u8, channels: &str, key: &str) -> LsbOptions {
+        LsbOptions {
+            bits,
+            channels: channels.parse().unwrap(),
+            key: Some(Zeroizing::new(key.as_bytes().to_vec())),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = load("res.png").image().unwrap();
+        for options in [
+            LsbOptions::default(),
+            keyed(2, "rgba", "secret"),
+            keyed(8, "a", "other secret"),
+        ] {
+            let mut image = original.clone();
+            embed(&mut image, b"hidden message", &options).unwrap();
+            assert_ne!(image, original);
+            assert_eq!(extract(&image, &options).unwrap(), b"hidden message");
+
+            let max_change = (1 << options.bits) - 1;
+            assert!(original
+                .samples()
+                .iter()
+                .zip(image.samples())
+                .all(|(a, b)| a.abs_diff(*b) <= max_change));
+        }
+    }
+
+    #[test]
+    fn test_wrong_options() {
+        let mut image = load("res.png").image().unwrap();
+        embed(&mut image, b"hidden message", &keyed(1, "rgb", "secret")).unwrap();
+        assert!(matches!(
+            extract(&image, &keyed(1, "rgb", "wrong")),
+            Err(PngError::HiddenPayloadNotFound)
+        ));
+        assert!(matches!(
+            extract(&image, &LsbOptions::default()),
+            Err(PngError::HiddenPayloadNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_survives_stripping() {
+        let mut png = load("res.png");
+        let mut image = png.image().unwrap();
+        let options = keyed(1, "rgb", "secret");
+        embed(&mut image, b"hidden message", &options).unwrap();
+        png.replace_image(&image, &Default::default()).unwrap();
+
+        let mut png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        png.strip(&StripPolicy::default());
+        let image = png.image().unwrap();
+        assert_eq!(extract(&image, &options).unwrap(), b"hidden message");
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut image = load("res.png").image().unwrap();
+        let pixels = (image.width() * image.height()) as usize;
+        let options = LsbOptions::default();
+        let capacity = capacity(&image, &options).unwrap();
+        assert_eq!(capacity, pixels * 3 / 8 - HEADER_LENGTH);
+
+        assert!(embed(&mut image, &vec![0; capacity], &options).is_ok());
+        assert!(matches!(
+            embed(&mut image, &vec![0; capacity + 1], &options),
+            Err(PngError::PayloadTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_images() {
+        let indexed = Png::try_from(
+            std::fs::read("assets/conformance/palette8.png")
+                .unwrap()
+                .as_ref(),
+        )
+        .unwrap()
+        .image()
+        .unwrap();
+        assert!(matches!(
+            capacity(&indexed, &LsbOptions::default()),
+            Err(PngError::LsbUnsupported(_))
+        ));
+
+        let gray = Png::try_from(
+            std::fs::read("assets/conformance/gray4.png")
+                .unwrap()
+                .as_ref(),
+        )
+        .unwrap()
+        .image()
+        .unwrap();
+        assert!(capacity(&gray, &keyed(4, "r", "")).is_ok());
+        assert!(capacity(&gray, &keyed(8, "r", "")).is_err());
+        assert!(capacity(&gray, &keyed(1, "a", "")).is_err());
+        assert!("rgbx".parse::<Channels>().is_err());
+        assert!("rr".parse::<Channels>().is_err());
+    }
+}
@@ -1,21 +1,32 @@
 use std::path::PathBuf;
 
-use clap::{App, Arg, ArgGroup};
-use crate::commands::{encode, decode, remove, print};
+use clap::{App, Arg, ArgGroup, ArgMatches};
+use crate::commands::{encode, decode, remove, print, info, capacity, analyze, keygen, keygen_signing, lint, strip, reencode, repair, read_passphrase, read_lsb_key, verify, text_add, text_get, text_list, text_remove, text_set};
 
-mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
-mod utils;
-mod fs;
-mod error;
-mod download;
+mod format;
 
-pub use crate::error::{PngError, Result};
+use commands::{read_payload, DecodeOptions, Method, PrintOptions, Decryption, EncodeOptions, Encoding, Encryption, Selector};
+use regex::Regex;
 
-fn main() -> Result<()> {
+use pngme::capacity::{EncryptionKind, Processing};
+use pngme::fs::WriteOptions;
+use pngme::{ChunkPosition, IdatOptions, LsbOptions, StripPolicy, StripScope, TextEntry, TextKind};
+pub use pngme::{PngError, Result};
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {}", error);
+        let mut source = std::error::Error::source(&error);
+        while let Some(cause) = source {
+            eprintln!("  caused by: {}", cause);
+            source = cause.source();
+        }
+        std::process::exit(error.exit_code());
+    }
+}
+
+fn run() -> Result<()> {
 
     let matches = App::new("pngme")
         .version("0.2.0")
@@ -23,6 +34,14 @@ fn main() -> Result<()> {
         .about("A tool to encode and decode secret data in PNG files")
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(Arg::new("backup")
+            .help("Keep the previous version of a modified file as <file>.bak")
+            .global(true)
+            .long("backup"))
+        .arg(Arg::new("preserve_mtime")
+            .help("Keep the modification time of a modified file")
+            .global(true)
+            .long("preserve-mtime"))
         .subcommand(App::new("encode")
             .about("Encode a message in a PNG file")
             .arg(Arg::new("file_path")
@@ -36,17 +55,56 @@ fn main() -> Result<()> {
                 .required_unless("file_path")
                 .long("url"))
             .arg(Arg::new("chunk_type")
-                .help("The chunk type to use")
-                .required(true)
+                .help("The chunk type to use, left out with --method lsb")
                 .takes_value(true))
             .arg(Arg::new("message")
                 .help("The message to encode")
-                .required(true)
                 .takes_value(true))
+            .arg(Arg::new("input")
+                .help("Read the message from this file instead, - for stdin")
+                .takes_value(true)
+                .conflicts_with("message")
+                .long("input"))
+            .arg(encoding_arg("How the message is written"))
             .arg(Arg::new("output_file")
                 .help("The path to the output file")
                 .required(false)
                 .takes_value(true))
+            .arg(Arg::new("position")
+                .help("Where to insert the chunk")
+                .takes_value(true)
+                .possible_values(["before-iend", "after-ihdr", "before-idat", "after-idat"])
+                .default_value("before-iend")
+                .long("position"))
+            .arg(Arg::new("index")
+                .help("Insert the chunk at this index instead")
+                .takes_value(true)
+                .conflicts_with("position")
+                .long("index"))
+            .arg(Arg::new("encrypt")
+                .help("Encrypt the message with a passphrase (read from PNGME_PASSPHRASE or prompted)")
+                .long("encrypt"))
+            .arg(Arg::new("recipient")
+                .help("Encrypt the message to this age public key (age1...), can be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with("encrypt")
+                .long("recipient"))
+            .arg(Arg::new("max_chunk_size")
+                .help("Split the message in chunks of at most this many bytes")
+                .takes_value(true)
+                .long("max-chunk-size"))
+            .arg(Arg::new("compress")
+                .help("Compress the message before embedding it")
+                .takes_value(true)
+                .possible_values(["deflate", "zstd", "brotli"])
+                .long("compress"))
+            .arg(Arg::new("sign")
+                .help("Sign the message and the image data with this signing key file")
+                .takes_value(true)
+                .long("sign"))
+            .args(method_args())
+            .arg(max_image_size_arg())
             .group(ArgGroup::new("type_path")
                 .args(&["file_path", "url"])
                 .required(true)))
@@ -57,11 +115,61 @@ fn main() -> Result<()> {
                 .takes_value(true)
                 .required(true)
                 .index(1))
-            .arg(Arg::new("chunk_type")
-                .help("The chunk type to use")
+            .args(selector_args(true))
+            .group(selector_group().required(false))
+            .arg(Arg::new("output")
+                .help("Write the message to this file, - for stdout, instead of printing the chunk")
+                .takes_value(true)
+                .long("output"))
+            .arg(encoding_arg("How to write the message"))
+            .arg(Arg::new("decrypt")
+                .help("Decrypt the message with a passphrase (read from PNGME_PASSPHRASE or prompted)")
+                .long("decrypt"))
+            .arg(Arg::new("identity")
+                .help("Decrypt the message with this age identity file, can be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with("decrypt")
+                .long("identity"))
+            .arg(Arg::new("decompress")
+                .help("Decompress the message, compressed by encode --compress")
+                .long("decompress"))
+            .arg(Arg::new("max_decompressed_size")
+                .help("Refuse to decompress the message past this many bytes (--decompress)")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("268435456")
+                .long("max-decompressed-size"))
+            .args(method_args())
+            .arg(max_image_size_arg())
+            .arg(parse_mode_arg()))
+        .subcommand(App::new("keygen")
+            .about("Create an age keypair to receive encrypted messages, or a signing key")
+            .arg(Arg::new("output_file")
+                .help("The path of the key file to create")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("sign")
+                .help("Create an Ed25519 signing key instead")
+                .long("sign")))
+        .subcommand(App::new("verify")
+            .about("Check the signatures of a PNG file against trusted public keys")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
                 .required(true)
+                .index(1))
+            .arg(Arg::new("trusted")
+                .help("A file of trusted public keys, one per line, can be repeated")
                 .takes_value(true)
-                .index(2)))
+                .multiple_occurrences(true)
+                .long("trusted"))
+            .arg(Arg::new("key")
+                .help("A trusted public key (ed25519:...), can be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .long("key")))
         .subcommand(App::new("remove")
             .about("Remove a chunk from a PNG file")
             .arg(Arg::new("file_path")
@@ -69,43 +177,578 @@ fn main() -> Result<()> {
                 .takes_value(true)
                 .required(true)
                 .index(1))
-            .arg(Arg::new("chunk_type")
-                .help("The chunk type to use")
+            .args(selector_args(true))
+            .group(selector_group()))
+        .subcommand(App::new("print")
+            .about("Print all of the chunks in a PNG file, or the selected ones")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
                 .required(true)
+                .index(1))
+            .args(selector_args(false))
+            .arg(Arg::new("chunks")
+                .help("Only show these chunk types, e.g. IHDR,tEXt")
                 .takes_value(true)
-                .index(2)))
-        .subcommand(App::new("print")
-            .about("Print all of the chunks in a PNG file")
+                .multiple_occurrences(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .long("chunks"))
+            .arg(Arg::new("format")
+                .help("Output format, the json and yaml schema is in docs/print.schema.json")
+                .takes_value(true)
+                .possible_values(["text", "json", "yaml", "table", "tree", "hexdump"])
+                .default_value("text")
+                .long("format"))
+            .arg(Arg::new("preview_length")
+                .help("Number of data bytes shown in previews")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("32")
+                .long("preview-length"))
+            .arg(parse_mode_arg()))
+        .subcommand(App::new("info")
+            .about("Print the header and palette of a PNG file")
             .arg(Arg::new("file_path")
                 .help("The path to the PNG file")
                 .takes_value(true)
                 .required(true)
                 .index(1)))
+        .subcommand(App::new("strip")
+            .about("Remove metadata from a PNG file, keeping the image")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("output_file")
+                .help("The path to the output file, the PNG file is rewritten otherwise")
+                .takes_value(true)
+                .index(2))
+            .arg(Arg::new("all_ancillary")
+                .help("Remove every chunk the image does not need (default)")
+                .long("all-ancillary"))
+            .arg(Arg::new("private_only")
+                .help("Remove only private chunks")
+                .conflicts_with("all_ancillary")
+                .long("private-only"))
+            .arg(Arg::new("keep_color")
+                .help("Keep the color space chunks (gAMA, cHRM, sRGB, iCCP)")
+                .long("keep-color"))
+            .arg(Arg::new("keep_text")
+                .help("Keep the text chunks with these keywords, comma separated or repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true)
+                .long("keep-text")))
+        .subcommand(App::new("reencode")
+            .about("Decode the image data and encode it again, e.g. to make it smaller")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("output_file")
+                .help("The path to the output file, the PNG file is rewritten otherwise")
+                .takes_value(true)
+                .index(2))
+            .arg(Arg::new("filter")
+                .help("Scanline filter, or the best one for each scanline")
+                .takes_value(true)
+                .possible_values(["none", "sub", "up", "average", "paeth", "adaptive"])
+                .default_value("adaptive")
+                .long("filter"))
+            .arg(Arg::new("level")
+                .help("Deflate level, from 0 (stored) to 9 (smallest)")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(u32).range(0..=9))
+                .default_value("9")
+                .long("level"))
+            .arg(Arg::new("idat_size")
+                .help("Maximum size of the data of each IDAT chunk")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("65536")
+                .long("idat-size"))
+            .arg(max_image_size_arg()))
+        .subcommand(App::new("capacity")
+            .about("Show how many bytes each embedding method can hide in a PNG file")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("channels")
+                .help("Channels holding the message with lsb, letters among r, g, b and a")
+                .takes_value(true)
+                .default_value("rgb")
+                .long("channels"))
+            .arg(Arg::new("compress")
+                .help("Count the header of compressed messages")
+                .takes_value(true)
+                .possible_values(["deflate", "zstd", "brotli"])
+                .long("compress"))
+            .arg(Arg::new("encrypt")
+                .help("Count the header of messages encrypted with a passphrase")
+                .long("encrypt"))
+            .arg(Arg::new("recipient")
+                .help("Count the header of messages encrypted to this age public key, can be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with("encrypt")
+                .long("recipient"))
+            .arg(max_image_size_arg()))
+        .subcommand(App::new("analyze")
+            .about("Look for data hidden in a PNG file and score how suspicious it is")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("threshold")
+                .help("Fail if the suspicion score, from 0 to 100, reaches this value")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(u32).range(1..=100))
+                .long("threshold"))
+            .arg(max_image_size_arg()))
+        .subcommand(App::new("repair")
+            .about("Repair a damaged PNG file and report every fix")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("output_file")
+                .help("The path to the output file, the PNG file is rewritten otherwise")
+                .takes_value(true)
+                .index(2))
+            .arg(Arg::new("dry_run")
+                .help("Only report the fixes, write nothing")
+                .long("dry-run")))
+        .subcommand(App::new("lint")
+            .about("Check a PNG file against the PNG specification")
+            .arg(Arg::new("file_path")
+                .help("The path to the PNG file")
+                .takes_value(true)
+                .required(true)
+                .index(1))
+            .arg(Arg::new("strict")
+                .help("Fail on warnings too")
+                .long("strict")))
+        .subcommand(App::new("text")
+            .about("List, read, add, update and delete text chunks (tEXt, zTXt, iTXt)")
+            .subcommand_required(true)
+            .subcommand(App::new("list")
+                .about("List every text entry")
+                .arg(Arg::new("file_path")
+                    .help("The path to the PNG file")
+                    .takes_value(true)
+                    .required(true)
+                    .index(1)))
+            .subcommand(App::new("get")
+                .about("Print the text of a keyword")
+                .arg(Arg::new("file_path")
+                    .help("The path to the PNG file")
+                    .takes_value(true)
+                    .required(true)
+                    .index(1))
+                .arg(Arg::new("keyword")
+                    .help("The keyword of the entry")
+                    .takes_value(true)
+                    .required(true)
+                    .index(2)))
+            .subcommand(text_entry_args(App::new("add")
+                .about("Add a text entry")))
+            .subcommand(text_entry_args(App::new("set")
+                .about("Add a text entry, replacing the entries with the same keyword")))
+            .subcommand(App::new("remove")
+                .about("Remove every text entry of a keyword")
+                .arg(Arg::new("file_path")
+                    .help("The path to the PNG file")
+                    .takes_value(true)
+                    .required(true)
+                    .index(1))
+                .arg(Arg::new("keyword")
+                    .help("The keyword of the entries")
+                    .takes_value(true)
+                    .required(true)
+                    .index(2))))
         .get_matches();
 
+    let write = WriteOptions {
+        backup: matches.is_present("backup"),
+        preserve_mtime: matches.is_present("preserve_mtime"),
+    };
+
     match matches.subcommand() {
         Some(("encode", encode_matches)) => {
             let path: Option<&str> = encode_matches.value_of("file_path");
             let url: Option<&str> = encode_matches.value_of("url");
-            let chunk_type: &str = encode_matches.value_of("chunk_type").unwrap();
-            let message: &str = encode_matches.value_of("message").unwrap();
-            let output_file: Option<PathBuf> = encode_matches.value_of("output_file").map(|s| s.into());
-            encode(&path, &url, chunk_type, message, &output_file)?;
+            let encoding: Encoding = encode_matches.value_of("encoding").unwrap().parse()?;
+            let input: Option<PathBuf> = encode_matches.value_of("input").map(|s| s.into());
+            // --method lsb takes no chunk type, the arguments start with the message
+            let lsb = encode_matches.value_of("method") == Some("lsb");
+            let mut positionals = ["chunk_type", "message", "output_file"].into_iter().filter_map(|name| encode_matches.value_of(name));
+            let chunk_type: Option<&str> = if lsb { None } else { positionals.next() };
+            if !lsb && chunk_type.is_none() {
+                return Err(PngError::ChunkTypeNotSpecified);
+            }
+            let message: Option<&str> = if input.is_some() { None } else { positionals.next() };
+            let output_file: Option<PathBuf> = positionals.next().map(|s| s.into());
+            if positionals.next().is_some() {
+                return Err(PngError::LsbUnsupported("a chunk type can't be given"));
+            }
+            let message = read_payload(message, input.as_deref(), encoding)?;
+            let position: ChunkPosition = match encode_matches.value_of("index") {
+                Some(index) => index.parse()?,
+                None => encode_matches.value_of("position").unwrap().parse()?,
+            };
+            let encryption = if encode_matches.is_present("encrypt") {
+                Some(Encryption::Passphrase(read_passphrase(true)?))
+            } else {
+                encode_matches.values_of("recipient")
+                    .map(|recipients| Encryption::Recipients(recipients.map(String::from).collect()))
+            };
+            let options = EncodeOptions {
+                method: method(encode_matches, true)?,
+                position,
+                compression: match encode_matches.value_of("compress") {
+                    Some(compression) => Some(compression.parse()?),
+                    None => None,
+                },
+                encryption,
+                signing_key: encode_matches.value_of("sign").map(PathBuf::from),
+                max_chunk_size: match encode_matches.value_of("max_chunk_size") {
+                    Some(size) => Some(size.parse().map_err(|_| PngError::InvalidChunkSize)?),
+                    None => None,
+                },
+                max_image_size: encode_matches.get_one::<u64>("max_image_size").copied(),
+                write,
+            };
+            encode(&path, &url, chunk_type, &message, &output_file, &options)?;
         }
         Some(("decode", decode_matches)) => {
             let path: PathBuf = decode_matches.value_of("file_path").unwrap().into();
-            decode(&path, decode_matches.value_of("chunk_type").unwrap())?;
+            let decryption = if decode_matches.is_present("decrypt") {
+                Some(Decryption::Passphrase(read_passphrase(false)?))
+            } else {
+                decode_matches.values_of("identity")
+                    .map(|paths| Decryption::IdentityFiles(paths.map(PathBuf::from).collect()))
+            };
+            let method = method(decode_matches, false)?;
+            if method == Method::Chunk && !decode_matches.is_present("selector") {
+                return Err(PngError::ChunkTypeNotSpecified);
+            }
+            let options = DecodeOptions {
+                method,
+                decryption,
+                decompress: decode_matches.is_present("decompress"),
+                max_decompressed_size: decode_matches.get_one::<u64>("max_decompressed_size").copied(),
+                max_image_size: decode_matches.get_one::<u64>("max_image_size").copied(),
+                output: decode_matches.value_of("output").map(PathBuf::from),
+                encoding: decode_matches.value_of("encoding").unwrap().parse()?,
+                parse_mode: decode_matches.value_of("parse_mode").unwrap().parse()?,
+            };
+            decode(&path, &selector(decode_matches)?, &options)?;
         }
         Some(("remove", remove_matches)) => {
             let path: PathBuf = remove_matches.value_of("file_path").unwrap().into();
-            remove(&path, remove_matches.value_of("chunk_type").unwrap())?;
+            remove(&path, &selector(remove_matches)?, &write)?;
         }
         Some(("print", print_matches)) => {
             let path: PathBuf = print_matches.value_of("file_path").unwrap().into();
-            print(&path)?;
+            let options = PrintOptions {
+                format: print_matches.value_of("format").unwrap().parse()?,
+                parse_mode: print_matches.value_of("parse_mode").unwrap().parse()?,
+                preview_length: *print_matches.get_one::<usize>("preview_length").unwrap(),
+            };
+            print(&path, &selector(print_matches)?, &options)?;
+        }
+        Some(("info", info_matches)) => {
+            let path: PathBuf = info_matches.value_of("file_path").unwrap().into();
+            info(&path)?;
+        }
+        Some(("keygen", keygen_matches)) => {
+            let path: PathBuf = keygen_matches.value_of("output_file").unwrap().into();
+            if keygen_matches.is_present("sign") {
+                keygen_signing(&path)?;
+            } else {
+                keygen(&path)?;
+            }
+        }
+        Some(("verify", verify_matches)) => {
+            let path: PathBuf = verify_matches.value_of("file_path").unwrap().into();
+            let trusted: Vec<PathBuf> = verify_matches.values_of("trusted").unwrap_or_default().map(PathBuf::from).collect();
+            let keys: Vec<String> = verify_matches.values_of("key").unwrap_or_default().map(String::from).collect();
+            verify(&path, &trusted, &keys)?;
+        }
+        Some(("strip", strip_matches)) => {
+            let path: PathBuf = strip_matches.value_of("file_path").unwrap().into();
+            let output_file: Option<PathBuf> = strip_matches.value_of("output_file").map(|s| s.into());
+            let policy = StripPolicy {
+                scope: if strip_matches.is_present("private_only") {
+                    StripScope::PrivateOnly
+                } else {
+                    StripScope::AllAncillary
+                },
+                keep_color: strip_matches.is_present("keep_color"),
+                keep_text: strip_matches.values_of("keep_text")
+                    .map(|keywords| keywords.map(String::from).collect())
+                    .unwrap_or_default(),
+            };
+            strip(&path, output_file.as_deref(), &policy, &write)?;
         }
+        Some(("reencode", reencode_matches)) => {
+            let path: PathBuf = reencode_matches.value_of("file_path").unwrap().into();
+            let output_file: Option<PathBuf> = reencode_matches.value_of("output_file").map(|s| s.into());
+            let options = IdatOptions {
+                filter: reencode_matches.value_of("filter").unwrap().parse()?,
+                level: *reencode_matches.get_one::<u32>("level").unwrap(),
+                chunk_size: *reencode_matches.get_one::<usize>("idat_size").unwrap(),
+            };
+            reencode(&path, output_file.as_deref(), &options, *reencode_matches.get_one::<u64>("max_image_size").unwrap(), &write)?;
+        }
+        Some(("capacity", capacity_matches)) => {
+            let path: PathBuf = capacity_matches.value_of("file_path").unwrap().into();
+            let encryption = if capacity_matches.is_present("encrypt") {
+                Some(EncryptionKind::Passphrase)
+            } else {
+                capacity_matches.values_of("recipient").map(|recipients| EncryptionKind::Recipients(recipients.count()))
+            };
+            let processing = Processing {
+                compression: match capacity_matches.value_of("compress") {
+                    Some(compression) => Some(compression.parse()?),
+                    None => None,
+                },
+                encryption,
+            };
+            capacity(&path, capacity_matches.value_of("channels").unwrap().parse()?, &processing, *capacity_matches.get_one::<u64>("max_image_size").unwrap())?;
+        }
+        Some(("repair", repair_matches)) => {
+            let path: PathBuf = repair_matches.value_of("file_path").unwrap().into();
+            let output_file: Option<PathBuf> = repair_matches.value_of("output_file").map(|s| s.into());
+            repair(&path, output_file.as_deref(), repair_matches.is_present("dry_run"), &write)?;
+        }
+        Some(("analyze", analyze_matches)) => {
+            let path: PathBuf = analyze_matches.value_of("file_path").unwrap().into();
+            analyze(&path, analyze_matches.get_one::<u32>("threshold").copied(), *analyze_matches.get_one::<u64>("max_image_size").unwrap())?;
+        }
+        Some(("lint", lint_matches)) => {
+            let path: PathBuf = lint_matches.value_of("file_path").unwrap().into();
+            lint(&path, lint_matches.is_present("strict"))?;
+        }
+        Some(("text", text_matches)) => match text_matches.subcommand() {
+            Some(("list", list_matches)) => {
+                let path: PathBuf = list_matches.value_of("file_path").unwrap().into();
+                text_list(&path)?;
+            }
+            Some(("get", get_matches)) => {
+                let path: PathBuf = get_matches.value_of("file_path").unwrap().into();
+                text_get(&path, get_matches.value_of("keyword").unwrap())?;
+            }
+            Some(("add", add_matches)) => {
+                let path: PathBuf = add_matches.value_of("file_path").unwrap().into();
+                text_add(&path, &text_entry(add_matches)?, &write)?;
+            }
+            Some(("set", set_matches)) => {
+                let path: PathBuf = set_matches.value_of("file_path").unwrap().into();
+                text_set(&path, &text_entry(set_matches)?, &write)?;
+            }
+            Some(("remove", remove_matches)) => {
+                let path: PathBuf = remove_matches.value_of("file_path").unwrap().into();
+                text_remove(&path, remove_matches.value_of("keyword").unwrap(), &write)?;
+            }
+            _ => unreachable!(),
+        },
         _ => unreachable!(), // If all subcommands are defined above, anything else is unreachable
     }
 
     Ok(())
 }
+
+fn parse_mode_arg() -> Arg<'static> {
+    Arg::new("parse_mode")
+        .help("How to read a damaged file: stop at the first invalid chunk, skip chunks with a bad crc, or resync on the next valid chunk")
+        .takes_value(true)
+        .possible_values(["strict", "skip-bad-crc", "resync"])
+        .default_value("strict")
+        .long("parse-mode")
+}
+
+fn max_image_size_arg() -> Arg<'static> {
+    Arg::new("max_image_size")
+        .help("Refuse to decode the pixels if they take more than this many bytes, 2 per sample")
+        .takes_value(true)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("268435456")
+        .long("max-image-size")
+}
+
+fn encoding_arg(help: &'static str) -> Arg<'static> {
+    Arg::new("encoding")
+        .help(help)
+        .takes_value(true)
+        .possible_values(["raw", "utf8", "base64", "hex"])
+        .default_value("raw")
+        .long("encoding")
+}
+
+/// The chunk type and the options choosing the chunks to act on, `--all`
+/// only when acting on the first chunk is the default.
+fn selector_args(with_all: bool) -> Vec<Arg<'static>> {
+    let mut args = vec![
+        Arg::new("chunk_type")
+            .help("The chunk type to use")
+            .takes_value(true)
+            .index(2),
+        Arg::new("type_regex")
+            .help("Select the chunks whose type matches this regex")
+            .takes_value(true)
+            .long("type-regex"),
+        Arg::new("index")
+            .help("Select the chunk at this index in the file, from 0")
+            .takes_value(true)
+            .long("index"),
+        Arg::new("private")
+            .help("Select private chunks")
+            .long("private"),
+        Arg::new("ancillary")
+            .help("Select ancillary chunks")
+            .long("ancillary"),
+        Arg::new("unknown")
+            .help("Select chunks the PNG specification does not define")
+            .long("unknown"),
+    ];
+    if with_all {
+        args.push(Arg::new("all")
+            .help("Act on every selected chunk instead of the first one")
+            .long("all"));
+    }
+    args
+}
+
+/// Where the message is hidden, and with `lsb` the options needed to find it
+/// again.
+fn method_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("method")
+            .help("Hide the message in a chunk, or in the least significant bits of the pixels")
+            .takes_value(true)
+            .possible_values(["chunk", "lsb"])
+            .default_value("chunk")
+            .long("method"),
+        Arg::new("bits")
+            .help("Low bits of each channel holding the message (lsb)")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u8).range(1..=8))
+            .default_value("1")
+            .long("bits"),
+        Arg::new("channels")
+            .help("Channels holding the message, letters among r, g, b and a (lsb)")
+            .takes_value(true)
+            .default_value("rgb")
+            .long("channels"),
+        Arg::new("key")
+            .help("Visit the pixels in an order shuffled with a key (lsb, read from PNGME_LSB_KEY or prompted)")
+            .long("key"),
+    ]
+}
+
+fn method(matches: &ArgMatches, confirm_key: bool) -> Result<Method> {
+    match matches.value_of("method").unwrap() {
+        "lsb" => Ok(Method::Lsb(LsbOptions {
+            bits: *matches.get_one::<u8>("bits").unwrap(),
+            channels: matches.value_of("channels").unwrap().parse()?,
+            key: match matches.is_present("key") {
+                true => Some(read_lsb_key(confirm_key)?),
+                false => None,
+            },
+        })),
+        _ => Ok(Method::Chunk),
+    }
+}
+
+fn selector_group() -> ArgGroup<'static> {
+    ArgGroup::new("selector")
+        .args(&["chunk_type", "type_regex", "index", "private", "ancillary", "unknown"])
+        .multiple(true)
+        .required(true)
+}
+
+fn selector(matches: &ArgMatches) -> Result<Selector> {
+    Ok(Selector {
+        chunk_type: matches.value_of("chunk_type").map(String::from),
+        type_regex: match matches.value_of("type_regex") {
+            Some(pattern) => Some(Regex::new(pattern).map_err(|_| PngError::InvalidTypeRegex)?),
+            None => None,
+        },
+        index: match matches.value_of("index") {
+            Some(index) => Some(index.parse().map_err(|_| PngError::InvalidChunkPosition)?),
+            None => None,
+        },
+        // only print has --chunks
+        chunk_types: match matches.try_get_many::<String>("chunks") {
+            Ok(Some(chunk_types)) => chunk_types.cloned().collect(),
+            _ => Vec::new(),
+        },
+        private: matches.is_present("private"),
+        ancillary: matches.is_present("ancillary"),
+        unknown: matches.is_present("unknown"),
+        // print has no --all, it always shows every selected chunk
+        all: matches.try_contains_id("all").unwrap_or(false),
+    })
+}
+
+fn text_entry_args(app: App<'static>) -> App<'static> {
+    app.arg(Arg::new("file_path")
+            .help("The path to the PNG file")
+            .takes_value(true)
+            .required(true)
+            .index(1))
+        .arg(Arg::new("keyword")
+            .help("The keyword of the entry (1 to 79 Latin-1 characters)")
+            .takes_value(true)
+            .required(true)
+            .index(2))
+        .arg(Arg::new("text")
+            .help("The text of the entry")
+            .takes_value(true)
+            .required(true)
+            .index(3))
+        .arg(Arg::new("compress")
+            .help("Compress the text (zTXt, or compressed iTXt with --utf8)")
+            .long("compress"))
+        .arg(Arg::new("utf8")
+            .help("Store UTF-8 text in an iTXt chunk instead of Latin-1")
+            .long("utf8"))
+        .arg(Arg::new("language")
+            .help("Language tag of the text, e.g. fr-FR (iTXt)")
+            .takes_value(true)
+            .long("language"))
+        .arg(Arg::new("translated_keyword")
+            .help("The keyword translated in the language of the text (iTXt)")
+            .takes_value(true)
+            .long("translated-keyword"))
+}
+
+fn text_entry(matches: &ArgMatches) -> Result<TextEntry> {
+    let compressed = matches.is_present("compress");
+    let international = matches.is_present("utf8")
+        || matches.is_present("language")
+        || matches.is_present("translated_keyword");
+
+    let kind = match (international, compressed) {
+        (false, false) => TextKind::Text,
+        (false, true) => TextKind::CompressedText,
+        (true, compressed) => TextKind::InternationalText {
+            compressed,
+            language_tag: matches.value_of("language").unwrap_or_default().to_string(),
+            translated_keyword: matches.value_of("translated_keyword").unwrap_or_default().to_string(),
+        },
+    };
+    TextEntry::new(
+        matches.value_of("keyword").unwrap(),
+        matches.value_of("text").unwrap(),
+        kind,
+    )
+}
@@ -1,7 +1,47 @@
-use crate::chunk::{Chunk, ChunkIterator};
+use crate::chunk::{Chunk, ChunkIterator, ParseMode};
+use crate::critical::{Ihdr, Plte};
+use crate::image::{IdatOptions, Image};
+use crate::strip::StripPolicy;
+use crate::text::TextEntry;
+use crate::validate::{Finding, Validator};
 use crate::{PngError, Result};
 use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
 
+/// Where [`Png::insert_chunk`] puts a new chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkPosition {
+    /// Right before `IEND`, or at the end if there is no `IEND`.
+    #[default]
+    BeforeIend,
+    /// Right after `IHDR`.
+    AfterIhdr,
+    /// Right before the first `IDAT`.
+    BeforeIdat,
+    /// Right after the `IDAT` chunks, which must stay contiguous.
+    AfterIdat,
+    /// At an explicit index in the chunk list.
+    Index(usize),
+}
+
+impl FromStr for ChunkPosition {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "before-iend" => Ok(ChunkPosition::BeforeIend),
+            "after-ihdr" => Ok(ChunkPosition::AfterIhdr),
+            "before-idat" => Ok(ChunkPosition::BeforeIdat),
+            "after-idat" => Ok(ChunkPosition::AfterIdat),
+            _ => s
+                .parse()
+                .map(ChunkPosition::Index)
+                .map_err(|_| PngError::InvalidChunkPosition),
+        }
+    }
+}
+
+/// A PNG file as its signature followed by an ordered list of chunks.
 pub struct Png {
     chunks: Vec<Chunk>,
 }
@@ -19,31 +59,115 @@ impl TryFrom<&[u8]> for Png {
     type Error = PngError;
 
     fn try_from(value: &[u8]) -> Result<Self> {
-        if value.len() < Png::STANDARD_HEADER.len()
-            || &value[0..Png::STANDARD_HEADER.len()] != Png::STANDARD_HEADER
-        {
-            return Err(PngError::InvalidPngFile);
-        }
+        Ok(Png::parse_with(value, &ParseOptions::default())?.png)
+    }
+}
 
-        let chunks = ChunkIterator::new(&value[Png::STANDARD_HEADER.len()..])
-            .collect::<Result<Vec<Chunk>>>()?;
+/// How [`Png::parse_with`] reads a file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+}
 
-        Ok(Png { chunks: chunks })
-    }
+/// The chunks read by [`Png::parse_with`] and the errors it went past.
+pub struct Parsed {
+    pub png: Png,
+    /// Invalid chunks, as [`PngError::Chunk`] errors with their index and
+    /// offset.
+    pub diagnostics: Vec<PngError>,
 }
 
 impl Png {
+    /// The 8 bytes signature every PNG file starts with.
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    #[allow(dead_code)]
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png { chunks }
     }
 
+    /// Parses a png file, going past invalid chunks unless the mode is
+    /// [`ParseMode::Strict`], which fails on the first one like
+    /// [`Png::try_from`].
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<Parsed> {
+        if bytes.len() < Png::STANDARD_HEADER.len()
+            || bytes[0..Png::STANDARD_HEADER.len()] != Png::STANDARD_HEADER
+        {
+            return Err(PngError::InvalidPngFile);
+        }
+
+        let mut chunks = Vec::new();
+        let mut diagnostics = Vec::new();
+        let iterator = ChunkIterator::with_offset(
+            &bytes[Png::STANDARD_HEADER.len()..],
+            Png::STANDARD_HEADER.len(),
+        )
+        .parse_mode(options.mode);
+        for chunk in iterator {
+            match chunk {
+                Ok(chunk) => chunks.push(chunk),
+                Err(error) if options.mode == ParseMode::Strict => return Err(error),
+                Err(error) => diagnostics.push(error),
+            }
+        }
+
+        Ok(Parsed {
+            png: Png { chunks },
+            diagnostics,
+        })
+    }
+
+    pub fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
+
+    /// Pushes a chunk at the end of the file, after `IEND` if there is one.
+    /// Use [`Png::insert_chunk`] to produce spec-conformant files.
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
+    /// Inserts a chunk at the given position and returns its index.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: ChunkPosition) -> Result<usize> {
+        let index = self.insertion_index(position)?;
+        self.chunks.insert(index, chunk);
+        Ok(index)
+    }
+
+    /// Inserts chunks next to each other at the given position and returns the
+    /// index of the first one.
+    pub fn insert_chunks(&mut self, chunks: Vec<Chunk>, position: ChunkPosition) -> Result<usize> {
+        let index = self.insertion_index(position)?;
+        self.chunks.splice(index..index, chunks);
+        Ok(index)
+    }
+
+    fn insertion_index(&self, position: ChunkPosition) -> Result<usize> {
+        let find = |chunk_type: &str| {
+            self.chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        };
+
+        match position {
+            ChunkPosition::BeforeIend => Ok(find("IEND").unwrap_or(self.chunks.len())),
+            ChunkPosition::AfterIhdr => find("IHDR")
+                .map(|index| index + 1)
+                .ok_or(PngError::ChunkNotFound),
+            ChunkPosition::BeforeIdat => find("IDAT").ok_or(PngError::ChunkNotFound),
+            ChunkPosition::AfterIdat => {
+                let first = find("IDAT").ok_or(PngError::ChunkNotFound)?;
+                let count = self.chunks[first..]
+                    .iter()
+                    .take_while(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                    .count();
+                Ok(first + count)
+            }
+            ChunkPosition::Index(index) if index <= self.chunks.len() => Ok(index),
+            ChunkPosition::Index(_) => Err(PngError::InvalidChunkPosition),
+        }
+    }
+
+    /// Removes the first chunk of the given type.
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         self.chunks
             .iter()
@@ -52,26 +176,194 @@ impl Png {
             .ok_or(PngError::ChunkNotFound)
     }
 
+    /// Removes the chunk at `index`.
+    pub fn remove_chunk_at(&mut self, index: usize) -> Result<Chunk> {
+        if index >= self.chunks.len() {
+            return Err(PngError::ChunkNotFound);
+        }
+        Ok(self.chunks.remove(index))
+    }
+
+    /// Removes every chunk for which `predicate` returns true, given its index
+    /// before any removal, and returns them in file order.
+    pub fn remove_where<F>(&mut self, mut predicate: F) -> Vec<Chunk>
+    where
+        F: FnMut(usize, &Chunk) -> bool,
+    {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .enumerate()
+            .partition::<Vec<_>, _>(|(index, chunk)| predicate(*index, chunk));
+        self.chunks = kept.into_iter().map(|(_, chunk)| chunk).collect();
+        removed.into_iter().map(|(_, chunk)| chunk).collect()
+    }
+
     pub fn header(&self) -> &[u8; 8] {
         &Self::STANDARD_HEADER
     }
 
-    #[allow(dead_code)]
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
+    /// Returns the first chunk of the given type.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        match self
-            .chunks
+        self.chunks
             .iter()
             .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
-        {
-            Some(chunk) => Some(chunk),
-            None => None,
+    }
+
+    /// Decodes the pixels of the image, see [`Image::decode`].
+    pub fn image(&self) -> Result<Image> {
+        Image::decode(self)
+    }
+
+    /// Decodes the pixels of the image, failing if they would take more than
+    /// `limit` bytes, see [`Image::decode_with_limit`].
+    pub fn image_with_limit(&self, limit: u64) -> Result<Image> {
+        Image::decode_with_limit(self, limit)
+    }
+
+    /// Replaces the `IDAT` chunks with `image` encoded as `options` says,
+    /// where the first one was. `IHDR` is rewritten from the image header,
+    /// every other chunk is kept.
+    pub fn replace_image(&mut self, image: &Image, options: &IdatOptions) -> Result<()> {
+        // nothing changes before every step that can fail has passed
+        let idat = image.encode(options)?;
+        let ihdr = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == Ihdr::CHUNK_TYPE)
+            .ok_or(PngError::ChunkNotFound)?;
+        let index = self.insertion_index(ChunkPosition::BeforeIdat)?;
+
+        self.chunks[ihdr] = image.ihdr().to_chunk();
+        self.remove_where(|_, chunk| chunk.chunk_type().to_string() == "IDAT");
+        self.chunks.splice(index..index, idat);
+        Ok(())
+    }
+
+    /// Removes the chunks the policy does not keep and returns them.
+    pub fn strip(&mut self, policy: &StripPolicy) -> Vec<Chunk> {
+        self.remove_where(|_, chunk| policy.removes(chunk))
+    }
+
+    /// Returns every chunk of the given type, in file order.
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Returns the chunk at `index`.
+    pub fn chunk_at(&self, index: usize) -> Option<&Chunk> {
+        self.chunks.get(index)
+    }
+
+    /// Decodes the `IHDR` chunk.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        self.chunk_by_type(Ihdr::CHUNK_TYPE)
+            .ok_or(PngError::ChunkNotFound)
+            .and_then(Ihdr::try_from)
+    }
+
+    /// Decodes the `PLTE` chunk, if there is one.
+    pub fn palette(&self) -> Result<Option<Plte>> {
+        self.chunk_by_type(Plte::CHUNK_TYPE)
+            .map(Plte::try_from)
+            .transpose()
+    }
+
+    /// Decodes every `tEXt`, `zTXt` and `iTXt` chunk, in file order. A chunk
+    /// that can't be decoded gives its own error, with its index and offset,
+    /// so one bad chunk doesn't hide the others.
+    pub fn text_entries(&self) -> Vec<Result<TextEntry>> {
+        let mut offset = Png::STANDARD_HEADER.len() as u64;
+        let mut entries = Vec::new();
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if TextEntry::is_text_chunk(chunk) {
+                entries.push(
+                    TextEntry::try_from(chunk)
+                        .map_err(|e| e.in_chunk(index, offset, Some(*chunk.chunk_type()))),
+                );
+            }
+            offset += chunk.length() as u64 + Chunk::CHUNK_METADATA_LENGTH as u64;
+        }
+        entries
+    }
+
+    /// Returns the first text entry with this keyword, skipping the chunks
+    /// that can't be decoded.
+    pub fn text(&self, keyword: &str) -> Option<TextEntry> {
+        self.text_entries()
+            .into_iter()
+            .flatten()
+            .find(|entry| entry.keyword == keyword)
+    }
+
+    /// Adds a text entry before `IEND`, even if the keyword already exists.
+    pub fn add_text(&mut self, entry: &TextEntry) -> Result<()> {
+        self.insert_chunk(entry.to_chunk()?, ChunkPosition::BeforeIend)?;
+        Ok(())
+    }
+
+    /// Replaces the first text entry with the same keyword, or adds it if
+    /// there is none. Other entries with this keyword are removed.
+    pub fn set_text(&mut self, entry: &TextEntry) -> Result<()> {
+        let chunk = entry.to_chunk()?;
+        match self.text_indices(&entry.keyword).first() {
+            Some(&index) => {
+                self.remove_text(&entry.keyword)?;
+                self.chunks.insert(index, chunk);
+            }
+            None => {
+                self.insert_chunk(chunk, ChunkPosition::BeforeIend)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every text entry with this keyword and returns them.
+    pub fn remove_text(&mut self, keyword: &str) -> Result<Vec<TextEntry>> {
+        let indices = self.text_indices(keyword);
+        if indices.is_empty() {
+            return Err(PngError::ChunkNotFound);
+        }
+
+        let mut removed: Vec<TextEntry> = indices
+            .into_iter()
+            .rev()
+            .map(|index| TextEntry::try_from(&self.chunks.remove(index)))
+            .collect::<Result<_>>()?;
+        removed.reverse();
+        Ok(removed)
+    }
+
+    /// Indices of the text chunks with this keyword. Chunks that can't be
+    /// decoded have no keyword and are left alone.
+    fn text_indices(&self, keyword: &str) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| TextEntry::is_text_chunk(chunk))
+            .filter(|(_, chunk)| {
+                TextEntry::try_from(*chunk).is_ok_and(|entry| entry.keyword == keyword)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Checks the chunks against the rules of the PNG specification: ordering,
+    /// uniqueness, chunk type bits and the content of the critical chunks.
+    pub fn validate(&self) -> Vec<Finding> {
+        let mut validator = Validator::new();
+        for chunk in self.chunks.iter() {
+            validator.push(chunk);
         }
+        validator.finish()
     }
 
+    /// Serializes the signature followed by every chunk.
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         bytes.extend_from_slice(self.header());
@@ -91,13 +383,11 @@ mod tests {
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -178,6 +468,52 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_invalid_chunk_reports_position() {
+        let mut bytes = Png::try_from(&PNG_FILE[..]).unwrap().as_bytes();
+        // corrupt the crc of the second chunk (sRGB)
+        let offset = 8 + 13 + Chunk::CHUNK_METADATA_LENGTH;
+        bytes[offset + 12] ^= 1;
+
+        match Png::try_from(bytes.as_ref()) {
+            Err(PngError::Chunk {
+                index,
+                offset: error_offset,
+                chunk_type,
+                source,
+            }) => {
+                assert_eq!(index, 1);
+                assert_eq!(error_offset, offset as u64);
+                assert_eq!(chunk_type.unwrap().to_string(), "sRGB");
+                assert!(matches!(*source, PngError::InvalidCrc { .. }));
+            }
+            _ => panic!("expected a positioned chunk error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_with() {
+        let mut bytes = testing_png().as_bytes();
+        // damage the crc of the first chunk and the length of the second
+        bytes[8 + 8 + 20] ^= 1;
+        bytes[8 + 32 + 3] = 200;
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+        let parse = |mode| Png::parse_with(&bytes, &ParseOptions { mode }).unwrap();
+
+        let parsed = parse(ParseMode::SkipBadCrc);
+        assert!(parsed.png.chunks().is_empty());
+        assert_eq!(parsed.diagnostics.len(), 2);
+
+        let parsed = parse(ParseMode::Resync);
+        let chunk = parsed.png.chunk_by_type("LASt").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the last chunk");
+        assert!(matches!(
+            parsed.diagnostics[..],
+            [PngError::Chunk { offset: 8, .. }]
+        ));
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -193,6 +529,21 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am a second first chunk").unwrap());
+        let chunks: Vec<_> = png.chunks_by_type("FrSt").collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            &chunks[1].data_as_string().unwrap(),
+            "I am a second first chunk"
+        );
+        assert_eq!(png.chunks_by_type("NoNe").count(), 0);
+        assert_eq!(png.chunk_at(2).unwrap().chunk_type().to_string(), "LASt");
+        assert!(png.chunk_at(4).is_none());
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -202,6 +553,120 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    fn image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "first").unwrap(),
+            chunk_from_strings("IDAT", "second").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_chunk_before_iend() {
+        let mut png = image_png();
+        let index = png
+            .insert_chunk(
+                chunk_from_strings("RuSt", "Message").unwrap(),
+                ChunkPosition::default(),
+            )
+            .unwrap();
+        assert_eq!(index, 3);
+        assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IDAT", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunks() {
+        let mut png = image_png();
+        let chunks = vec![
+            chunk_from_strings("RuSt", "1").unwrap(),
+            chunk_from_strings("RuSt", "2").unwrap(),
+        ];
+        let index = png.insert_chunks(chunks, ChunkPosition::AfterIhdr).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "RuSt", "RuSt", "IDAT", "IDAT", "IEND"]
+        );
+        assert_eq!(png.chunks()[2].data(), b"2");
+    }
+
+    #[test]
+    fn test_insert_chunk_without_iend() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("RuSt", "Message").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        assert_eq!(chunk_types(&png), ["FrSt", "miDl", "LASt", "RuSt"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_around_image_data() {
+        let mut png = image_png();
+        png.insert_chunk(
+            chunk_from_strings("AfHr", "").unwrap(),
+            ChunkPosition::AfterIhdr,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("BfIt", "").unwrap(),
+            ChunkPosition::BeforeIdat,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("AfIt", "").unwrap(),
+            ChunkPosition::AfterIdat,
+        )
+        .unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "AfHr", "BfIt", "IDAT", "IDAT", "AfIt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_insert_chunk_at_index() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("RuSt", "Message").unwrap(),
+            ChunkPosition::Index(0),
+        )
+        .unwrap();
+        assert_eq!(&png.chunks()[0].chunk_type().to_string(), "RuSt");
+
+        let chunk = chunk_from_strings("RuSt", "Message").unwrap();
+        assert!(png.insert_chunk(chunk, ChunkPosition::Index(10)).is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_missing_anchor() {
+        let mut png = testing_png();
+        let chunk = chunk_from_strings("RuSt", "Message").unwrap();
+        assert!(png.insert_chunk(chunk, ChunkPosition::AfterIhdr).is_err());
+    }
+
+    #[test]
+    fn test_chunk_position_from_str() {
+        assert_eq!(
+            ChunkPosition::from_str("after-idat").unwrap(),
+            ChunkPosition::AfterIdat
+        );
+        assert_eq!(
+            ChunkPosition::from_str("2").unwrap(),
+            ChunkPosition::Index(2)
+        );
+        assert!(ChunkPosition::from_str("nowhere").is_err());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -211,20 +676,153 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_strip() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.add_text(&TextEntry::new("Author", "someone", crate::TextKind::Text).unwrap())
+            .unwrap();
+
+        let removed = png.strip(&StripPolicy::default());
+        let types: Vec<_> = removed
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["sRGB", "gAMA", "pHYs", "RuSt", "tEXt"]);
+        assert!(png
+            .chunks()
+            .iter()
+            .all(|chunk| chunk.chunk_type().is_critical() && chunk.chunk_type().is_known()));
+    }
+
+    #[test]
+    fn test_remove_chunk_at() {
+        let mut png = testing_png();
+        let chunk = png.remove_chunk_at(1).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.remove_chunk_at(2).is_err());
+    }
+
+    #[test]
+    fn test_remove_where() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "I am a later chunk").unwrap());
+        png.append_chunk(chunk_from_strings("miDl", "I am the last one").unwrap());
+
+        // indices are the ones before removal
+        let removed =
+            png.remove_where(|index, chunk| chunk.chunk_type().to_string() == "miDl" && index != 3);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(&removed[1].data_as_string().unwrap(), "I am the last one");
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["FrSt", "LASt", "miDl"]);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (50, 50));
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, crate::ColorType::Rgba);
+        assert!(png.palette().unwrap().is_none());
+
+        assert!(testing_png().ihdr().is_err());
+    }
+
+    #[test]
+    fn test_text_entries() {
+        use crate::text::TextKind;
+
+        let mut png = image_png();
+        let title = TextEntry::new("Title", "Dice", TextKind::Text).unwrap();
+        let comment = TextEntry::new("Comment", "first", TextKind::CompressedText).unwrap();
+        png.add_text(&title).unwrap();
+        png.add_text(&comment).unwrap();
+        png.add_text(&comment).unwrap();
+        assert_eq!(png.text_entries().len(), 3);
+        assert_eq!(png.text("Title").unwrap(), title);
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+
+        let updated = TextEntry::new("Comment", "second", TextKind::Text).unwrap();
+        png.set_text(&updated).unwrap();
+        let entries: Vec<_> = png.text_entries().into_iter().flatten().collect();
+        assert_eq!(entries, [title.clone(), updated.clone()]);
+
+        assert_eq!(png.remove_text("Comment").unwrap(), [updated]);
+        assert!(png.text("Comment").is_none());
+        assert!(png.remove_text("Comment").is_err());
+    }
+
+    #[test]
+    fn test_invalid_text_entries() {
+        use crate::text::TextKind;
+
+        let mut png = image_png();
+        // no keyword separator
+        let broken = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Broken".to_vec());
+        png.insert_chunk(broken, ChunkPosition::BeforeIend).unwrap();
+        let title = TextEntry::new("Title", "Dice", TextKind::Text).unwrap();
+        png.add_text(&title).unwrap();
+
+        let entries = png.text_entries();
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(entries[0], Err(PngError::Chunk { .. })));
+        assert_eq!(entries[1].as_ref().unwrap(), &title);
+
+        let updated = TextEntry::new("Title", "Cards", TextKind::Text).unwrap();
+        png.set_text(&updated).unwrap();
+        assert_eq!(png.remove_text("Title").unwrap(), [updated]);
+        assert_eq!(png.text_entries().len(), 1);
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_fuzz_regressions() {
+        // same entry points as fuzz/fuzz_targets/parse.rs, must not panic
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/regressions");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let data = std::fs::read(entry.unwrap().path()).unwrap();
+
+            if let Ok(png) = Png::try_from(data.as_ref()) {
+                let _ = png.to_string();
+            }
+            if let Ok(reader) = crate::ChunkReader::new(data.as_slice()) {
+                for chunk in reader.flatten() {
+                    let _ = chunk.to_string();
+                }
+            }
+            if let Ok(chunk) = Chunk::try_from(data.as_ref()) {
+                let _ = chunk.to_string();
+            }
+            if let Ok(s) = std::str::from_utf8(&data) {
+                if let Ok(chunk_type) = ChunkType::from_str(s) {
+                    let _ = chunk_type.to_string();
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,326 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::chunk::{chunk_header, find_chunk, Chunk, MAX_RESYNC_BYTES};
+use crate::chunk_type::ChunkType;
+use crate::critical::Iend;
+use crate::png::Png;
+use crate::{PngError, Result};
+
+/// One change made by [`repair`]. Offsets are in bytes from the start of the
+/// damaged file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Fix {
+    /// The file did not start with the png signature.
+    Signature,
+    /// The crc of a chunk did not match its type and data and was recomputed.
+    Crc {
+        offset: u64,
+        chunk_type: ChunkType,
+        found: u32,
+        expected: u32,
+    },
+    /// Bytes that are not a chunk were skipped up to the next valid chunk.
+    Resync { offset: u64, skipped: u64 },
+    /// The last `IDAT` chunk is cut short, its data was kept up to the end of
+    /// the file.
+    Truncated {
+        offset: u64,
+        chunk_type: ChunkType,
+        length: u32,
+        kept: u32,
+    },
+    /// Bytes at the end of the file holding no whole chunk were dropped.
+    DroppedTrailing { offset: u64, length: u64 },
+    /// Bytes after `IEND` were dropped.
+    AfterIend { offset: u64, length: u64 },
+    /// `IEND` was missing and was added.
+    MissingIend,
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Fix::Signature => write!(f, "replaced the invalid png signature"),
+            Fix::Crc {
+                offset,
+                chunk_type,
+                found,
+                expected,
+            } => write!(
+                f,
+                "offset {}: recomputed the crc of {} (found 0x{:08x}, expected 0x{:08x})",
+                offset, chunk_type, found, expected
+            ),
+            Fix::Resync { offset, skipped } => write!(
+                f,
+                "offset {}: skipped {} bytes up to the next chunk",
+                offset, skipped
+            ),
+            Fix::Truncated {
+                offset,
+                chunk_type,
+                length,
+                kept,
+            } => write!(
+                f,
+                "offset {}: {} is cut short, kept {} of its {} bytes",
+                offset, chunk_type, kept, length
+            ),
+            Fix::DroppedTrailing { offset, length } => write!(
+                f,
+                "offset {}: dropped {} trailing bytes holding no whole chunk",
+                offset, length
+            ),
+            Fix::AfterIend { offset, length } => {
+                write!(f, "offset {}: removed {} bytes after IEND", offset, length)
+            }
+            Fix::MissingIend => write!(f, "added the missing IEND chunk"),
+        }
+    }
+}
+
+/// The chunks recovered by [`repair`] and what was changed to get them.
+pub struct Repair {
+    pub png: Png,
+    pub fixes: Vec<Fix>,
+}
+
+/// Reads as much as possible of a damaged png file. Wrong crcs are
+/// recomputed when the next chunk starts where expected, otherwise the bytes
+/// are skipped up to the next chunk with a valid crc. Fails only if no chunk
+/// could be found.
+pub fn repair(bytes: &[u8]) -> Result<Repair> {
+    let mut fixes = Vec::new();
+    let start = if bytes.starts_with(&Png::STANDARD_HEADER) {
+        Png::STANDARD_HEADER.len()
+    } else {
+        fixes.push(Fix::Signature);
+        // the signature is either missing or damaged
+        if chunk_header(bytes, 0).is_some() {
+            0
+        } else {
+            Png::STANDARD_HEADER.len().min(bytes.len())
+        }
+    };
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut pos = start;
+    let mut resync_budget = MAX_RESYNC_BYTES;
+    while pos < bytes.len() {
+        let offset = pos as u64;
+        let remaining = (bytes.len() - pos) as u64;
+        let ended = chunks
+            .last()
+            .is_some_and(|chunk| chunk.chunk_type().to_string() == Iend::CHUNK_TYPE);
+        if ended {
+            fixes.push(Fix::AfterIend {
+                offset,
+                length: remaining,
+            });
+            break;
+        }
+
+        match chunk_header(bytes, pos) {
+            Some((chunk_type, length))
+                if pos + length + Chunk::CHUNK_METADATA_LENGTH <= bytes.len() =>
+            {
+                let end = pos + length + Chunk::CHUNK_METADATA_LENGTH;
+                let chunk = Chunk::new(chunk_type, bytes[pos + 8..end - 4].to_vec());
+                let found = u32::from_be_bytes(bytes[end - 4..end].try_into().expect("4 bytes"));
+                let expected = chunk.crc();
+                // with the next chunk where expected, only the crc or the data is
+                // damaged, otherwise the length probably is
+                if found == expected || end == bytes.len() || chunk_header(bytes, end).is_some() {
+                    if found != expected {
+                        fixes.push(Fix::Crc {
+                            offset,
+                            chunk_type,
+                            found,
+                            expected,
+                        });
+                    }
+                    chunks.push(chunk);
+                    pos = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        match (
+            find_chunk(bytes, pos + 1, &mut resync_budget),
+            chunk_header(bytes, pos),
+        ) {
+            (Some(next), _) => {
+                fixes.push(Fix::Resync {
+                    offset,
+                    skipped: (next - pos) as u64,
+                });
+                pos = next;
+            }
+            (None, Some((chunk_type, length)))
+                if pos + length + Chunk::CHUNK_METADATA_LENGTH > bytes.len() =>
+            {
+                // the file ends in the middle of this chunk
+                if chunk_type.to_string() == "IDAT" && remaining > 8 {
+                    let kept = (bytes.len() - pos - 8).min(length);
+                    chunks.push(Chunk::new(
+                        chunk_type,
+                        bytes[pos + 8..pos + 8 + kept].to_vec(),
+                    ));
+                    fixes.push(Fix::Truncated {
+                        offset,
+                        chunk_type,
+                        length: length as u32,
+                        kept: kept as u32,
+                    });
+                } else {
+                    fixes.push(Fix::DroppedTrailing {
+                        offset,
+                        length: remaining,
+                    });
+                }
+                break;
+            }
+            (None, _) => {
+                fixes.push(Fix::DroppedTrailing {
+                    offset,
+                    length: remaining,
+                });
+                break;
+            }
+        }
+    }
+
+    if chunks.is_empty() {
+        return Err(PngError::InvalidPngFile);
+    }
+    if chunks.last().unwrap().chunk_type().to_string() != Iend::CHUNK_TYPE {
+        chunks.push(Iend.to_chunk());
+        fixes.push(Fix::MissingIend);
+    }
+    Ok(Repair {
+        png: Png::from_chunks(chunks),
+        fixes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("RuSt", b"hidden message"),
+            chunk("IDAT", &[7; 100]),
+            Iend.to_chunk(),
+        ])
+        .as_bytes()
+    }
+
+    fn types(repair: &Repair) -> Vec<String> {
+        repair
+            .png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_intact_file() {
+        let repaired = repair(&testing_bytes()).unwrap();
+        assert!(repaired.fixes.is_empty());
+        assert_eq!(repaired.png.as_bytes(), testing_bytes());
+    }
+
+    #[test]
+    fn test_wrong_crc() {
+        let mut bytes = testing_bytes();
+        // crc of RuSt, after the signature, IHDR and its own header and data
+        let crc = 8 + 25 + 8 + 14;
+        bytes[crc] ^= 0xff;
+
+        let repaired = repair(&bytes).unwrap();
+        assert!(matches!(repaired.fixes[..], [Fix::Crc { offset: 33, .. }]));
+        assert_eq!(repaired.png.as_bytes(), testing_bytes());
+    }
+
+    #[test]
+    fn test_resync() {
+        let mut bytes = testing_bytes();
+        // a damaged length loses the chunk, the next one is found again
+        bytes[33..37].copy_from_slice(&1000_u32.to_be_bytes());
+        bytes.splice(33..33, *b"garbage");
+
+        let repaired = repair(&bytes).unwrap();
+        assert!(matches!(
+            repaired.fixes[..],
+            [Fix::Resync {
+                offset: 33,
+                skipped: 33
+            }]
+        ));
+        assert_eq!(types(&repaired), ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let mut bytes = testing_bytes();
+        bytes.truncate(bytes.len() - 12 - 50);
+
+        let repaired = repair(&bytes).unwrap();
+        assert!(matches!(
+            repaired.fixes[..],
+            [
+                Fix::Truncated {
+                    length: 100,
+                    kept: 54,
+                    ..
+                },
+                Fix::MissingIend
+            ]
+        ));
+        assert_eq!(types(&repaired), ["IHDR", "RuSt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_trailing_bytes() {
+        let mut bytes = testing_bytes();
+        bytes.truncate(bytes.len() - 12);
+        bytes.extend_from_slice(b"\x00\x00");
+        let repaired = repair(&bytes).unwrap();
+        assert!(matches!(
+            repaired.fixes[..],
+            [Fix::DroppedTrailing { length: 2, .. }, Fix::MissingIend]
+        ));
+
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(b"garbage after the end");
+        let repaired = repair(&bytes).unwrap();
+        assert!(matches!(
+            repaired.fixes[..],
+            [Fix::AfterIend { length: 21, .. }]
+        ));
+        assert_eq!(repaired.png.as_bytes(), testing_bytes());
+    }
+
+    #[test]
+    fn test_signature() {
+        let mut bytes = testing_bytes();
+        bytes[1] = b'X';
+        let repaired = repair(&bytes).unwrap();
+        assert_eq!(repaired.fixes, [Fix::Signature]);
+        assert_eq!(repaired.png.as_bytes(), testing_bytes());
+
+        let repaired = repair(&testing_bytes()[8..]).unwrap();
+        assert_eq!(repaired.fixes, [Fix::Signature]);
+
+        assert!(repair(b"not a png file").is_err());
+    }
+}
@@ -0,0 +1,470 @@
+use std::str::FromStr;
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::split::{self, Piece};
+use crate::{PngError, Result};
+
+/// Whether a part of the file signed by a [`Signature`] is unchanged.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Integrity {
+    Intact,
+    Altered,
+    /// The signed chunk is no longer in the file.
+    Missing,
+}
+
+/// An Ed25519 signature over a payload chunk and the image data (`IHDR`,
+/// `PLTE` and `IDAT` chunks), stored in a private `pmSG` chunk.
+///
+/// A split payload is signed through its first piece, whose header holds the
+/// digest of the whole payload: the other pieces must reassemble to it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Signature {
+    pub signer: VerifyingKey,
+    pub payload_type: ChunkType,
+    pub payload_digest: [u8; 32],
+    pub image_digest: [u8; 32],
+    pub signature: ed25519_dalek::Signature,
+}
+
+impl Signature {
+    /// Ancillary, private and not safe to copy: the signature no longer holds
+    /// once the image data changes.
+    pub const CHUNK_TYPE: &'static str = "pmSG";
+    const VERSION: u8 = 1;
+    /// version, signer, payload chunk type, both digests and the signature
+    const LENGTH: usize = 1 + 32 + 4 + 32 + 32 + 64;
+    const DOMAIN: &'static [u8] = b"pngme signature v1\0";
+
+    /// Signs `payload` and the image data of `png` with `key`.
+    pub fn new(png: &Png, payload: &Chunk, key: &SigningKey) -> Signature {
+        let mut signature = Signature {
+            signer: key.verifying_key(),
+            payload_type: *payload.chunk_type(),
+            payload_digest: chunk_digest(payload),
+            image_digest: image_digest(png),
+            signature: ed25519_dalek::Signature::from_bytes(&[0; 64]),
+        };
+        signature.signature = key.sign(&signature.signed_message());
+        signature
+    }
+
+    /// Checks the signature itself, not what it covers.
+    pub fn is_valid(&self) -> bool {
+        self.signer
+            .verify_strict(&self.signed_message(), &self.signature)
+            .is_ok()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.signed_fields();
+        data.extend_from_slice(&self.signature.to_bytes());
+        let chunk_type = ChunkType::from_str(Signature::CHUNK_TYPE).expect("valid chunk type");
+        Chunk::new(chunk_type, data)
+    }
+
+    fn signed_fields(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Signature::LENGTH);
+        data.push(Signature::VERSION);
+        data.extend_from_slice(self.signer.as_bytes());
+        data.extend_from_slice(&self.payload_type.bytes());
+        data.extend_from_slice(&self.payload_digest);
+        data.extend_from_slice(&self.image_digest);
+        data
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        let mut message = Signature::DOMAIN.to_vec();
+        message.extend(self.signed_fields());
+        message
+    }
+}
+
+impl TryFrom<&Chunk> for Signature {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let invalid = |reason| PngError::InvalidChunkData {
+            chunk_type: Signature::CHUNK_TYPE,
+            reason,
+        };
+        if chunk.chunk_type().to_string() != Signature::CHUNK_TYPE {
+            return Err(invalid("wrong chunk type"));
+        }
+        let data = chunk.data();
+        if data.len() != Signature::LENGTH || data[0] != Signature::VERSION {
+            return Err(invalid("unknown signature format"));
+        }
+
+        let array32 =
+            |start: usize| -> [u8; 32] { data[start..start + 32].try_into().expect("32 bytes") };
+        let signer =
+            VerifyingKey::from_bytes(&array32(1)).map_err(|_| invalid("invalid public key"))?;
+        let payload_type = ChunkType::try_from([data[33], data[34], data[35], data[36]])?;
+        let signature = data[101..].try_into().expect("64 bytes");
+
+        Ok(Signature {
+            signer,
+            payload_type,
+            payload_digest: array32(37),
+            image_digest: array32(69),
+            signature: ed25519_dalek::Signature::from_bytes(signature),
+        })
+    }
+}
+
+/// Outcome of checking one signature chunk of a file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Verification {
+    /// Index of the signature chunk.
+    pub index: usize,
+    pub signature: Signature,
+    /// Whether the signer is one of the trusted keys.
+    pub trusted: bool,
+    pub valid: bool,
+    pub payload: Integrity,
+    pub image: Integrity,
+    /// Index and type of the chunks of the file covered by the signature.
+    pub covered: Vec<(usize, ChunkType)>,
+}
+
+impl Verification {
+    /// A valid signature by a trusted key over unchanged data.
+    pub fn is_ok(&self) -> bool {
+        self.trusted
+            && self.valid
+            && self.payload == Integrity::Intact
+            && self.image == Integrity::Intact
+    }
+}
+
+/// Checks every signature chunk of `png` against `trusted` keys. A signature
+/// chunk that can't be decoded gives its own error, with its index and
+/// offset, so it doesn't hide the other signatures. Fails with
+/// [`PngError::ChunkNotFound`] when the file is not signed.
+pub fn verify(png: &Png, trusted: &[VerifyingKey]) -> Result<Vec<Result<Verification>>> {
+    let mut offset = Png::STANDARD_HEADER.len() as u64;
+    let mut verifications = Vec::new();
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().to_string() == Signature::CHUNK_TYPE {
+            verifications.push(
+                Signature::try_from(chunk)
+                    .map(|signature| check(png, index, signature, trusted))
+                    .map_err(|e| e.in_chunk(index, offset, Some(*chunk.chunk_type()))),
+            );
+        }
+        offset += chunk.length() as u64 + Chunk::CHUNK_METADATA_LENGTH as u64;
+    }
+
+    if verifications.is_empty() {
+        return Err(PngError::ChunkNotFound);
+    }
+    Ok(verifications)
+}
+
+/// Checks the signature found in the chunk at `index`.
+fn check(png: &Png, index: usize, signature: Signature, trusted: &[VerifyingKey]) -> Verification {
+    // several chunks may share the payload type, look for the signed one
+    let payloads: Vec<_> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| *chunk.chunk_type() == signature.payload_type)
+        .collect();
+    let payload = payloads
+        .iter()
+        .find(|(_, chunk)| chunk_digest(chunk) == signature.payload_digest)
+        .or(payloads.first());
+    let mut payload_integrity = match payload {
+        Some((_, chunk)) if chunk_digest(chunk) == signature.payload_digest => Integrity::Intact,
+        Some(_) => Integrity::Altered,
+        None => Integrity::Missing,
+    };
+    let pieces = match payload {
+        Some((_, chunk)) => pieces_of(&payloads, chunk),
+        None => Vec::new(),
+    };
+    if !pieces.is_empty() && split::reassemble(pieces.iter().map(|(_, chunk)| *chunk)).is_err() {
+        payload_integrity = Integrity::Altered;
+    }
+    let image = if image_digest(png) == signature.image_digest {
+        Integrity::Intact
+    } else {
+        Integrity::Altered
+    };
+
+    let mut covered: Vec<_> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| is_image_chunk(chunk))
+        .map(|(index, chunk)| (index, *chunk.chunk_type()))
+        .collect();
+    if let Some((index, chunk)) = payload {
+        covered.push((*index, *chunk.chunk_type()));
+    }
+    covered.extend(
+        pieces
+            .iter()
+            .filter(|(index, _)| payload.is_none_or(|(signed, _)| index != signed))
+            .map(|(index, chunk)| (*index, *chunk.chunk_type())),
+    );
+    covered.sort_by_key(|(index, _)| *index);
+
+    Verification {
+        index,
+        trusted: trusted.contains(&signature.signer),
+        valid: signature.is_valid(),
+        payload: payload_integrity,
+        image,
+        covered,
+        signature,
+    }
+}
+
+const PUBLIC_KEY_PREFIX: &str = "ed25519:";
+const SECRET_KEY_PREFIX: &str = "ED25519-SECRET-KEY:";
+
+pub fn generate_signing_key() -> SigningKey {
+    let mut seed = Zeroizing::new([0; 32]);
+    OsRng.fill_bytes(seed.as_mut());
+    SigningKey::from_bytes(&seed)
+}
+
+/// Formats a public key as `ed25519:` followed by 64 hex digits.
+pub fn verifying_key_to_string(key: &VerifyingKey) -> String {
+    format!("{}{}", PUBLIC_KEY_PREFIX, hex::encode(key.as_bytes()))
+}
+
+pub fn parse_verifying_key(key: &str) -> Result<VerifyingKey> {
+    let bytes = key
+        .trim()
+        .strip_prefix(PUBLIC_KEY_PREFIX)
+        .and_then(|key| hex::decode(key).ok())
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or(PngError::InvalidVerifyingKey)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| PngError::InvalidVerifyingKey)
+}
+
+/// Parses a list of trusted public keys: one per line, blank lines and `#`
+/// comments are ignored.
+pub fn parse_verifying_keys(contents: &str) -> Result<Vec<VerifyingKey>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_verifying_key)
+        .collect()
+}
+
+/// Contents of a signing key file, with the public key as a comment.
+pub fn signing_key_file(key: &SigningKey) -> Zeroizing<String> {
+    let secret = Zeroizing::new(hex::encode_upper(key.as_bytes()));
+    Zeroizing::new(format!(
+        "# public key: {}\n{}{}\n",
+        verifying_key_to_string(&key.verifying_key()),
+        SECRET_KEY_PREFIX,
+        secret.as_str()
+    ))
+}
+
+pub fn parse_signing_key_file(contents: &str) -> Result<SigningKey> {
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or(PngError::InvalidSigningKey)?;
+    let seed = line
+        .strip_prefix(SECRET_KEY_PREFIX)
+        .and_then(|seed| hex::decode(seed).ok())
+        .map(Zeroizing::new)
+        .ok_or(PngError::InvalidSigningKey)?;
+    let seed: Zeroizing<[u8; 32]> = Zeroizing::new(
+        seed.as_slice()
+            .try_into()
+            .map_err(|_| PngError::InvalidSigningKey)?,
+    );
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// The pieces among `payloads` of the payload `signed` was split from, or
+/// none if it is not a piece.
+fn pieces_of<'a>(payloads: &[(usize, &'a Chunk)], signed: &Chunk) -> Vec<(usize, &'a Chunk)> {
+    let Ok(id) = Piece::try_from(signed).map(|piece| piece.id) else {
+        return Vec::new();
+    };
+    payloads
+        .iter()
+        .filter(|(_, chunk)| Piece::try_from(*chunk).is_ok_and(|piece| piece.id == id))
+        .copied()
+        .collect()
+}
+
+fn is_image_chunk(chunk: &Chunk) -> bool {
+    matches!(
+        chunk.chunk_type().to_string().as_str(),
+        "IHDR" | "PLTE" | "IDAT"
+    )
+}
+
+fn hash_chunk(hasher: &mut Sha256, chunk: &Chunk) {
+    hasher.update(chunk.length().to_be_bytes());
+    hasher.update(chunk.chunk_type().bytes());
+    hasher.update(chunk.data());
+}
+
+fn chunk_digest(chunk: &Chunk) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hash_chunk(&mut hasher, chunk);
+    hasher.finalize().into()
+}
+
+fn image_digest(png: &Png) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for chunk in png.chunks().iter().filter(|chunk| is_image_chunk(chunk)) {
+        hash_chunk(&mut hasher, chunk);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::ChunkPosition;
+
+    fn signed_png(key: &SigningKey) -> Png {
+        let mut png = Png::try_from(std::fs::read("assets/res.png").unwrap().as_slice()).unwrap();
+        let payload = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"secret".to_vec());
+        let signature = Signature::new(&png, &payload, key);
+        png.insert_chunk(payload, ChunkPosition::AfterIdat).unwrap();
+        png.insert_chunk(signature.to_chunk(), ChunkPosition::BeforeIend)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_signature_round_trip() {
+        let key = generate_signing_key();
+        let png = signed_png(&key);
+        let chunk = png.chunk_by_type(Signature::CHUNK_TYPE).unwrap();
+        let signature = Signature::try_from(chunk).unwrap();
+
+        assert!(signature.is_valid());
+        assert_eq!(
+            Signature::try_from(&signature.to_chunk()).unwrap(),
+            signature
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = generate_signing_key();
+        let png = signed_png(&key);
+
+        let verifications = verify(&png, &[key.verifying_key()]).unwrap();
+        assert_eq!(verifications.len(), 1);
+        let verification = verifications[0].as_ref().unwrap();
+        assert!(verification.is_ok());
+        let covered: Vec<_> = verification
+            .covered
+            .iter()
+            .map(|(_, chunk_type)| chunk_type.to_string())
+            .collect();
+        assert_eq!(covered, ["IHDR", "IDAT", "RuSt"]);
+
+        let untrusted = verify(&png, &[generate_signing_key().verifying_key()]).unwrap();
+        let untrusted = untrusted[0].as_ref().unwrap();
+        assert!(!untrusted.trusted);
+        assert!(!untrusted.is_ok());
+    }
+
+    #[test]
+    fn test_verify_altered() {
+        let key = generate_signing_key();
+        let png = signed_png(&key);
+
+        let altered = |chunk_type: &str, data: &[u8]| {
+            let chunks = png
+                .chunks()
+                .iter()
+                .map(|chunk| {
+                    let replaced = chunk.chunk_type().to_string() == chunk_type;
+                    let data = if replaced { data } else { chunk.data() };
+                    Chunk::new(*chunk.chunk_type(), data.to_vec())
+                })
+                .collect();
+            verify(&Png::from_chunks(chunks), &[key.verifying_key()])
+                .unwrap()
+                .remove(0)
+                .unwrap()
+        };
+
+        let payload = altered("RuSt", b"swapped");
+        assert_eq!(payload.payload, Integrity::Altered);
+        assert_eq!(payload.image, Integrity::Intact);
+
+        let image = altered("IDAT", b"other pixels");
+        assert_eq!(image.payload, Integrity::Intact);
+        assert_eq!(image.image, Integrity::Altered);
+
+        let mut forged = png
+            .chunk_by_type(Signature::CHUNK_TYPE)
+            .unwrap()
+            .data()
+            .to_vec();
+        forged[40] ^= 1;
+        let forged = altered(Signature::CHUNK_TYPE, &forged);
+        assert!(!forged.valid);
+    }
+
+    #[test]
+    fn test_verify_malformed_signature() {
+        let key = generate_signing_key();
+        let mut png = signed_png(&key);
+        let malformed = Chunk::new(
+            ChunkType::from_str(Signature::CHUNK_TYPE).unwrap(),
+            b"not a signature".to_vec(),
+        );
+        png.insert_chunk(malformed, ChunkPosition::AfterIhdr)
+            .unwrap();
+
+        let verifications = verify(&png, &[key.verifying_key()]).unwrap();
+        assert_eq!(verifications.len(), 2);
+        assert!(matches!(
+            verifications[0],
+            Err(PngError::Chunk { index: 1, .. })
+        ));
+        assert!(verifications[1].as_ref().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let png = Png::from_chunks(Vec::new());
+        assert!(matches!(verify(&png, &[]), Err(PngError::ChunkNotFound)));
+    }
+
+    #[test]
+    fn test_key_files() {
+        let key = generate_signing_key();
+        let contents = signing_key_file(&key);
+        let public_key = verifying_key_to_string(&key.verifying_key());
+
+        assert!(contents.contains(&public_key));
+        assert_eq!(parse_signing_key_file(&contents).unwrap(), key);
+        assert_eq!(
+            parse_verifying_keys(&format!("# team\n{}\n", public_key)).unwrap(),
+            [key.verifying_key()]
+        );
+        assert!(parse_verifying_key("ed25519:1234").is_err());
+        assert!(parse_signing_key_file(&public_key).is_err());
+    }
+}
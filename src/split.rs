@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::validate::Validator;
+use crate::{PngError, Result};
+
+/// One chunk of a payload split by [`split`], with the header needed to put
+/// the payload back together.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Piece {
+    /// Tells apart the pieces of different payloads sharing a chunk type,
+    /// random so that the same payload encoded twice gets two ids.
+    pub id: u32,
+    /// Position of the piece, from 0.
+    pub sequence: u32,
+    /// Number of pieces of the payload.
+    pub total: u32,
+    /// SHA-256 of the whole payload.
+    pub digest: [u8; 32],
+    pub data: Vec<u8>,
+}
+
+impl Piece {
+    pub const MAGIC: [u8; 4] = *b"PMSP";
+    const VERSION: u8 = 1;
+    /// magic, version, id, sequence, total and digest
+    pub const HEADER_LENGTH: usize = 4 + 1 + 4 + 4 + 4 + 32;
+
+    /// Whether the chunk data starts with a piece header.
+    pub fn is_piece(data: &[u8]) -> bool {
+        data.len() >= Piece::HEADER_LENGTH
+            && data[0..4] == Piece::MAGIC
+            && data[4] == Piece::VERSION
+    }
+
+    pub fn to_chunk(&self, chunk_type: ChunkType) -> Chunk {
+        let mut data = Vec::with_capacity(Piece::HEADER_LENGTH + self.data.len());
+        data.extend_from_slice(&Piece::MAGIC);
+        data.push(Piece::VERSION);
+        data.extend_from_slice(&self.id.to_be_bytes());
+        data.extend_from_slice(&self.sequence.to_be_bytes());
+        data.extend_from_slice(&self.total.to_be_bytes());
+        data.extend_from_slice(&self.digest);
+        data.extend_from_slice(&self.data);
+        Chunk::new(chunk_type, data)
+    }
+}
+
+impl TryFrom<&Chunk> for Piece {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if !Piece::is_piece(data) {
+            return Err(PngError::InvalidChunkData {
+                chunk_type: "split payload",
+                reason: "missing piece header",
+            });
+        }
+
+        let u32_at = |offset: usize| {
+            u32::from_be_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+        let piece = Piece {
+            id: u32_at(5),
+            sequence: u32_at(9),
+            total: u32_at(13),
+            digest: data[17..Piece::HEADER_LENGTH].try_into().expect("32 bytes"),
+            data: data[Piece::HEADER_LENGTH..].to_vec(),
+        };
+        if piece.total == 0 || piece.sequence >= piece.total {
+            return Err(PngError::InvalidChunkData {
+                chunk_type: "split payload",
+                reason: "sequence number out of range",
+            });
+        }
+        Ok(piece)
+    }
+}
+
+/// Splits `payload` into chunks of `chunk_type` of at most `max_chunk_size`
+/// bytes of data each, header included.
+pub fn split(chunk_type: ChunkType, payload: &[u8], max_chunk_size: usize) -> Result<Vec<Chunk>> {
+    if max_chunk_size <= Piece::HEADER_LENGTH
+        || max_chunk_size > Validator::MAX_CHUNK_LENGTH as usize
+    {
+        return Err(PngError::InvalidChunkSize);
+    }
+
+    let digest: [u8; 32] = Sha256::digest(payload).into();
+    let id = rand::random();
+    let pieces: Vec<&[u8]> = if payload.is_empty() {
+        vec![&[]]
+    } else {
+        payload
+            .chunks(max_chunk_size - Piece::HEADER_LENGTH)
+            .collect()
+    };
+    let total = u32::try_from(pieces.len()).map_err(|_| PngError::InvalidChunkSize)?;
+
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, data)| {
+            Piece {
+                id,
+                sequence: sequence as u32,
+                total,
+                digest,
+                data: data.to_vec(),
+            }
+            .to_chunk(chunk_type)
+        })
+        .collect())
+}
+
+/// Puts back together the payload split in `chunks`, in any order. Only the
+/// pieces with the id of the first one are used, the others belong to another
+/// payload. Fails on missing or duplicated pieces and checks the digest.
+pub fn reassemble<'a, I>(chunks: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    // keyed by sequence: `total` comes from the file and can't size anything
+    let mut pieces: BTreeMap<u32, Piece> = BTreeMap::new();
+    let mut first: Option<(u32, u32, [u8; 32])> = None;
+
+    for chunk in chunks {
+        let piece = Piece::try_from(chunk)?;
+        let (id, total, digest) = *first.get_or_insert((piece.id, piece.total, piece.digest));
+        if piece.id != id {
+            continue;
+        }
+        if piece.total != total || piece.digest != digest {
+            return Err(PngError::InvalidChunkData {
+                chunk_type: "split payload",
+                reason: "pieces disagree on the payload",
+            });
+        }
+
+        let sequence = piece.sequence;
+        if pieces.insert(sequence, piece).is_some() {
+            return Err(PngError::DuplicatePiece { sequence });
+        }
+    }
+
+    let (_, total, digest) = first.ok_or(PngError::ChunkNotFound)?;
+    if pieces.len() != total as usize {
+        // every sequence is below total, so the first gap is a missing piece
+        let sequence = (0..)
+            .zip(pieces.keys())
+            .find(|(expected, sequence)| expected != *sequence)
+            .map_or(pieces.len() as u32, |(expected, _)| expected);
+        return Err(PngError::MissingPiece { sequence, total });
+    }
+    let payload: Vec<u8> = pieces.into_values().flat_map(|piece| piece.data).collect();
+
+    if <[u8; 32]>::from(Sha256::digest(&payload)) != digest {
+        return Err(PngError::InvalidChunkData {
+            chunk_type: "split payload",
+            reason: "digest does not match the reassembled payload",
+        });
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn pieces(payload: &[u8], max_chunk_size: usize) -> Vec<Chunk> {
+        split(
+            ChunkType::from_str("RuSt").unwrap(),
+            payload,
+            max_chunk_size,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_split_round_trip() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let chunks = pieces(&payload, 100);
+
+        assert_eq!(
+            chunks.len(),
+            1000_usize.div_ceil(100 - Piece::HEADER_LENGTH)
+        );
+        assert!(chunks.iter().all(|chunk| chunk.length() <= 100));
+        assert_eq!(reassemble(&chunks).unwrap(), payload);
+        assert_eq!(reassemble(chunks.iter().rev()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let chunks = pieces(b"", 100);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(reassemble(&chunks).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_missing_and_duplicated_pieces() {
+        let mut chunks = pieces(&[7; 500], 100);
+        let removed = chunks.remove(2);
+        assert!(matches!(
+            reassemble(&chunks),
+            Err(PngError::MissingPiece {
+                sequence: 2,
+                total: 10
+            })
+        ));
+
+        chunks.push(Chunk::new(*removed.chunk_type(), removed.data().to_vec()));
+        chunks.push(removed);
+        assert!(matches!(
+            reassemble(&chunks),
+            Err(PngError::DuplicatePiece { sequence: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_huge_total() {
+        let piece = Piece {
+            id: 1,
+            sequence: 0,
+            total: u32::MAX,
+            digest: [0; 32],
+            data: vec![7; 50],
+        };
+        let chunks = [piece.to_chunk(ChunkType::from_str("RuSt").unwrap())];
+        assert!(matches!(
+            reassemble(&chunks),
+            Err(PngError::MissingPiece {
+                sequence: 1,
+                total: u32::MAX
+            })
+        ));
+    }
+
+    #[test]
+    fn test_other_payload_ignored() {
+        let mut chunks = pieces(b"first payload", 60);
+        chunks.extend(pieces(b"second payload", 60));
+        assert_eq!(reassemble(&chunks).unwrap(), b"first payload");
+    }
+
+    #[test]
+    fn test_same_payload_twice() {
+        let first = pieces(&[7; 500], 100);
+        let second = pieces(&[7; 500], 100);
+        let id = |chunk: &Chunk| Piece::try_from(chunk).unwrap().id;
+        assert_ne!(id(&first[0]), id(&second[0]));
+
+        let chunks: Vec<Chunk> = first.into_iter().chain(second).collect();
+        assert_eq!(reassemble(&chunks).unwrap(), [7; 500]);
+        assert_eq!(reassemble(chunks.iter().rev()).unwrap(), [7; 500]);
+    }
+
+    #[test]
+    fn test_altered_piece() {
+        let mut chunks = pieces(&[7; 500], 100);
+        let mut data = chunks[3].data().to_vec();
+        data[Piece::HEADER_LENGTH] ^= 1;
+        chunks[3] = Chunk::new(*chunks[3].chunk_type(), data);
+        assert!(reassemble(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_invalid_chunk_size() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        assert!(split(chunk_type, b"payload", Piece::HEADER_LENGTH).is_err());
+        assert!(split(chunk_type, b"payload", 1 << 31).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use std::io::{self, Read, Write};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{ChunkPosition, Png};
+use crate::{PngError, Result};
+
+/// Reads the chunks of a png stream one at a time, checking the signature
+/// first and the crc of every chunk.
+///
+/// Only the chunk being read is kept in memory, so it works on large files
+/// and pipes. Like [`crate::ChunkIterator`], it stops after the first error.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: u64,
+    index: usize,
+    chunk_type: Option<ChunkType>,
+    tainted: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks the png signature.
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut header = [0; 8];
+        match read_full(&mut reader, &mut header)? {
+            8 if header == Png::STANDARD_HEADER => Ok(ChunkReader {
+                reader,
+                offset: Png::STANDARD_HEADER.len() as u64,
+                index: 0,
+                chunk_type: None,
+                tainted: false,
+            }),
+            _ => Err(PngError::InvalidPngFile),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        self.chunk_type = None;
+
+        let mut length = [0; 4];
+        match read_full(&mut self.reader, &mut length)? {
+            0 => return Ok(None), // clean end of stream
+            4 => {}
+            _ => return Err(PngError::ShortChunk),
+        }
+        let length = u32::from_be_bytes(length) as u64;
+
+        let mut chunk_type = [0; 4];
+        if read_full(&mut self.reader, &mut chunk_type)? != 4 {
+            return Err(PngError::ShortChunk);
+        }
+        self.chunk_type = Some(ChunkType::try_from(chunk_type)?);
+
+        // read through `take` so a corrupted length can't make us allocate gigabytes upfront
+        let mut data = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut data)?;
+        if data.len() as u64 != length {
+            return Err(PngError::ShortChunk);
+        }
+
+        let mut crc = [0; 4];
+        if read_full(&mut self.reader, &mut crc)? != 4 {
+            return Err(PngError::ShortChunk);
+        }
+
+        let chunk = Chunk::new(ChunkType::try_from(chunk_type)?, data);
+        let crc = u32::from_be_bytes(crc);
+        if chunk.crc() != crc {
+            return Err(PngError::InvalidCrc {
+                expected: chunk.crc(),
+                found: crc,
+            });
+        }
+
+        self.offset += length + Chunk::CHUNK_METADATA_LENGTH as u64;
+        self.index += 1;
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.tainted {
+            return None;
+        }
+
+        match self.read_chunk().transpose() {
+            Some(Err(e)) => {
+                self.tainted = true;
+                Some(Err(e.in_chunk(self.index, self.offset, self.chunk_type)))
+            }
+            chunk => chunk,
+        }
+    }
+}
+
+/// Writes a png stream: the signature first, then chunks one at a time.
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes the png signature.
+    pub fn new(mut writer: W) -> Result<ChunkWriter<W>> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(ChunkWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Copies every chunk of `reader` to `writer`, inserting `chunk` at `position`
+/// on the way. Same placement rules as [`Png::insert_chunk`].
+pub fn insert_chunk<R: Read, W: Write>(
+    reader: &mut ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunk: Chunk,
+    position: ChunkPosition,
+) -> Result<()> {
+    insert_chunks(reader, writer, vec![chunk], position)
+}
+
+/// Like [`insert_chunk`], with several chunks kept together in order.
+pub fn insert_chunks<R: Read, W: Write>(
+    reader: &mut ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunks: Vec<Chunk>,
+    position: ChunkPosition,
+) -> Result<()> {
+    let mut pending = Some(chunks);
+    let mut previous: Option<String> = None;
+    let mut count = 0;
+
+    for current in reader {
+        let current = current?;
+        let chunk_type = current.chunk_type().to_string();
+
+        let insert_here = match position {
+            ChunkPosition::BeforeIend => chunk_type == "IEND",
+            ChunkPosition::AfterIhdr => previous.as_deref() == Some("IHDR"),
+            ChunkPosition::BeforeIdat => chunk_type == "IDAT",
+            ChunkPosition::AfterIdat => previous.as_deref() == Some("IDAT") && chunk_type != "IDAT",
+            ChunkPosition::Index(index) => index == count,
+        };
+        if insert_here {
+            for chunk in pending.take().unwrap_or_default() {
+                writer.write_chunk(&chunk)?;
+            }
+        }
+
+        writer.write_chunk(&current)?;
+        previous = Some(chunk_type);
+        count += 1;
+    }
+
+    if let Some(chunks) = pending {
+        // the anchor may be the last chunk of the stream
+        let insert_at_end = match position {
+            ChunkPosition::BeforeIend => true,
+            ChunkPosition::AfterIhdr => previous.as_deref() == Some("IHDR"),
+            ChunkPosition::BeforeIdat => false,
+            ChunkPosition::AfterIdat => previous.as_deref() == Some("IDAT"),
+            ChunkPosition::Index(index) => index == count,
+        };
+        match (insert_at_end, position) {
+            (true, _) => {
+                for chunk in chunks {
+                    writer.write_chunk(&chunk)?;
+                }
+            }
+            (false, ChunkPosition::Index(_)) => return Err(PngError::InvalidChunkPosition),
+            (false, _) => return Err(PngError::ChunkNotFound),
+        }
+    }
+
+    Ok(())
+}
+
+/// Like `read_exact`, but returns how many bytes were read before the end of
+/// the stream instead of failing.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("IDAT", "first"),
+            chunk_from_strings("IDAT", "second"),
+            chunk_from_strings("IEND", ""),
+        ])
+    }
+
+    fn chunk_types(bytes: &[u8]) -> Vec<String> {
+        ChunkReader::new(bytes)
+            .unwrap()
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_reader_matches_png() {
+        let bytes = testing_png().as_bytes();
+        let chunks: Vec<Chunk> = ChunkReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[2].data_as_string().unwrap(), "second");
+    }
+
+    #[test]
+    fn test_reader_invalid_header() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[0] = 13;
+        assert!(ChunkReader::new(bytes.as_slice()).is_err());
+        assert!(ChunkReader::new(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn test_reader_invalid_crc() {
+        let mut bytes = testing_png().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let chunks: Vec<Result<Chunk>> = ChunkReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(chunks.len(), 4);
+        match &chunks[3] {
+            Err(PngError::Chunk {
+                index,
+                offset,
+                chunk_type,
+                ..
+            }) => {
+                assert_eq!(*index, 3);
+                assert_eq!(*offset as usize, bytes.len() - Chunk::CHUNK_METADATA_LENGTH);
+                assert_eq!(chunk_type.unwrap().to_string(), "IEND");
+            }
+            _ => panic!("expected a positioned chunk error"),
+        }
+    }
+
+    #[test]
+    fn test_reader_truncated() {
+        let bytes = testing_png().as_bytes();
+        let chunks: Vec<Result<Chunk>> = ChunkReader::new(&bytes[..bytes.len() - 2])
+            .unwrap()
+            .collect();
+        assert!(chunks.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_reader_huge_length() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&[0xff, 0xff, 0xff, 0xf0]);
+        bytes.extend_from_slice(b"RuSt");
+        bytes.extend_from_slice(b"data");
+
+        let chunks: Vec<Result<Chunk>> = ChunkReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_err());
+    }
+
+    #[test]
+    fn test_writer_matches_png() {
+        let png = testing_png();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        for chunk in png.chunks() {
+            writer.write_chunk(chunk).unwrap();
+        }
+
+        assert_eq!(writer.into_inner().unwrap(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let bytes = testing_png().as_bytes();
+        let cases = [
+            (
+                ChunkPosition::BeforeIend,
+                ["IHDR", "IDAT", "IDAT", "RuSt", "IEND"],
+            ),
+            (
+                ChunkPosition::AfterIhdr,
+                ["IHDR", "RuSt", "IDAT", "IDAT", "IEND"],
+            ),
+            (
+                ChunkPosition::BeforeIdat,
+                ["IHDR", "RuSt", "IDAT", "IDAT", "IEND"],
+            ),
+            (
+                ChunkPosition::AfterIdat,
+                ["IHDR", "IDAT", "IDAT", "RuSt", "IEND"],
+            ),
+            (
+                ChunkPosition::Index(4),
+                ["IHDR", "IDAT", "IDAT", "IEND", "RuSt"],
+            ),
+        ];
+
+        for (position, expected) in cases {
+            let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+            let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+            insert_chunk(
+                &mut reader,
+                &mut writer,
+                chunk_from_strings("RuSt", "Message"),
+                position,
+            )
+            .unwrap();
+
+            assert_eq!(chunk_types(&writer.into_inner().unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn test_insert_chunk_missing_anchor() {
+        let bytes = testing_png().as_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        let result = insert_chunk(
+            &mut reader,
+            &mut writer,
+            chunk_from_strings("RuSt", "Message"),
+            ChunkPosition::Index(5),
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::chunk::Chunk;
+use crate::text::TextEntry;
+
+/// Which chunks [`StripPolicy`] removes, before the ones it keeps.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StripScope {
+    /// Every chunk the image does not need to be displayed.
+    #[default]
+    AllAncillary,
+    /// Only private chunks, e.g. hidden messages or editor data.
+    PrivateOnly,
+}
+
+/// Chooses the chunks removed by [`Png::strip`](crate::Png::strip).
+///
+/// `IHDR`, `PLTE`, `IDAT` and `IEND` are always kept, and so is `tRNS`:
+/// without it transparent pixels turn opaque. Unknown chunks that are
+/// not safe to copy are always removed, even with [`StripScope::PrivateOnly`]:
+/// they may depend on image data this crate can't check.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct StripPolicy {
+    pub scope: StripScope,
+    /// Keeps the chunks changing how colors are displayed.
+    pub keep_color: bool,
+    /// Keeps the text chunks with these keywords.
+    pub keep_text: Vec<String>,
+}
+
+impl StripPolicy {
+    /// Color space chunks kept by `keep_color`.
+    pub const COLOR_CHUNKS: [&'static str; 4] = ["gAMA", "cHRM", "sRGB", "iCCP"];
+
+    /// Ancillary chunks needed to display the image, always kept.
+    pub const DISPLAY_CHUNKS: [&'static str; 1] = ["tRNS"];
+
+    /// Whether the policy removes this chunk.
+    pub fn removes(&self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical() && chunk_type.is_known() {
+            return false;
+        }
+        if Self::DISPLAY_CHUNKS.contains(&chunk_type.to_string().as_str()) {
+            return false;
+        }
+        if self.keep_color && Self::COLOR_CHUNKS.contains(&chunk_type.to_string().as_str()) {
+            return false;
+        }
+        if TextEntry::is_text_chunk(chunk) {
+            let kept = TextEntry::try_from(chunk)
+                .is_ok_and(|entry| self.keep_text.contains(&entry.keyword));
+            if kept {
+                return false;
+            }
+        }
+
+        match self.scope {
+            StripScope::AllAncillary => true,
+            StripScope::PrivateOnly => {
+                !chunk_type.is_public() || (!chunk_type.is_known() && !chunk_type.is_safe_to_copy())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::text::TextKind;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), Vec::new())
+    }
+
+    fn text(keyword: &str) -> Chunk {
+        TextEntry::new(keyword, "text", TextKind::Text)
+            .unwrap()
+            .to_chunk()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_all_ancillary() {
+        let policy = StripPolicy::default();
+        for chunk_type in ["IHDR", "PLTE", "IDAT", "IEND"] {
+            assert!(!policy.removes(&chunk(chunk_type)), "{}", chunk_type);
+        }
+        for chunk_type in ["gAMA", "tIME", "eXIf", "RuSt", "prVt"] {
+            assert!(policy.removes(&chunk(chunk_type)), "{}", chunk_type);
+        }
+        assert!(policy.removes(&text("Author")));
+    }
+
+    #[test]
+    fn test_transparency_kept() {
+        for scope in [StripScope::AllAncillary, StripScope::PrivateOnly] {
+            let policy = StripPolicy {
+                scope,
+                ..Default::default()
+            };
+            assert!(!policy.removes(&chunk("tRNS")));
+        }
+    }
+
+    #[test]
+    fn test_keep_color_and_text() {
+        let policy = StripPolicy {
+            keep_color: true,
+            keep_text: vec!["Copyright".to_string()],
+            ..Default::default()
+        };
+        assert!(!policy.removes(&chunk("sRGB")));
+        assert!(!policy.removes(&chunk("iCCP")));
+        assert!(policy.removes(&chunk("pHYs")));
+        assert!(!policy.removes(&text("Copyright")));
+        assert!(policy.removes(&text("Author")));
+    }
+
+    #[test]
+    fn test_private_only() {
+        let policy = StripPolicy {
+            scope: StripScope::PrivateOnly,
+            ..Default::default()
+        };
+        assert!(policy.removes(&chunk("RuSt")));
+        assert!(policy.removes(&chunk("prVt")));
+        assert!(!policy.removes(&chunk("tIME")));
+        assert!(!policy.removes(&text("Author")));
+        // unknown public chunks are kept only if safe to copy
+        assert!(!policy.removes(&chunk("sMPl")));
+        assert!(policy.removes(&chunk("sMPL")));
+    }
+}
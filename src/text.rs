@@ -0,0 +1,340 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{PngError, Result};
+
+/// Which of the three textual chunks stores a [`TextEntry`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TextKind {
+    /// `tEXt`: uncompressed Latin-1 text.
+    Text,
+    /// `zTXt`: zlib compressed Latin-1 text.
+    CompressedText,
+    /// `iTXt`: UTF-8 text, optionally compressed, with the language of the
+    /// text and the keyword translated in that language.
+    InternationalText {
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+    },
+}
+
+impl TextKind {
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            TextKind::Text => TextEntry::TEXT,
+            TextKind::CompressedText => TextEntry::COMPRESSED_TEXT,
+            TextKind::InternationalText { .. } => TextEntry::INTERNATIONAL_TEXT,
+        }
+    }
+}
+
+/// A keyword and its text, decoded from a `tEXt`, `zTXt` or `iTXt` chunk.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+    pub kind: TextKind,
+}
+
+impl TextEntry {
+    pub const TEXT: &'static str = "tEXt";
+    pub const COMPRESSED_TEXT: &'static str = "zTXt";
+    pub const INTERNATIONAL_TEXT: &'static str = "iTXt";
+
+    /// Texts are not decompressed past this size, to stay safe from zip bombs.
+    pub const MAX_DECOMPRESSED_LENGTH: u64 = 16 * 1024 * 1024;
+
+    pub fn new(keyword: &str, text: &str, kind: TextKind) -> Result<TextEntry> {
+        let entry = TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            kind,
+        };
+        entry.validate()?;
+        Ok(entry)
+    }
+
+    /// Whether the chunk type is one of the textual chunks.
+    pub fn is_text_chunk(chunk: &Chunk) -> bool {
+        matches!(
+            chunk.chunk_type().to_string().as_str(),
+            TextEntry::TEXT | TextEntry::COMPRESSED_TEXT | TextEntry::INTERNATIONAL_TEXT
+        )
+    }
+
+    /// Checks the keyword and, for `tEXt` and `zTXt`, that the text is Latin-1.
+    pub fn validate(&self) -> Result<()> {
+        validate_keyword(&self.keyword)?;
+        match &self.kind {
+            TextKind::Text | TextKind::CompressedText => {
+                encode_latin1(&self.text)?;
+            }
+            TextKind::InternationalText {
+                language_tag,
+                translated_keyword,
+                ..
+            } => {
+                if !language_tag
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+                    || translated_keyword.contains('\0')
+                {
+                    return Err(PngError::InvalidText);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        self.validate()?;
+
+        let mut data = encode_latin1(&self.keyword)?;
+        data.push(0);
+        match &self.kind {
+            TextKind::Text => data.extend(encode_latin1(&self.text)?),
+            TextKind::CompressedText => {
+                data.push(0); // compression method: zlib
+                data.extend(compress(&encode_latin1(&self.text)?)?);
+            }
+            TextKind::InternationalText {
+                compressed,
+                language_tag,
+                translated_keyword,
+            } => {
+                data.extend_from_slice(&[*compressed as u8, 0]);
+                data.extend_from_slice(language_tag.as_bytes());
+                data.push(0);
+                data.extend_from_slice(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend(compress(self.text.as_bytes())?);
+                } else {
+                    data.extend_from_slice(self.text.as_bytes());
+                }
+            }
+        }
+
+        let chunk_type = ChunkType::from_str(self.kind.chunk_type())?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextEntry {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let chunk_type = match chunk.chunk_type().to_string().as_str() {
+            TextEntry::TEXT => TextEntry::TEXT,
+            TextEntry::COMPRESSED_TEXT => TextEntry::COMPRESSED_TEXT,
+            TextEntry::INTERNATIONAL_TEXT => TextEntry::INTERNATIONAL_TEXT,
+            _ => return Err(PngError::InvalidChunkType),
+        };
+        let invalid = |reason| PngError::InvalidChunkData { chunk_type, reason };
+
+        let (keyword, rest) = split_null(chunk.data()).ok_or(invalid("missing keyword"))?;
+        let keyword = decode_latin1(keyword);
+        validate_keyword(&keyword)?;
+
+        let (text, kind) = match chunk_type {
+            TextEntry::TEXT => (decode_latin1(rest), TextKind::Text),
+            TextEntry::COMPRESSED_TEXT => match rest {
+                [0, compressed @ ..] => (
+                    decode_latin1(&decompress(compressed, chunk_type)?),
+                    TextKind::CompressedText,
+                ),
+                _ => return Err(invalid("unknown compression method")),
+            },
+            _ => {
+                // compression flag then compression method (zlib)
+                let (compressed, rest) = match rest {
+                    [0, 0, rest @ ..] => (false, rest),
+                    [1, 0, rest @ ..] => (true, rest),
+                    _ => return Err(invalid("unknown compression method")),
+                };
+                let (language_tag, rest) =
+                    split_null(rest).ok_or(invalid("missing language tag"))?;
+                let (translated_keyword, text) =
+                    split_null(rest).ok_or(invalid("missing translated keyword"))?;
+
+                let text = if compressed {
+                    decompress(text, chunk_type)?
+                } else {
+                    text.to_vec()
+                };
+                let utf8 = |bytes: Vec<u8>| {
+                    String::from_utf8(bytes).map_err(|_| invalid("text is not UTF-8"))
+                };
+
+                (
+                    utf8(text)?,
+                    TextKind::InternationalText {
+                        compressed,
+                        language_tag: utf8(language_tag.to_vec())?,
+                        translated_keyword: utf8(translated_keyword.to_vec())?,
+                    },
+                )
+            }
+        };
+
+        Ok(TextEntry {
+            keyword,
+            text,
+            kind,
+        })
+    }
+}
+
+/// Keywords are 1 to 79 printable Latin-1 characters, without leading,
+/// trailing or consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    let bytes = encode_latin1(keyword).map_err(|_| PngError::InvalidKeyword)?;
+    let printable = bytes
+        .iter()
+        .all(|byte| matches!(byte, 32..=126 | 161..=255));
+
+    if bytes.is_empty()
+        || bytes.len() > 79
+        || !printable
+        || keyword.starts_with(' ')
+        || keyword.ends_with(' ')
+        || keyword.contains("  ")
+    {
+        return Err(PngError::InvalidKeyword);
+    }
+    Ok(())
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| match c as u32 {
+            0 => Err(PngError::InvalidText),
+            code @ 1..=255 => Ok(code as u8),
+            _ => Err(PngError::InvalidText),
+        })
+        .collect()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+fn split_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|&byte| byte == 0)?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+fn compress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+fn decompress(bytes: &[u8], chunk_type: &'static str) -> Result<Vec<u8>> {
+    let mut text = Vec::new();
+    ZlibDecoder::new(bytes)
+        .take(TextEntry::MAX_DECOMPRESSED_LENGTH + 1)
+        .read_to_end(&mut text)
+        .map_err(|_| PngError::InvalidChunkData {
+            chunk_type,
+            reason: "invalid zlib stream",
+        })?;
+
+    if text.len() as u64 > TextEntry::MAX_DECOMPRESSED_LENGTH {
+        return Err(PngError::InvalidChunkData {
+            chunk_type,
+            reason: "decompressed text is too large",
+        });
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn international(compressed: bool) -> TextKind {
+        TextKind::InternationalText {
+            compressed,
+            language_tag: String::from("fr-FR"),
+            translated_keyword: String::from("Auteur"),
+        }
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let kinds = [
+            TextKind::Text,
+            TextKind::CompressedText,
+            international(false),
+            international(true),
+        ];
+
+        for kind in kinds {
+            let entry = TextEntry::new("Author", "Élodie Dupré", kind).unwrap();
+            let chunk = entry.to_chunk().unwrap();
+
+            assert_eq!(chunk.chunk_type().to_string(), entry.kind.chunk_type());
+            assert_eq!(TextEntry::try_from(&chunk).unwrap(), entry);
+        }
+    }
+
+    #[test]
+    fn test_text_chunk_layout() {
+        let chunk = TextEntry::new("Title", "Dice", TextKind::Text)
+            .unwrap()
+            .to_chunk()
+            .unwrap();
+        assert_eq!(chunk.data(), b"Title\0Dice");
+    }
+
+    #[test]
+    fn test_keyword_validation() {
+        assert!(validate_keyword("Comment").is_ok());
+        assert!(validate_keyword("Créé par").is_ok());
+        assert!(validate_keyword("").is_err());
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+        assert!(validate_keyword(" Comment").is_err());
+        assert!(validate_keyword("Two  spaces").is_err());
+        assert!(validate_keyword("Emoji 🦀").is_err());
+        assert!(validate_keyword("Tab\tkey").is_err());
+    }
+
+    #[test]
+    fn test_latin1_text_only() {
+        assert!(TextEntry::new("Comment", "🦀", TextKind::Text).is_err());
+        assert!(TextEntry::new("Comment", "🦀", international(false)).is_ok());
+    }
+
+    #[test]
+    fn test_translated_keyword_without_null() {
+        let kind = TextKind::InternationalText {
+            compressed: false,
+            language_tag: String::from("fr"),
+            translated_keyword: String::from("Aut\0eur"),
+        };
+        assert!(TextEntry::new("Author", "Élodie", kind).is_err());
+    }
+
+    #[test]
+    fn test_invalid_text_chunks() {
+        let chunk_type = ChunkType::from_str("zTXt").unwrap();
+        let garbage = Chunk::new(chunk_type, b"Comment\0\0not zlib".to_vec());
+        assert!(TextEntry::try_from(&garbage).is_err());
+
+        let chunk_type = ChunkType::from_str("tEXt").unwrap();
+        let no_keyword = Chunk::new(chunk_type, b"Comment".to_vec());
+        assert!(TextEntry::try_from(&no_keyword).is_err());
+
+        let chunk_type = ChunkType::from_str("iTXt").unwrap();
+        let truncated = Chunk::new(chunk_type, b"Comment\0\0\0fr".to_vec());
+        assert!(TextEntry::try_from(&truncated).is_err());
+    }
+}
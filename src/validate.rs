@@ -0,0 +1,405 @@
+use core::fmt::{self, Display, Formatter};
+use std::collections::HashSet;
+use std::io::Read;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::critical::{ColorType, Iend, Ihdr, Plte};
+use crate::stream::ChunkReader;
+use crate::Result;
+
+/// How bad a [`Finding`] is. Errors break the specification, warnings are
+/// tolerated by most decoders.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by the validator.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Offset in bytes of the chunk in the file, or of the end of the file
+    /// for problems that concern the whole file.
+    pub offset: u64,
+    pub chunk_index: Option<usize>,
+    pub chunk_type: Option<ChunkType>,
+    pub message: String,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.severity, self.offset)?;
+        match (self.chunk_index, self.chunk_type) {
+            (Some(index), Some(chunk_type)) => write!(f, " (chunk #{} {})", index, chunk_type)?,
+            (Some(index), None) => write!(f, " (chunk #{})", index)?,
+            _ => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Checks chunks one at a time against the ordering and content rules of
+/// the PNG specification.
+pub struct Validator {
+    findings: Vec<Finding>,
+    index: usize,
+    offset: u64,
+    seen: HashSet<String>,
+    ihdr: Option<Ihdr>,
+    idat_ended: bool,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator::new()
+    }
+}
+
+impl Validator {
+    /// Largest chunk length allowed by the specification.
+    pub const MAX_CHUNK_LENGTH: u32 = i32::MAX as u32;
+
+    const KNOWN_CRITICAL: [&'static str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+    const SINGLETONS: [&'static str; 14] = [
+        "IHDR", "PLTE", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS",
+        "pHYs", "tIME", "eXIf",
+    ];
+    const BEFORE_PLTE: [&'static str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+    const AFTER_PLTE: [&'static str; 3] = ["bKGD", "hIST", "tRNS"];
+    const BEFORE_IDAT: [&'static str; 2] = ["pHYs", "sPLT"];
+
+    pub fn new() -> Validator {
+        Validator {
+            findings: Vec::new(),
+            index: 0,
+            // chunks start after the signature
+            offset: 8,
+            seen: HashSet::new(),
+            ihdr: None,
+            idat_ended: false,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &Chunk) {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        let name = name.as_str();
+        let seen_plte = self.seen.contains("PLTE");
+        let seen_idat = self.seen.contains("IDAT");
+        let seen_iend = self.seen.contains("IEND");
+        let duplicate = Self::SINGLETONS.contains(&name) && self.seen.contains(name);
+
+        if self.index == 0 && name != "IHDR" {
+            self.error(chunk, "the first chunk must be IHDR");
+        }
+        if seen_iend {
+            self.error(chunk, "chunk after IEND");
+        }
+        if !chunk_type.is_reserved_bit_valid() {
+            self.error(chunk, "the reserved bit of the chunk type is set");
+        }
+        if chunk_type.is_critical() && !Self::KNOWN_CRITICAL.contains(&name) {
+            self.error(chunk, "unknown critical chunk");
+        }
+        if chunk.length() > Self::MAX_CHUNK_LENGTH {
+            self.error(chunk, "chunk length exceeds 2^31-1 bytes");
+        }
+        if duplicate {
+            self.error(chunk, "duplicate chunk, only one is allowed");
+        }
+        if seen_idat && name != "IDAT" {
+            self.idat_ended = true;
+        }
+
+        match name {
+            "IHDR" => match Ihdr::try_from(chunk) {
+                Ok(ihdr) => self.ihdr = self.ihdr.or(Some(ihdr)),
+                Err(e) => self.error(chunk, &e.to_string()),
+            },
+            "PLTE" => {
+                if let Err(e) = Plte::try_from(chunk) {
+                    self.error(chunk, &e.to_string());
+                }
+                if seen_idat {
+                    self.error(chunk, "PLTE must come before IDAT");
+                }
+                if let Some(ColorType::Grayscale | ColorType::GrayscaleAlpha) =
+                    self.ihdr.map(|ihdr| ihdr.color_type)
+                {
+                    self.error(chunk, "PLTE is not allowed in grayscale images");
+                }
+            }
+            "IDAT" if self.idat_ended => {
+                self.error(chunk, "IDAT chunks must be contiguous");
+            }
+            "IEND" => {
+                if let Err(e) = Iend::try_from(chunk) {
+                    self.error(chunk, &e.to_string());
+                }
+            }
+            _ if Self::BEFORE_PLTE.contains(&name) && (seen_plte || seen_idat) => {
+                self.error(chunk, "must come before PLTE and IDAT");
+            }
+            _ if Self::AFTER_PLTE.contains(&name) || Self::BEFORE_IDAT.contains(&name) => {
+                if seen_idat {
+                    self.error(chunk, "must come before IDAT");
+                }
+                let indexed = self.ihdr.map(|ihdr| ihdr.color_type) == Some(ColorType::Indexed);
+                if Self::AFTER_PLTE.contains(&name) && indexed && !seen_plte {
+                    self.error(chunk, "must come after PLTE");
+                }
+            }
+            _ => {}
+        }
+
+        self.seen.insert(name.to_string());
+        self.index += 1;
+        self.offset += chunk.length() as u64 + Chunk::CHUNK_METADATA_LENGTH as u64;
+    }
+
+    /// Reports data that could not be parsed as a chunk at the current offset.
+    pub fn push_unparsable(&mut self, message: &str) {
+        let (severity, message) = if self.seen.contains("IEND") {
+            (Severity::Warning, "trailing data after IEND".to_string())
+        } else {
+            (Severity::Error, message.to_string())
+        };
+        self.findings.push(Finding {
+            severity,
+            offset: self.offset,
+            chunk_index: Some(self.index),
+            chunk_type: None,
+            message,
+        });
+    }
+
+    pub fn finish(mut self) -> Vec<Finding> {
+        let seen = |name: &str| self.seen.contains(name);
+        let missing: Vec<&str> = ["IHDR", "IDAT", "IEND"]
+            .into_iter()
+            .filter(|name| !seen(name))
+            .collect();
+        let indexed_without_palette =
+            self.ihdr.map(|ihdr| ihdr.color_type) == Some(ColorType::Indexed) && !seen("PLTE");
+        let icc_and_srgb = seen("iCCP") && seen("sRGB");
+
+        for name in missing {
+            self.file_finding(Severity::Error, &format!("missing {} chunk", name));
+        }
+        if indexed_without_palette {
+            self.file_finding(Severity::Error, "indexed images need a PLTE chunk");
+        }
+        if icc_and_srgb {
+            self.file_finding(
+                Severity::Warning,
+                "iCCP and sRGB should not both be present",
+            );
+        }
+
+        self.findings.sort_by_key(|finding| finding.offset);
+        self.findings
+    }
+
+    fn error(&mut self, chunk: &Chunk, message: &str) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            offset: self.offset,
+            chunk_index: Some(self.index),
+            chunk_type: Some(*chunk.chunk_type()),
+            message: message.to_string(),
+        });
+    }
+
+    fn file_finding(&mut self, severity: Severity, message: &str) {
+        self.findings.push(Finding {
+            severity,
+            offset: self.offset,
+            chunk_index: None,
+            chunk_type: None,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Validates a whole png stream, including data that can't be parsed as
+/// chunks. Fails only if the signature is invalid or on I/O errors.
+pub fn validate_reader<R: Read>(reader: R) -> Result<Vec<Finding>> {
+    let mut validator = Validator::new();
+    for chunk in ChunkReader::new(reader)? {
+        match chunk {
+            Ok(chunk) => validator.push(&chunk),
+            Err(e) => {
+                // report the cause, the position is already known by the validator
+                let message = std::error::Error::source(&e)
+                    .map(|source| source.to_string())
+                    .unwrap_or_else(|| e.to_string());
+                validator.push_unparsable(&message);
+            }
+        }
+    }
+    Ok(validator.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::critical::Rgb;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr(color_type: ColorType) -> Chunk {
+        Ihdr {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            color_type,
+            compression: 0,
+            filter: 0,
+            interlace: 0,
+        }
+        .to_chunk()
+    }
+
+    fn messages(chunks: Vec<Chunk>) -> Vec<String> {
+        Png::from_chunks(chunks)
+            .validate()
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        let chunks = vec![
+            ihdr(ColorType::Rgb),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("IDAT", &[1]),
+            chunk("IDAT", &[2]),
+            chunk("tEXt", b"Title\0Dice"),
+            Iend.to_chunk(),
+        ];
+        assert!(messages(chunks).is_empty());
+    }
+
+    #[test]
+    fn test_ordering_rules() {
+        let chunks = vec![
+            chunk("IDAT", &[1]),
+            ihdr(ColorType::Rgb),
+            chunk("tEXt", b"Title\0Dice"),
+            chunk("IDAT", &[2]),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            Iend.to_chunk(),
+            chunk("RuSt", b"hidden"),
+        ];
+        let messages = messages(chunks);
+
+        assert!(messages.contains(&"the first chunk must be IHDR".to_string()));
+        assert!(messages.contains(&"IDAT chunks must be contiguous".to_string()));
+        assert!(messages.contains(&"must come before PLTE and IDAT".to_string()));
+        assert!(messages.contains(&"chunk after IEND".to_string()));
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let palette = Plte(vec![Rgb { r: 0, g: 0, b: 0 }]).to_chunk();
+
+        let messages_indexed = messages(vec![
+            ihdr(ColorType::Indexed),
+            chunk("tRNS", &[0]),
+            chunk("IDAT", &[1]),
+            Iend.to_chunk(),
+        ]);
+        assert!(messages_indexed.contains(&"must come after PLTE".to_string()));
+        assert!(messages_indexed.contains(&"indexed images need a PLTE chunk".to_string()));
+
+        let messages_grayscale = messages(vec![
+            ihdr(ColorType::Grayscale),
+            chunk("IDAT", &[1]),
+            palette,
+            Iend.to_chunk(),
+        ]);
+        assert!(messages_grayscale.contains(&"PLTE must come before IDAT".to_string()));
+        assert!(messages_grayscale.contains(&"PLTE is not allowed in grayscale images".to_string()));
+    }
+
+    #[test]
+    fn test_chunk_type_rules() {
+        let messages = messages(vec![
+            ihdr(ColorType::Rgb),
+            ihdr(ColorType::Rgb),
+            chunk("RUST", &[]),
+            chunk("ruat", &[]),
+            chunk("IDAT", &[1]),
+        ]);
+
+        assert!(messages.contains(&"duplicate chunk, only one is allowed".to_string()));
+        assert!(messages.contains(&"unknown critical chunk".to_string()));
+        assert!(messages.contains(&"the reserved bit of the chunk type is set".to_string()));
+        assert!(messages.contains(&"missing IEND chunk".to_string()));
+    }
+
+    #[test]
+    fn test_offsets() {
+        let findings = Png::from_chunks(vec![
+            ihdr(ColorType::Rgb),
+            chunk("IDAT", &[1]),
+            chunk("RUST", &[]),
+            Iend.to_chunk(),
+        ])
+        .validate();
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].offset, 8 + 25 + 13);
+        assert_eq!(findings[0].chunk_index, Some(2));
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = Png::from_chunks(vec![
+            ihdr(ColorType::Rgb),
+            chunk("IDAT", &[1]),
+            Iend.to_chunk(),
+        ])
+        .as_bytes();
+        let end = bytes.len() as u64;
+        bytes.extend_from_slice(b"garbage");
+
+        let findings = validate_reader(bytes.as_slice()).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].offset, end);
+        assert_eq!(findings[0].message, "trailing data after IEND");
+    }
+
+    #[test]
+    fn test_corrupted_chunk() {
+        let mut bytes = Png::from_chunks(vec![
+            ihdr(ColorType::Rgb),
+            chunk("IDAT", &[1]),
+            Iend.to_chunk(),
+        ])
+        .as_bytes();
+        bytes[8 + 25 + 9] ^= 1;
+
+        let findings = validate_reader(bytes.as_slice()).unwrap();
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[0].offset, 8 + 25);
+        assert!(findings[0].message.starts_with("Invalid crc"));
+    }
+}